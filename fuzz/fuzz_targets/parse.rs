@@ -0,0 +1,27 @@
+// Run with `cargo +nightly fuzz run parse` from the repository root.
+#![no_main]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use libfuzzer_sys::fuzz_target;
+use rusty_monkey::{dump, lexer, parser};
+
+// Any input must lex and parse without panicking or hanging, and whatever
+// parses must print back to source that parses the same way.
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let lex = lexer::new(&input);
+    let mut parser = parser::new(Rc::new(RefCell::new(lex)));
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        return;
+    }
+
+    let printed = program.to_string();
+    let lex = lexer::new(&printed);
+    let mut reparser = parser::new(Rc::new(RefCell::new(lex)));
+    let reparsed = reparser.parse_program();
+    assert!(reparser.errors().is_empty(), "{printed:?}: {:?}", reparser.errors());
+    assert_eq!(dump::to_sexpr(&reparsed), dump::to_sexpr(&program));
+});
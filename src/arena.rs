@@ -0,0 +1,656 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Index;
+
+use crate::ast::{self, TypeAnnotation};
+use crate::token::Span;
+
+// A flat representation of a program. Every node lives in the arena for its
+// kind and refers to its children by `NodeId`, so analysis passes can keep
+// per-node results in a `SideTable` instead of growing the tree. Nodes are
+// allocated children first: a node's id is always greater than the ids of
+// the nodes it contains, so walking an arena in order visits every node
+// after its children.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ast {
+    pub exprs: Arena<Expr>,
+    pub stmts: Arena<Stmt>,
+    pub blocks: Arena<Block>,
+    pub patterns: Arena<Pattern>,
+    pub idents: Arena<Ident>,
+    // The top-level statements of the program.
+    pub statements: Vec<StmtId>,
+    pub spans: Spans,
+}
+
+// Index of a node of type `T`. Ids of different node kinds cannot be mixed
+// up, and an id is only meaningful for the `Ast` that handed it out.
+pub struct NodeId<T> {
+    index: u32,
+    kind: PhantomData<fn() -> T>,
+}
+
+pub type ExprId = NodeId<Expr>;
+pub type StmtId = NodeId<Stmt>;
+pub type BlockId = NodeId<Block>;
+pub type PatternId = NodeId<Pattern>;
+pub type IdentId = NodeId<Ident>;
+
+impl<T> NodeId<T> {
+    fn new(index: usize) -> NodeId<T> {
+        NodeId {
+            index: u32::try_from(index).expect("too many nodes for a NodeId"),
+            kind: PhantomData,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// Implemented by hand because deriving would require the same traits of `T`.
+impl<T> Clone for NodeId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeId<T> {}
+
+impl<T> PartialEq for NodeId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for NodeId<T> {}
+
+impl<T> Hash for NodeId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for NodeId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arena<T> {
+    nodes: Vec<T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena { nodes: Vec::new() }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn alloc(&mut self, node: T) -> NodeId<T> {
+        self.nodes.push(node);
+        NodeId::new(self.nodes.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Every node with its id, in allocation order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId<T>, &T)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (NodeId::new(i), node))
+    }
+}
+
+impl<T> Index<NodeId<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId<T>) -> &T {
+        &self.nodes[id.index()]
+    }
+}
+
+// Extra data about some of the nodes of one kind, such as their types or the
+// scope a name resolves to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideTable<T, V> {
+    values: Vec<Option<V>>,
+    kind: PhantomData<fn() -> T>,
+}
+
+impl<T, V> Default for SideTable<T, V> {
+    fn default() -> Self {
+        SideTable {
+            values: Vec::new(),
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<T, V> SideTable<T, V> {
+    pub fn new() -> SideTable<T, V> {
+        SideTable::default()
+    }
+
+    // Store `value` for `id`, returning the value it replaces.
+    pub fn insert(&mut self, id: NodeId<T>, value: V) -> Option<V> {
+        if self.values.len() <= id.index() {
+            self.values.resize_with(id.index() + 1, || None);
+        }
+        self.values[id.index()].replace(value)
+    }
+
+    pub fn get(&self, id: NodeId<T>) -> Option<&V> {
+        self.values.get(id.index()).and_then(Option::as_ref)
+    }
+
+    pub fn contains(&self, id: NodeId<T>) -> bool {
+        self.get(id).is_some()
+    }
+}
+
+// The span of the token each node was built from, as in the tree form.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Spans {
+    pub exprs: SideTable<Expr, Span>,
+    pub stmts: SideTable<Stmt, Span>,
+    pub blocks: SideTable<Block, Span>,
+    pub patterns: SideTable<Pattern, Span>,
+    pub idents: SideTable<Ident, Span>,
+}
+
+// A name at the place it is bound: a `let` pattern, a parameter or a `for`
+// variable. Names that are used rather than bound are `Expr::Identifier`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Let {
+        pattern: PatternId,
+        annotation: Option<TypeAnnotation>,
+        value: ExprId,
+    },
+    Return(ExprId),
+    Expression(ExprId),
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub statements: Vec<StmtId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(IdentId),
+    Wildcard,
+    Literal(ExprId),
+    Array {
+        elements: Vec<PatternId>,
+        rest: Option<IdentId>,
+    },
+    // Field names are hash keys, not bindings.
+    Hash(Vec<(String, PatternId)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Identifier(String),
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Prefix {
+        operator: String,
+        right: ExprId,
+    },
+    Infix {
+        operator: String,
+        left: ExprId,
+        right: ExprId,
+    },
+    If {
+        condition: ExprId,
+        consequence: BlockId,
+        alternative: Option<BlockId>,
+    },
+    Function {
+        parameters: Vec<Parameter>,
+        rest: Option<IdentId>,
+        result: Option<TypeAnnotation>,
+        body: BlockId,
+    },
+    Macro {
+        parameters: Vec<IdentId>,
+        body: BlockId,
+    },
+    Call {
+        function: ExprId,
+        arguments: Vec<ExprId>,
+        named_arguments: Vec<(String, ExprId)>,
+    },
+    Array(Vec<ExprId>),
+    Index {
+        left: ExprId,
+        index: ExprId,
+    },
+    Hash(Vec<(ExprId, ExprId)>),
+    While {
+        condition: ExprId,
+        body: BlockId,
+    },
+    For {
+        variable: IdentId,
+        iterable: ExprId,
+        body: BlockId,
+    },
+    Assign {
+        operator: String,
+        target: ExprId,
+        value: ExprId,
+    },
+    Match {
+        subject: ExprId,
+        arms: Vec<MatchArm>,
+    },
+    Range {
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+        inclusive: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: IdentId,
+    pub annotation: Option<TypeAnnotation>,
+    pub default: Option<ExprId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: PatternId,
+    pub guard: Option<ExprId>,
+    pub body: ExprId,
+}
+
+impl From<&ast::Program> for Ast {
+    fn from(program: &ast::Program) -> Ast {
+        let mut ast = Ast::default();
+        ast.statements = program
+            .statements
+            .iter()
+            .map(|s| ast.lower_stmt(s))
+            .collect();
+        ast
+    }
+}
+
+impl Ast {
+    // The names `pattern` binds, in source order.
+    pub fn pattern_bindings(&self, pattern: PatternId) -> Vec<IdentId> {
+        match &self.patterns[pattern] {
+            Pattern::Identifier(ident) => vec![*ident],
+            Pattern::Wildcard | Pattern::Literal(_) => vec![],
+            Pattern::Array { elements, rest } => elements
+                .iter()
+                .flat_map(|&element| self.pattern_bindings(element))
+                .chain(*rest)
+                .collect(),
+            Pattern::Hash(fields) => fields
+                .iter()
+                .flat_map(|(_, value)| self.pattern_bindings(*value))
+                .collect(),
+        }
+    }
+}
+
+// Conversion from the tree form. Each `lower_*` method lowers the children
+// of a node before allocating the node itself.
+impl Ast {
+    fn lower_stmt(&mut self, stmt: &ast::Statement) -> StmtId {
+        let (node, span) = match stmt {
+            ast::Statement::Let(s) => (
+                Stmt::Let {
+                    pattern: self.lower_pattern(&s.pattern),
+                    annotation: s.annotation.clone(),
+                    value: self.lower_expr(&s.value),
+                },
+                s.token.span,
+            ),
+            ast::Statement::Return(s) => {
+                (Stmt::Return(self.lower_expr(&s.return_value)), s.token.span)
+            }
+            ast::Statement::Expression(s) => (
+                Stmt::Expression(self.lower_expr(&s.expression)),
+                s.expression.span(),
+            ),
+            ast::Statement::Break(s) => (Stmt::Break, s.token.span),
+            ast::Statement::Continue(s) => (Stmt::Continue, s.token.span),
+        };
+        let id = self.stmts.alloc(node);
+        self.spans.stmts.insert(id, span);
+        id
+    }
+
+    fn lower_block(&mut self, block: &ast::BlockStatement) -> BlockId {
+        let statements = block
+            .statements
+            .iter()
+            .map(|s| self.lower_stmt(s))
+            .collect();
+        let id = self.blocks.alloc(Block { statements });
+        self.spans.blocks.insert(id, block.token.span);
+        id
+    }
+
+    fn lower_ident(&mut self, ident: &ast::Identifier) -> IdentId {
+        let id = self.idents.alloc(Ident {
+            name: ident.value.clone(),
+        });
+        self.spans.idents.insert(id, ident.token.span);
+        id
+    }
+
+    fn lower_pattern(&mut self, pattern: &ast::Pattern) -> PatternId {
+        let node = match pattern {
+            ast::Pattern::Identifier(p) => Pattern::Identifier(self.lower_ident(p)),
+            ast::Pattern::Wildcard(_) => Pattern::Wildcard,
+            ast::Pattern::Literal(p) => Pattern::Literal(self.lower_expr(p)),
+            ast::Pattern::Array(p) => Pattern::Array {
+                elements: p.elements.iter().map(|e| self.lower_pattern(e)).collect(),
+                rest: p.rest.as_ref().map(|r| self.lower_ident(r)),
+            },
+            ast::Pattern::Hash(p) => Pattern::Hash(
+                p.fields
+                    .iter()
+                    .map(|(key, value)| (key.value.clone(), self.lower_pattern(value)))
+                    .collect(),
+            ),
+        };
+        let id = self.patterns.alloc(node);
+        self.spans.patterns.insert(id, pattern.span());
+        id
+    }
+
+    fn lower_exprs(&mut self, exprs: &[ast::Expression]) -> Vec<ExprId> {
+        exprs.iter().map(|e| self.lower_expr(e)).collect()
+    }
+
+    fn lower_expr(&mut self, expr: &ast::Expression) -> ExprId {
+        let node = match expr {
+            ast::Expression::Identifier(e) => Expr::Identifier(e.value.clone()),
+            ast::Expression::Integer(e) => Expr::Integer(e.value),
+            ast::Expression::Boolean(e) => Expr::Boolean(e.value),
+            ast::Expression::String(e) => Expr::String(e.value.clone()),
+            ast::Expression::Prefix(e) => Expr::Prefix {
+                operator: e.operator.clone(),
+                right: self.lower_expr(&e.right),
+            },
+            ast::Expression::Infix(e) => Expr::Infix {
+                operator: e.operator.clone(),
+                left: self.lower_expr(&e.left),
+                right: self.lower_expr(&e.right),
+            },
+            ast::Expression::If(e) => Expr::If {
+                condition: self.lower_expr(&e.condition),
+                consequence: self.lower_block(&e.consequence),
+                alternative: e.alternative.as_ref().map(|alt| self.lower_block(alt)),
+            },
+            ast::Expression::Function(e) => Expr::Function {
+                parameters: e
+                    .parameters
+                    .iter()
+                    .map(|p| Parameter {
+                        name: self.lower_ident(&p.name),
+                        annotation: p.annotation.clone(),
+                        default: p.default.as_ref().map(|d| self.lower_expr(d)),
+                    })
+                    .collect(),
+                rest: e.rest.as_ref().map(|r| self.lower_ident(r)),
+                result: e.result.clone(),
+                body: self.lower_block(&e.body),
+            },
+            ast::Expression::Macro(e) => Expr::Macro {
+                parameters: e.parameters.iter().map(|p| self.lower_ident(p)).collect(),
+                body: self.lower_block(&e.body),
+            },
+            ast::Expression::Call(e) => Expr::Call {
+                function: self.lower_expr(&e.function),
+                arguments: self.lower_exprs(&e.arguments),
+                named_arguments: e
+                    .named_arguments
+                    .iter()
+                    .map(|(name, value)| (name.value.clone(), self.lower_expr(value)))
+                    .collect(),
+            },
+            ast::Expression::Array(e) => Expr::Array(self.lower_exprs(&e.elements)),
+            ast::Expression::Index(e) => Expr::Index {
+                left: self.lower_expr(&e.left),
+                index: self.lower_expr(&e.index),
+            },
+            ast::Expression::Hash(e) => Expr::Hash(
+                e.pairs
+                    .iter()
+                    .map(|(k, v)| (self.lower_expr(k), self.lower_expr(v)))
+                    .collect(),
+            ),
+            ast::Expression::While(e) => Expr::While {
+                condition: self.lower_expr(&e.condition),
+                body: self.lower_block(&e.body),
+            },
+            ast::Expression::For(e) => Expr::For {
+                variable: self.lower_ident(&e.variable),
+                iterable: self.lower_expr(&e.iterable),
+                body: self.lower_block(&e.body),
+            },
+            ast::Expression::Assign(e) => Expr::Assign {
+                operator: e.operator.clone(),
+                target: self.lower_expr(&e.target),
+                value: self.lower_expr(&e.value),
+            },
+            ast::Expression::Match(e) => Expr::Match {
+                subject: self.lower_expr(&e.subject),
+                arms: e
+                    .arms
+                    .iter()
+                    .map(|arm| MatchArm {
+                        pattern: self.lower_pattern(&arm.pattern),
+                        guard: arm.guard.as_ref().map(|g| self.lower_expr(g)),
+                        body: self.lower_expr(&arm.body),
+                    })
+                    .collect(),
+            },
+            ast::Expression::Range(e) => Expr::Range {
+                start: e.start.as_ref().map(|s| self.lower_expr(s)),
+                end: e.end.as_ref().map(|s| self.lower_expr(s)),
+                inclusive: e.inclusive,
+            },
+        };
+        let id = self.exprs.alloc(node);
+        self.spans.exprs.insert(id, expr.span());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn lower(input: &str) -> Ast {
+        let lex = lexer::new(input);
+        let mut p = parser::new(Rc::new(RefCell::new(lex)));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser errors: {:?}", p.errors());
+        Ast::from(&program)
+    }
+
+    #[test]
+    fn test_lowering() {
+        let ast = lower("let [a, ...r] = f(1, b: x + 2);");
+        assert_eq!(ast.statements.len(), 1);
+        let (pattern, value) = match &ast.stmts[ast.statements[0]] {
+            Stmt::Let { pattern, value, .. } => (*pattern, *value),
+            other => panic!("expected a let statement, got {other:?}"),
+        };
+
+        match &ast.patterns[pattern] {
+            Pattern::Array { elements, rest } => {
+                assert_eq!(elements.len(), 1);
+                match ast.patterns[elements[0]] {
+                    Pattern::Identifier(id) => assert_eq!(ast.idents[id].name, "a"),
+                    ref other => panic!("expected an identifier pattern, got {other:?}"),
+                }
+                assert_eq!(ast.idents[rest.unwrap()].name, "r");
+            }
+            other => panic!("expected an array pattern, got {other:?}"),
+        }
+
+        match &ast.exprs[value] {
+            Expr::Call {
+                function,
+                arguments,
+                named_arguments,
+            } => {
+                assert_eq!(ast.exprs[*function], Expr::Identifier("f".to_string()));
+                assert_eq!(arguments.len(), 1);
+                assert_eq!(ast.exprs[arguments[0]], Expr::Integer(1));
+                assert_eq!(named_arguments.len(), 1);
+                assert_eq!(named_arguments[0].0, "b");
+                match &ast.exprs[named_arguments[0].1] {
+                    Expr::Infix { operator, .. } => assert_eq!(operator, "+"),
+                    other => panic!("expected an infix expression, got {other:?}"),
+                }
+            }
+            other => panic!("expected a call expression, got {other:?}"),
+        }
+
+        // f, 1, x, 2, x + 2 and the call itself.
+        assert_eq!(ast.exprs.len(), 6);
+        assert_eq!(ast.idents.len(), 2);
+        assert_eq!(ast.patterns.len(), 2);
+    }
+
+    #[test]
+    fn test_children_allocated_first() {
+        let ast = lower(
+            "let f = fn(a, b = 1, ...c) { for (x in a..=b) { if (x) { break } } };
+             match f(1) { [_, y] if y => y, {k: 2} => -k, _ => { \"s\": [true] } };
+             while (i < 3) { i += 1; continue; }
+             s[1..][0] |> g",
+        );
+        for (id, expr) in ast.exprs.iter() {
+            let mut children = vec![];
+            match expr {
+                Expr::Prefix { right, .. } => children.push(*right),
+                Expr::Infix { left, right, .. } => children.extend([*left, *right]),
+                Expr::If { condition, .. } | Expr::While { condition, .. } => {
+                    children.push(*condition)
+                }
+                Expr::Function { parameters, .. } => {
+                    children.extend(parameters.iter().filter_map(|p| p.default))
+                }
+                Expr::Call {
+                    function,
+                    arguments,
+                    named_arguments,
+                } => {
+                    children.push(*function);
+                    children.extend(arguments);
+                    children.extend(named_arguments.iter().map(|(_, e)| *e));
+                }
+                Expr::Array(elements) => children.extend(elements),
+                Expr::Index { left, index } => children.extend([*left, *index]),
+                Expr::Hash(pairs) => children.extend(pairs.iter().flat_map(|(k, v)| [*k, *v])),
+                Expr::For { iterable, .. } => children.push(*iterable),
+                Expr::Assign { target, value, .. } => children.extend([*target, *value]),
+                Expr::Match { subject, arms } => {
+                    children.push(*subject);
+                    for arm in arms {
+                        children.extend(arm.guard);
+                        children.push(arm.body);
+                    }
+                }
+                Expr::Range { start, end, .. } => children.extend(start.iter().chain(end)),
+                _ => {}
+            }
+            for child in children {
+                assert!(
+                    child.index() < id.index(),
+                    "{child:?} allocated after {id:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let ast = lower("let x = 1;\nx + 10");
+        let position = |span: Option<&Span>| span.map(|s| (s.line, s.column));
+
+        assert_eq!(
+            position(ast.spans.stmts.get(ast.statements[0])),
+            Some((1, 1))
+        );
+
+        let infix = match ast.stmts[ast.statements[1]] {
+            Stmt::Expression(e) => e,
+            ref other => panic!("expected an expression statement, got {other:?}"),
+        };
+        let Expr::Infix { left, right, .. } = ast.exprs[infix] else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(position(ast.spans.exprs.get(infix)), Some((2, 3)));
+        assert_eq!(position(ast.spans.exprs.get(left)), Some((2, 1)));
+        assert_eq!(position(ast.spans.exprs.get(right)), Some((2, 5)));
+        assert_eq!(
+            ast.spans.stmts.get(ast.statements[1]),
+            ast.spans.exprs.get(infix)
+        );
+
+        // Every node gets a span.
+        assert!(ast.exprs.iter().all(|(id, _)| ast.spans.exprs.contains(id)));
+        assert!(ast
+            .idents
+            .iter()
+            .all(|(id, _)| ast.spans.idents.contains(id)));
+    }
+
+    #[test]
+    fn test_side_table() {
+        let ast = lower("1 + true");
+        let mut types: SideTable<Expr, &str> = SideTable::new();
+        for (id, expr) in ast.exprs.iter() {
+            match expr {
+                Expr::Integer(_) => types.insert(id, "int"),
+                Expr::Boolean(_) => types.insert(id, "bool"),
+                _ => None,
+            };
+        }
+        let ids: Vec<ExprId> = ast.exprs.iter().map(|(id, _)| id).collect();
+        assert_eq!(types.get(ids[0]), Some(&"int"));
+        assert_eq!(types.get(ids[1]), Some(&"bool"));
+        assert_eq!(types.get(ids[2]), None);
+        assert!(!types.contains(ids[2]));
+        assert_eq!(types.insert(ids[0], "number"), Some("int"));
+        assert_eq!(types.get(ids[0]), Some(&"number"));
+    }
+}
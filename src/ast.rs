@@ -1,58 +1,1903 @@
-use crate::token::Token;
+use std::fmt;
 
-trait Node {
+use crate::parser::{token_precedence, Precedence};
+use crate::token::{Span, Token};
+
+// Every node renders back to Monkey source through `Display`. The output is
+// minimally parenthesized: a sub-expression is only wrapped in parentheses
+// when the parser would otherwise bind it differently.
+pub trait Node: fmt::Display {
     fn token_literal(&self) -> &str;
 }
 
-trait Statement: Node {
-    fn statement_node(&self);
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+impl Node for Program {
+    fn token_literal(&self) -> &str {
+        match self.statements.first() {
+            Some(s) => s.token_literal(),
+            None => "",
+        }
+    }
 }
 
-trait Expression: Node {
-    fn expression_node(&self);
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, s) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{s}")?;
+        }
+        Ok(())
+    }
 }
 
-struct Program<T: Statement> {
-    pub statements: Vec<T>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let(Box<LetStatement>),
+    Return(ReturnStatement),
+    Expression(ExpressionStatement),
+    Break(BreakStatement),
+    Continue(ContinueStatement),
 }
 
-impl<T: Statement> Node for Program<T> {
+impl Node for Statement {
     fn token_literal(&self) -> &str {
-        if let Some(s) = self.statements.first() {
-            return s.token_literal();
+        match self {
+            Statement::Let(s) => s.token_literal(),
+            Statement::Return(s) => s.token_literal(),
+            Statement::Expression(s) => s.token_literal(),
+            Statement::Break(s) => s.token_literal(),
+            Statement::Continue(s) => s.token_literal(),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Let(s) => write!(f, "{s}"),
+            Statement::Return(s) => write!(f, "{s}"),
+            Statement::Expression(s) => write!(f, "{s}"),
+            Statement::Break(s) => write!(f, "{s}"),
+            Statement::Continue(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Identifier(Identifier),
+    Integer(IntegerLiteral),
+    Boolean(Boolean),
+    String(StringLiteral),
+    Prefix(PrefixExpression),
+    Infix(InfixExpression),
+    If(IfExpression),
+    Function(FunctionLiteral),
+    Macro(MacroLiteral),
+    Call(CallExpression),
+    Array(ArrayLiteral),
+    Index(IndexExpression),
+    Hash(HashLiteral),
+    While(WhileExpression),
+    For(ForExpression),
+    Assign(AssignExpression),
+    Match(MatchExpression),
+    Range(RangeExpression),
+}
+
+impl Expression {
+    // How tightly the expression binds when printed. Primary expressions
+    // never need to be wrapped.
+    pub fn precedence(&self) -> Precedence {
+        match self {
+            Expression::Assign(_) => Precedence::Assign,
+            Expression::Range(_) => Precedence::Range,
+            Expression::Prefix(_) => Precedence::Prefix,
+            Expression::Infix(e) => token_precedence(&e.token.t_type),
+            Expression::Call(_) => Precedence::Call,
+            _ => Precedence::Index,
+        }
+    }
+
+    // The span of the expression's own token, e.g. the operator of an infix
+    // expression, used to point diagnostics at it.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Identifier(e) => e.token.span,
+            Expression::Integer(e) => e.token.span,
+            Expression::Boolean(e) => e.token.span,
+            Expression::String(e) => e.token.span,
+            Expression::Prefix(e) => e.token.span,
+            Expression::Infix(e) => e.token.span,
+            Expression::If(e) => e.token.span,
+            Expression::Function(e) => e.token.span,
+            Expression::Macro(e) => e.token.span,
+            Expression::Call(e) => e.token.span,
+            Expression::Array(e) => e.token.span,
+            Expression::Index(e) => e.token.span,
+            Expression::Hash(e) => e.token.span,
+            Expression::While(e) => e.token.span,
+            Expression::For(e) => e.token.span,
+            Expression::Assign(e) => e.token.span,
+            Expression::Match(e) => e.token.span,
+            Expression::Range(e) => e.token.span,
+        }
+    }
+}
+
+impl Node for Expression {
+    fn token_literal(&self) -> &str {
+        match self {
+            Expression::Identifier(e) => e.token_literal(),
+            Expression::Integer(e) => e.token_literal(),
+            Expression::Boolean(e) => e.token_literal(),
+            Expression::String(e) => e.token_literal(),
+            Expression::Prefix(e) => e.token_literal(),
+            Expression::Infix(e) => e.token_literal(),
+            Expression::If(e) => e.token_literal(),
+            Expression::Function(e) => e.token_literal(),
+            Expression::Macro(e) => e.token_literal(),
+            Expression::Call(e) => e.token_literal(),
+            Expression::Array(e) => e.token_literal(),
+            Expression::Index(e) => e.token_literal(),
+            Expression::Hash(e) => e.token_literal(),
+            Expression::While(e) => e.token_literal(),
+            Expression::For(e) => e.token_literal(),
+            Expression::Assign(e) => e.token_literal(),
+            Expression::Match(e) => e.token_literal(),
+            Expression::Range(e) => e.token_literal(),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Identifier(e) => write!(f, "{e}"),
+            Expression::Integer(e) => write!(f, "{e}"),
+            Expression::Boolean(e) => write!(f, "{e}"),
+            Expression::String(e) => write!(f, "{e}"),
+            Expression::Prefix(e) => write!(f, "{e}"),
+            Expression::Infix(e) => write!(f, "{e}"),
+            Expression::If(e) => write!(f, "{e}"),
+            Expression::Function(e) => write!(f, "{e}"),
+            Expression::Macro(e) => write!(f, "{e}"),
+            Expression::Call(e) => write!(f, "{e}"),
+            Expression::Array(e) => write!(f, "{e}"),
+            Expression::Index(e) => write!(f, "{e}"),
+            Expression::Hash(e) => write!(f, "{e}"),
+            Expression::While(e) => write!(f, "{e}"),
+            Expression::For(e) => write!(f, "{e}"),
+            Expression::Assign(e) => write!(f, "{e}"),
+            Expression::Match(e) => write!(f, "{e}"),
+            Expression::Range(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+// Write `expr`, wrapping it in parentheses when it binds looser than `min`.
+fn write_operand(f: &mut fmt::Formatter, expr: &Expression, min: Precedence) -> fmt::Result {
+    if expr.precedence() < min {
+        write!(f, "({expr})")
+    } else {
+        write!(f, "{expr}")
+    }
+}
+
+// A `///` comment right above a `let` is kept as its `doc`, and passed on
+// to a function literal bound by it that has no doc comment of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LetStatement {
+    pub token: Token,
+    pub pattern: Pattern,
+    pub annotation: Option<TypeAnnotation>,
+    pub value: Expression,
+    pub doc: Option<String>,
+}
+
+impl Node for LetStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for LetStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_doc(f, &self.doc)?;
+        write!(f, "let {}", self.pattern)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {annotation}")?;
+        }
+        write!(f, " = ")?;
+        match &self.value {
+            // Written once above the `let`, the parser hands it down again.
+            Expression::Function(func) if func.doc == self.doc => func.write_undocumented(f)?,
+            value => write!(f, "{value}")?,
+        }
+        write!(f, ";")
+    }
+}
+
+// Write a doc comment as `///` lines. Each ends the line it is on, so the
+// source continues on the next one.
+fn write_doc(f: &mut fmt::Formatter, doc: &Option<String>) -> fmt::Result {
+    if let Some(doc) = doc {
+        for line in doc.split('\n') {
+            if line.is_empty() {
+                writeln!(f, "///")?;
+            } else {
+                writeln!(f, "/// {line}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The left-hand side of a `let` or of a `match` arm: a name, `_`, a literal
+// or a destructuring pattern. Literals are integers (optionally negated),
+// strings and booleans, and match values equal to them. Patterns nest, and
+// the parser rejects a pattern that binds the same name twice.
+//
+// Whether a value fits is only known at runtime. A `match` arm that does not
+// fit is skipped; in a `let` the mismatch is an error:
+//
+//   cannot destructure INTEGER as an array
+//   expected 2 elements to destructure, got 3
+//   expected at least 2 elements to destructure, got 1
+//   cannot destructure INTEGER as a hash
+//   key "age" not found in hash
+//   2 does not match pattern 1
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(Identifier),
+    Wildcard(WildcardPattern),
+    Literal(Box<Expression>),
+    Array(ArrayPattern),
+    Hash(HashPattern),
+}
+
+impl Pattern {
+    // Every name the pattern binds, in source order.
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Identifier(ident) => vec![ident],
+            Pattern::Wildcard(_) | Pattern::Literal(_) => vec![],
+            Pattern::Array(p) => p
+                .elements
+                .iter()
+                .flat_map(Pattern::bindings)
+                .chain(p.rest.iter())
+                .collect(),
+            Pattern::Hash(p) => p.fields.iter().flat_map(|(_, v)| v.bindings()).collect(),
+        }
+    }
+
+    // Where the pattern starts.
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Identifier(p) => p.token.span,
+            Pattern::Wildcard(p) => p.token.span,
+            Pattern::Literal(p) => p.span(),
+            Pattern::Array(p) => p.token.span,
+            Pattern::Hash(p) => p.token.span,
+        }
+    }
+}
+
+impl Node for Pattern {
+    fn token_literal(&self) -> &str {
+        match self {
+            Pattern::Identifier(p) => p.token_literal(),
+            Pattern::Wildcard(p) => p.token_literal(),
+            Pattern::Literal(p) => p.token_literal(),
+            Pattern::Array(p) => p.token_literal(),
+            Pattern::Hash(p) => p.token_literal(),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Identifier(p) => write!(f, "{p}"),
+            Pattern::Wildcard(p) => write!(f, "{p}"),
+            Pattern::Literal(p) => write!(f, "{p}"),
+            Pattern::Array(p) => write!(f, "{p}"),
+            Pattern::Hash(p) => write!(f, "{p}"),
+        }
+    }
+}
+
+// `_` matches any value without binding it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WildcardPattern {
+    pub token: Token,
+}
+
+impl Node for WildcardPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for WildcardPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "_")
+    }
+}
+
+// `[a, b, ...rest]` matches an array. Without a rest binding the array must
+// have exactly as many elements as the pattern; with one it must have at
+// least as many, and `rest` receives a new array of the remaining elements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayPattern {
+    pub token: Token,
+    pub elements: Vec<Pattern>,
+    pub rest: Option<Identifier>,
+}
+
+impl Node for ArrayPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for ArrayPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut elements: Vec<String> = self.elements.iter().map(|e| e.to_string()).collect();
+        if let Some(rest) = &self.rest {
+            elements.push(format!("...{rest}"));
+        }
+        write!(f, "[{}]", elements.join(", "))
+    }
+}
+
+// `{name, age: years}` matches a hash. Each field looks up its name as a
+// string key and matches the value against its pattern; the shorthand
+// `{name}` stands for `{name: name}`. Keys the pattern does not mention are
+// ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashPattern {
+    pub token: Token,
+    pub fields: Vec<(Identifier, Pattern)>,
+}
+
+impl Node for HashPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for HashPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(key, value)| match value {
+                Pattern::Identifier(ident) if ident.value == key.value => key.to_string(),
+                _ => format!("{key}: {value}"),
+            })
+            .collect();
+        write!(f, "{{{}}}", fields.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnStatement {
+    pub token: Token,
+    pub return_value: Expression,
+}
+
+impl Node for ReturnStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for ReturnStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "return {};", self.return_value)
+    }
+}
+
+// An expression statement has no token of its own; it reports the token of
+// the wrapped expression so that redundant parentheses around it do not
+// change the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionStatement {
+    pub expression: Expression,
+}
+
+impl Node for ExpressionStatement {
+    fn token_literal(&self) -> &str {
+        self.expression.token_literal()
+    }
+}
+
+impl fmt::Display for ExpressionStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};", self.expression)
+    }
+}
+
+// Leaves the innermost enclosing loop. The parser rejects `break` and
+// `continue` outside of a loop body, including from inside a function
+// literal nested in a loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakStatement {
+    pub token: Token,
+}
+
+impl Node for BreakStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for BreakStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "break;")
+    }
+}
+
+// Skips the rest of the current iteration of the innermost enclosing loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinueStatement {
+    pub token: Token,
+}
+
+impl Node for ContinueStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for ContinueStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "continue;")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStatement {
+    pub token: Token,
+    pub statements: Vec<Statement>,
+}
+
+impl Node for BlockStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{}}");
+        }
+        write!(f, "{{")?;
+        for s in &self.statements {
+            write!(f, " {s}")?;
+        }
+        write!(f, " }}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub token: Token,
+    pub value: String,
+}
+
+impl Node for Identifier {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegerLiteral {
+    pub token: Token,
+    pub value: i64,
+}
+
+impl Node for IntegerLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for IntegerLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Boolean {
+    pub token: Token,
+    pub value: bool,
+}
+
+impl Node for Boolean {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for Boolean {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral {
+    pub token: Token,
+    pub value: String,
+}
+
+impl Node for StringLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for StringLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixExpression {
+    pub token: Token,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+
+impl Node for PrefixExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for PrefixExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operator)?;
+        write_operand(f, &self.right, Precedence::Prefix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfixExpression {
+    pub token: Token,
+    pub left: Box<Expression>,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+
+impl Node for InfixExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for InfixExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Infix operators are left associative, so a right operand of the
+        // same precedence has to keep its parentheses.
+        let prec = token_precedence(&self.token.t_type);
+        write_operand(f, &self.left, prec)?;
+        write!(f, " {} ", self.operator)?;
+        if self.right.precedence() <= prec {
+            write!(f, "({})", self.right)
         } else {
-            return "";
+            write!(f, "{}", self.right)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfExpression {
+    pub token: Token,
+    pub condition: Box<Expression>,
+    pub consequence: BlockStatement,
+    pub alternative: Option<BlockStatement>,
+}
+
+impl Node for IfExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for IfExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "if ({}) {}", self.condition, self.consequence)?;
+        if let Some(alt) = &self.alternative {
+            write!(f, " else {alt}")?;
+        }
+        Ok(())
+    }
+}
+
+// `fn(a, b = 10, ...rest) { body }`. Parameters with a default come after
+// the required ones, and the rest parameter comes last. A call binds its
+// positional arguments to the parameters in order, collects any surplus
+// into `rest` as an array, then binds named arguments by parameter name.
+// Defaults of the parameters still unbound are evaluated in the new scope,
+// left to right, so they can refer to earlier parameters. Binding fails at
+// runtime with:
+//
+//   too many arguments: expected at most 2, got 3
+//   unknown parameter c
+//   argument b given twice
+//   missing argument for parameter a
+//
+// `doc` is the `///` comment written above the literal, or above the `let`
+// binding it. The evaluator's `doc(f)` builtin returns it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionLiteral {
+    pub token: Token,
+    pub parameters: Vec<Parameter>,
+    pub rest: Option<Identifier>,
+    // The annotated type of the result, after `->`.
+    pub result: Option<TypeAnnotation>,
+    pub body: BlockStatement,
+    pub doc: Option<String>,
+}
+
+impl FunctionLiteral {
+    // The parameter list as written, such as `a: int, b = 10, ...rest`.
+    pub fn signature(&self) -> String {
+        let mut params: Vec<String> = self.parameters.iter().map(|p| p.to_string()).collect();
+        if let Some(rest) = &self.rest {
+            params.push(format!("...{rest}"));
+        }
+        params.join(", ")
+    }
+
+    fn write_undocumented(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fn({})", self.signature())?;
+        if let Some(result) = &self.result {
+            write!(f, " -> {result}")?;
+        }
+        write!(f, " {}", self.body)
+    }
+}
+
+impl Node for FunctionLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for FunctionLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_doc(f, &self.doc)?;
+        self.write_undocumented(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: Identifier,
+    pub annotation: Option<TypeAnnotation>,
+    pub default: Option<Expression>,
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {annotation}")?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {default}")?;
         }
+        Ok(())
     }
 }
 
-struct LetStatement<'a> {
-    token: Token,
-    name: Identifier<'a>,
-    value: dyn Expression,
+// A type written after `:` in a `let` or a parameter, or after `->` for the
+// result of a function literal:
+//
+//   int  bool  string  null  range  [T]  {K: V}  fn(T, U) -> R
+//
+// Annotations are optional. A value is checked against its annotation when
+// it is bound or returned, and one of another type is a runtime error:
+//
+//   expected int for x, got STRING
+//   expected int for an element of parameter xs, got BOOLEAN
+//   expected bool for the result, got INTEGER
+//
+// Arrays and hashes are checked element by element, but a function only
+// has to be a function: its parameter and result types are left to the
+// type checker, which proves most annotations before the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAnnotation {
+    // `int`, `bool`, `string`, `null` or `range`.
+    Named(Identifier),
+    Array(Box<TypeAnnotation>),
+    Hash(Box<TypeAnnotation>, Box<TypeAnnotation>),
+    Function(Vec<TypeAnnotation>, Box<TypeAnnotation>),
+}
+
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeAnnotation::Named(name) => write!(f, "{name}"),
+            TypeAnnotation::Array(element) => write!(f, "[{element}]"),
+            TypeAnnotation::Hash(key, value) => write!(f, "{{{key}: {value}}}"),
+            TypeAnnotation::Function(params, result) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "fn({}) -> {result}", params.join(", "))
+            }
+        }
+    }
 }
 
-impl Statement for LetStatement<'_> {
-    fn statement_node(&self) {}
+// `macro(a, b) { body }`, bound by a top-level `let`. Macro expansion
+// removes those definitions and replaces every call of the macro with the
+// code its body evaluates to, which must be a `quote(...)`. The arguments
+// are not evaluated: each parameter is bound to its argument, quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroLiteral {
+    pub token: Token,
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
 }
 
-impl Node for LetStatement<'_> {
+impl Node for MacroLiteral {
     fn token_literal(&self) -> &str {
         &self.token.literal
     }
 }
 
-struct Identifier<'a> {
-    token: Token,
-    value: &'a str,
+impl fmt::Display for MacroLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.parameters.iter().map(|p| p.to_string()).collect();
+        write!(f, "macro({}) {}", params.join(", "), self.body)
+    }
+}
+
+// Named arguments such as `f(1, b: 2)` follow the positional ones. Both
+// are evaluated left to right, in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallExpression {
+    pub token: Token,
+    pub function: Box<Expression>,
+    pub arguments: Vec<Expression>,
+    pub named_arguments: Vec<(Identifier, Expression)>,
+}
+
+impl Node for CallExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for CallExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_operand(f, &self.function, Precedence::Call)?;
+        let args: Vec<String> = self
+            .arguments
+            .iter()
+            .map(|a| a.to_string())
+            .chain(
+                self.named_arguments
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}")),
+            )
+            .collect();
+        write!(f, "({})", args.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayLiteral {
+    pub token: Token,
+    pub elements: Vec<Expression>,
+}
+
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for ArrayLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elements: Vec<String> = self.elements.iter().map(|e| e.to_string()).collect();
+        write!(f, "[{}]", elements.join(", "))
+    }
+}
+
+// `left[index]` looks up an array element, a character of a string or a
+// hash value. A negative integer index counts from the end, so `-1` is the
+// last element; an index that is out of range either way gives `null`.
+//
+// When the index is a range the result is a slice: a new array, or a new
+// string for a string. Each bound is first counted from the end if it is
+// negative, then clamped to `0..len`; a missing start is 0 and a missing end
+// is the length. An inclusive range takes one more element at the end. A
+// slice whose start is not before its end is empty, so slicing never fails
+// on bounds: `[1, 2, 3][-2..]` is `[2, 3]` and `"monkey"[..10]` is
+// `"monkey"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexExpression {
+    pub token: Token,
+    pub left: Box<Expression>,
+    pub index: Box<Expression>,
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for IndexExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Calls and index expressions chain left to right, so either can be
+        // indexed without parentheses.
+        write_operand(f, &self.left, Precedence::Call)?;
+        write!(f, "[{}]", self.index)
+    }
+}
+
+// `start..end` or `start..=end`, the integers from `start` up to `end`,
+// excluding or including `end`. A range whose end is not after its start is
+// empty; ranges never count down. A range is a value of its own: `for` loops
+// iterate over its integers, `len` gives its size, and indexing with it
+// slices. The start may be left out, and so may the end of an exclusive
+// range followed directly by `]`, as in `str[..5]` or `arr[1..]`. Such open
+// ranges can only be used to slice: the parser rejects one that is not the
+// whole index of an index expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeExpression {
+    pub token: Token,
+    pub start: Option<Box<Expression>>,
+    pub end: Option<Box<Expression>>,
+    pub inclusive: bool,
+}
+
+impl Node for RangeExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for RangeExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Ranges do not chain, so both bounds must bind tighter than `..`.
+        if let Some(start) = &self.start {
+            write_operand(f, start, Precedence::Equals)?;
+        }
+        write!(f, "{}", if self.inclusive { "..=" } else { ".." })?;
+        if let Some(end) = &self.end {
+            write_operand(f, end, Precedence::Equals)?;
+        }
+        Ok(())
+    }
+}
+
+// `while (condition) { body }` re-evaluates the condition before every
+// iteration and runs the body while it is truthy. Like every loop it
+// evaluates to `null`; a `return` inside the body leaves the enclosing
+// function as usual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileExpression {
+    pub token: Token,
+    pub condition: Box<Expression>,
+    pub body: BlockStatement,
+}
+
+impl Node for WhileExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for WhileExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "while ({}) {}", self.condition, self.body)
+    }
 }
 
-impl Expression for Identifier<'_> {
-    fn expression_node(&self) {}
+// `for (variable in iterable) { body }` evaluates the iterable once and
+// binds `variable` to each element of an array, each character of a string,
+// each key of a hash or each integer of a range in turn, in a scope private
+// to that iteration. Iterating over anything else is an error. The loop
+// evaluates to `null`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForExpression {
+    pub token: Token,
+    pub variable: Identifier,
+    pub iterable: Box<Expression>,
+    pub body: BlockStatement,
 }
 
-impl Node for Identifier<'_> {
+impl Node for ForExpression {
     fn token_literal(&self) -> &str {
         &self.token.literal
     }
 }
+
+impl fmt::Display for ForExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "for ({} in {}) {}",
+            self.variable, self.iterable, self.body
+        )
+    }
+}
+
+// `target = value` or a compound form such as `target += value`. The parser
+// only accepts identifiers and index expressions as targets. Assigning
+// updates the nearest enclosing binding of a name, and assigning to a name
+// that was never declared with `let` is a runtime error. A compound
+// assignment evaluates the target's collection and index once, then applies
+// the operator. The expression evaluates to the assigned value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignExpression {
+    pub token: Token,
+    pub target: Box<Expression>,
+    pub operator: String,
+    pub value: Box<Expression>,
+}
+
+impl Node for AssignExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for AssignExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.target, self.operator, self.value)
+    }
+}
+
+// `match subject { pattern => body, ... }` evaluates the subject once and
+// tries the arms in order. The first arm whose pattern fits and whose guard,
+// if any, is truthy has its body evaluated in a scope holding the pattern's
+// bindings, and that is the value of the expression. When no arm applies
+// the result is the runtime error `no match arm for VALUE`. The parser warns
+// about arms that can never be reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExpression {
+    pub token: Token,
+    pub subject: Box<Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Node for MatchExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for MatchExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.arms.is_empty() {
+            return write!(f, "match {} {{}}", self.subject);
+        }
+        let arms: Vec<String> = self.arms.iter().map(|a| a.to_string()).collect();
+        write!(f, "match {} {{ {} }}", self.subject, arms.join(", "))
+    }
+}
+
+// A single `pattern if guard => body` arm. The guard sees the pattern's
+// bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Expression,
+}
+
+impl fmt::Display for MatchArm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        if let Some(guard) = &self.guard {
+            write!(f, " if {guard}")?;
+        }
+        write!(f, " => {}", self.body)
+    }
+}
+
+// Pairs are kept in source order; duplicate keys are left for the evaluator
+// to resolve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashLiteral {
+    pub token: Token,
+    pub pairs: Vec<(Expression, Expression)>,
+}
+
+impl Node for HashLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+}
+
+impl fmt::Display for HashLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect();
+        write!(f, "{{{}}}", pairs.join(", "))
+    }
+}
+
+// Read-only traversal. Every method defaults to walking the children of the
+// node, so an implementation only overrides the nodes it cares about and
+// calls the matching `walk_*` function to keep descending.
+pub trait Visitor: Sized {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        walk_statement(self, stmt)
+    }
+
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        walk_block_statement(self, block)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern)
+    }
+
+    fn visit_identifier(&mut self, _ident: &Identifier) {}
+}
+
+pub fn walk_program<V: Visitor>(v: &mut V, program: &Program) {
+    for stmt in &program.statements {
+        v.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement<V: Visitor>(v: &mut V, stmt: &Statement) {
+    match stmt {
+        Statement::Let(s) => {
+            v.visit_pattern(&s.pattern);
+            v.visit_expression(&s.value);
+        }
+        Statement::Return(s) => v.visit_expression(&s.return_value),
+        Statement::Expression(s) => v.visit_expression(&s.expression),
+        Statement::Break(_) | Statement::Continue(_) => {}
+    }
+}
+
+pub fn walk_block_statement<V: Visitor>(v: &mut V, block: &BlockStatement) {
+    for stmt in &block.statements {
+        v.visit_statement(stmt);
+    }
+}
+
+// Hash pattern keys name fields rather than bindings, so only the
+// identifiers that get bound are visited.
+pub fn walk_pattern<V: Visitor>(v: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Identifier(ident) => v.visit_identifier(ident),
+        Pattern::Wildcard(_) => {}
+        Pattern::Literal(literal) => v.visit_expression(literal),
+        Pattern::Array(p) => {
+            for element in &p.elements {
+                v.visit_pattern(element);
+            }
+            if let Some(rest) = &p.rest {
+                v.visit_identifier(rest);
+            }
+        }
+        Pattern::Hash(p) => {
+            for (_, value) in &p.fields {
+                v.visit_pattern(value);
+            }
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor>(v: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Identifier(e) => v.visit_identifier(e),
+        Expression::Integer(_) | Expression::Boolean(_) | Expression::String(_) => {}
+        Expression::Prefix(e) => v.visit_expression(&e.right),
+        Expression::Infix(e) => {
+            v.visit_expression(&e.left);
+            v.visit_expression(&e.right);
+        }
+        Expression::If(e) => {
+            v.visit_expression(&e.condition);
+            v.visit_block_statement(&e.consequence);
+            if let Some(alt) = &e.alternative {
+                v.visit_block_statement(alt);
+            }
+        }
+        Expression::Function(e) => {
+            for param in &e.parameters {
+                v.visit_identifier(&param.name);
+                if let Some(default) = &param.default {
+                    v.visit_expression(default);
+                }
+            }
+            if let Some(rest) = &e.rest {
+                v.visit_identifier(rest);
+            }
+            v.visit_block_statement(&e.body);
+        }
+        Expression::Macro(e) => {
+            for param in &e.parameters {
+                v.visit_identifier(param);
+            }
+            v.visit_block_statement(&e.body);
+        }
+        Expression::Call(e) => {
+            v.visit_expression(&e.function);
+            for arg in &e.arguments {
+                v.visit_expression(arg);
+            }
+            // The names of named arguments refer to the callee's parameters,
+            // not to bindings in scope, so only the values are visited.
+            for (_, value) in &e.named_arguments {
+                v.visit_expression(value);
+            }
+        }
+        Expression::Array(e) => {
+            for element in &e.elements {
+                v.visit_expression(element);
+            }
+        }
+        Expression::Index(e) => {
+            v.visit_expression(&e.left);
+            v.visit_expression(&e.index);
+        }
+        Expression::Hash(e) => {
+            for (key, value) in &e.pairs {
+                v.visit_expression(key);
+                v.visit_expression(value);
+            }
+        }
+        Expression::While(e) => {
+            v.visit_expression(&e.condition);
+            v.visit_block_statement(&e.body);
+        }
+        Expression::For(e) => {
+            v.visit_identifier(&e.variable);
+            v.visit_expression(&e.iterable);
+            v.visit_block_statement(&e.body);
+        }
+        Expression::Assign(e) => {
+            v.visit_expression(&e.target);
+            v.visit_expression(&e.value);
+        }
+        Expression::Range(e) => {
+            if let Some(start) = &e.start {
+                v.visit_expression(start);
+            }
+            if let Some(end) = &e.end {
+                v.visit_expression(end);
+            }
+        }
+        Expression::Match(e) => {
+            v.visit_expression(&e.subject);
+            for arm in &e.arms {
+                v.visit_pattern(&arm.pattern);
+                if let Some(guard) = &arm.guard {
+                    v.visit_expression(guard);
+                }
+                v.visit_expression(&arm.body);
+            }
+        }
+    }
+}
+
+// Rewriting traversal. Each method takes ownership of a node and returns
+// its replacement; the defaults rebuild the node from its folded children,
+// so the identity fold returns an equal tree.
+pub trait Fold: Sized {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, stmt: Statement) -> Statement {
+        fold_statement(self, stmt)
+    }
+
+    fn fold_block_statement(&mut self, block: BlockStatement) -> BlockStatement {
+        fold_block_statement(self, block)
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_expression(self, expr)
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        fold_pattern(self, pattern)
+    }
+
+    fn fold_identifier(&mut self, ident: Identifier) -> Identifier {
+        ident
+    }
+}
+
+pub fn fold_program<F: Fold>(f: &mut F, program: Program) -> Program {
+    Program {
+        statements: program
+            .statements
+            .into_iter()
+            .map(|s| f.fold_statement(s))
+            .collect(),
+    }
+}
+
+pub fn fold_statement<F: Fold>(f: &mut F, stmt: Statement) -> Statement {
+    match stmt {
+        Statement::Let(s) => Statement::Let(Box::new(LetStatement {
+            token: s.token,
+            pattern: f.fold_pattern(s.pattern),
+            annotation: s.annotation,
+            value: f.fold_expression(s.value),
+            doc: s.doc,
+        })),
+        Statement::Return(s) => Statement::Return(ReturnStatement {
+            token: s.token,
+            return_value: f.fold_expression(s.return_value),
+        }),
+        Statement::Expression(s) => Statement::Expression(ExpressionStatement {
+            expression: f.fold_expression(s.expression),
+        }),
+        Statement::Break(_) | Statement::Continue(_) => stmt,
+    }
+}
+
+pub fn fold_block_statement<F: Fold>(f: &mut F, block: BlockStatement) -> BlockStatement {
+    BlockStatement {
+        token: block.token,
+        statements: block
+            .statements
+            .into_iter()
+            .map(|s| f.fold_statement(s))
+            .collect(),
+    }
+}
+
+pub fn fold_pattern<F: Fold>(f: &mut F, pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Identifier(ident) => Pattern::Identifier(f.fold_identifier(ident)),
+        Pattern::Wildcard(_) => pattern,
+        Pattern::Literal(literal) => Pattern::Literal(Box::new(f.fold_expression(*literal))),
+        Pattern::Array(p) => Pattern::Array(ArrayPattern {
+            token: p.token,
+            elements: p
+                .elements
+                .into_iter()
+                .map(|el| f.fold_pattern(el))
+                .collect(),
+            rest: p.rest.map(|rest| f.fold_identifier(rest)),
+        }),
+        Pattern::Hash(p) => Pattern::Hash(HashPattern {
+            token: p.token,
+            fields: p
+                .fields
+                .into_iter()
+                .map(|(key, value)| (key, f.fold_pattern(value)))
+                .collect(),
+        }),
+    }
+}
+
+pub fn fold_expression<F: Fold>(f: &mut F, expr: Expression) -> Expression {
+    match expr {
+        Expression::Identifier(e) => Expression::Identifier(f.fold_identifier(e)),
+        Expression::Integer(_) | Expression::Boolean(_) | Expression::String(_) => expr,
+        Expression::Prefix(e) => Expression::Prefix(PrefixExpression {
+            token: e.token,
+            operator: e.operator,
+            right: Box::new(f.fold_expression(*e.right)),
+        }),
+        Expression::Infix(e) => Expression::Infix(InfixExpression {
+            token: e.token,
+            left: Box::new(f.fold_expression(*e.left)),
+            operator: e.operator,
+            right: Box::new(f.fold_expression(*e.right)),
+        }),
+        Expression::If(e) => Expression::If(IfExpression {
+            token: e.token,
+            condition: Box::new(f.fold_expression(*e.condition)),
+            consequence: f.fold_block_statement(e.consequence),
+            alternative: e.alternative.map(|alt| f.fold_block_statement(alt)),
+        }),
+        Expression::Function(e) => Expression::Function(FunctionLiteral {
+            token: e.token,
+            parameters: e
+                .parameters
+                .into_iter()
+                .map(|p| Parameter {
+                    name: f.fold_identifier(p.name),
+                    annotation: p.annotation,
+                    default: p.default.map(|d| f.fold_expression(d)),
+                })
+                .collect(),
+            rest: e.rest.map(|rest| f.fold_identifier(rest)),
+            result: e.result,
+            body: f.fold_block_statement(e.body),
+            doc: e.doc,
+        }),
+        Expression::Macro(e) => Expression::Macro(MacroLiteral {
+            token: e.token,
+            parameters: e
+                .parameters
+                .into_iter()
+                .map(|p| f.fold_identifier(p))
+                .collect(),
+            body: f.fold_block_statement(e.body),
+        }),
+        Expression::Call(e) => Expression::Call(CallExpression {
+            token: e.token,
+            function: Box::new(f.fold_expression(*e.function)),
+            arguments: e
+                .arguments
+                .into_iter()
+                .map(|a| f.fold_expression(a))
+                .collect(),
+            named_arguments: e
+                .named_arguments
+                .into_iter()
+                .map(|(name, value)| (name, f.fold_expression(value)))
+                .collect(),
+        }),
+        Expression::Array(e) => Expression::Array(ArrayLiteral {
+            token: e.token,
+            elements: e
+                .elements
+                .into_iter()
+                .map(|el| f.fold_expression(el))
+                .collect(),
+        }),
+        Expression::Index(e) => Expression::Index(IndexExpression {
+            token: e.token,
+            left: Box::new(f.fold_expression(*e.left)),
+            index: Box::new(f.fold_expression(*e.index)),
+        }),
+        Expression::Hash(e) => Expression::Hash(HashLiteral {
+            token: e.token,
+            pairs: e
+                .pairs
+                .into_iter()
+                .map(|(k, v)| (f.fold_expression(k), f.fold_expression(v)))
+                .collect(),
+        }),
+        Expression::While(e) => Expression::While(WhileExpression {
+            token: e.token,
+            condition: Box::new(f.fold_expression(*e.condition)),
+            body: f.fold_block_statement(e.body),
+        }),
+        Expression::For(e) => Expression::For(ForExpression {
+            token: e.token,
+            variable: f.fold_identifier(e.variable),
+            iterable: Box::new(f.fold_expression(*e.iterable)),
+            body: f.fold_block_statement(e.body),
+        }),
+        Expression::Assign(e) => Expression::Assign(AssignExpression {
+            token: e.token,
+            target: Box::new(f.fold_expression(*e.target)),
+            operator: e.operator,
+            value: Box::new(f.fold_expression(*e.value)),
+        }),
+        Expression::Range(e) => Expression::Range(RangeExpression {
+            token: e.token,
+            start: e.start.map(|s| Box::new(f.fold_expression(*s))),
+            end: e.end.map(|s| Box::new(f.fold_expression(*s))),
+            inclusive: e.inclusive,
+        }),
+        Expression::Match(e) => Expression::Match(MatchExpression {
+            token: e.token,
+            subject: Box::new(f.fold_expression(*e.subject)),
+            arms: e
+                .arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    pattern: f.fold_pattern(arm.pattern),
+                    guard: arm.guard.map(|g| f.fold_expression(g)),
+                    body: f.fold_expression(arm.body),
+                })
+                .collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+    use crate::token::TokenType;
+
+    #[test]
+    fn test_display() {
+        let program = Program {
+            statements: vec![Statement::Let(Box::new(LetStatement {
+                token: Token::new(TokenType::Let, "let"),
+                pattern: Pattern::Identifier(Identifier {
+                    token: Token::new(TokenType::Ident, "myVar"),
+                    value: "myVar".to_string(),
+                }),
+                annotation: None,
+                value: Expression::Identifier(Identifier {
+                    token: Token::new(TokenType::Ident, "anotherVar"),
+                    value: "anotherVar".to_string(),
+                }),
+                doc: None,
+            }))],
+        };
+
+        assert_eq!(program.to_string(), "let myVar = anotherVar;");
+    }
+
+    #[test]
+    fn test_display_parenthesization() {
+        let tests = vec![
+            ("-a * b", "-a * b;"),
+            ("!-a", "!-a;"),
+            ("-(a + b)", "-(a + b);"),
+            ("a + b + c", "a + b + c;"),
+            ("a + (b + c)", "a + (b + c);"),
+            ("a - (b - c) * d", "a - (b - c) * d;"),
+            ("(a + b) * (c + d)", "(a + b) * (c + d);"),
+            ("((a))", "a;"),
+            ("a < b == c > d", "a < b == c > d;"),
+            ("a == (b == c)", "a == (b == c);"),
+            ("add(a, b * c)(d)", "add(a, b * c)(d);"),
+            ("(a + b)(c)", "(a + b)(c);"),
+            ("-f(x)", "-f(x);"),
+            ("(-f)(x)", "(-f)(x);"),
+            (
+                "if (x < y) { x } else { y }",
+                "if (x < y) { x; } else { y; };",
+            ),
+            ("fn() {}", "fn() {};"),
+            (
+                "let add = fn(x, y) { return x + y; }",
+                "let add = fn(x, y) { return x + y; };",
+            ),
+            ("fn(x) { x }(5)", "fn(x) { x; }(5);"),
+            ("[1, a + b][(c)]", "[1, a + b][c];"),
+            ("(m[i])[j]", "m[i][j];"),
+            ("(f(x))[0]", "f(x)[0];"),
+            (r#"{"a": 1 + 2, b: {}}"#, r#"{"a": 1 + 2, b: {}};"#),
+            ("{(a): [1], }", "{a: [1]};"),
+            ("a = (b = c)", "a = b = c;"),
+            ("(a = b) + 1", "(a = b) + 1;"),
+            ("-(a = b)", "-(a = b);"),
+            ("a[0] += (1 + 2)", "a[0] += 1 + 2;"),
+            ("let [a, [b], ...c] = x", "let [a, [b], ...c] = x;"),
+            ("let [] = x", "let [] = x;"),
+            ("let {a, b: c, d: [e]} = x", "let {a, b: c, d: [e]} = x;"),
+            ("let {a: a,} = x", "let {a} = x;"),
+            ("let [_, a] = x", "let [_, a] = x;"),
+            ("match x {}", "match x {};"),
+            (
+                "match (a + b) { 1 => x, -2 => y, [_, ...t] if (t) => t, {k: \"v\"} => z, _ => w, }",
+                "match a + b { 1 => x, -2 => y, [_, ...t] if t => t, {k: \"v\"} => z, _ => w };",
+            ),
+            ("(match x { _ => f })(1)", "match x { _ => f }(1);"),
+            ("(a + 1)..(b * 2)", "a + 1..b * 2;"),
+            ("(a..b) + 1", "(a..b) + 1;"),
+            ("(a == b)..=(c = d)", "a == b..=(c = d);"),
+            ("-(1..5)", "-(1..5);"),
+            ("s[..]", "s[..];"),
+            (
+                "fn(a, b = (c = 1), ...d) { d }(x, b: (y + 1))",
+                "fn(a, b = c = 1, ...d) { d; }(x, b: y + 1);",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input:?}");
+        }
+    }
+
+    // Collects every identifier the walk reaches, in source order.
+    #[derive(Default)]
+    struct IdentCollector {
+        names: Vec<String>,
+    }
+
+    impl Visitor for IdentCollector {
+        fn visit_identifier(&mut self, ident: &Identifier) {
+            self.names.push(ident.value.clone());
+        }
+    }
+
+    // Counts call expressions but does not descend into function bodies.
+    #[derive(Default)]
+    struct ShallowCallCounter {
+        calls: usize,
+    }
+
+    impl Visitor for ShallowCallCounter {
+        fn visit_expression(&mut self, expr: &Expression) {
+            match expr {
+                Expression::Function(_) => {}
+                Expression::Call(_) => {
+                    self.calls += 1;
+                    walk_expression(self, expr);
+                }
+                _ => walk_expression(self, expr),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let program =
+            parse("let add = fn(x, y) { x + y; }; if (add(a, b) > c) { add(c, d) } else { e }");
+
+        let mut idents = IdentCollector::default();
+        idents.visit_program(&program);
+        assert_eq!(
+            idents.names,
+            ["add", "x", "y", "x", "y", "add", "a", "b", "c", "add", "c", "d", "e"]
+        );
+
+        let program = parse("let {a: [b, ...c], d} = e;");
+        let mut idents = IdentCollector::default();
+        idents.visit_program(&program);
+        assert_eq!(idents.names, ["b", "c", "d", "e"]);
+
+        let program = parse("f(g(1), fn() { h(2) }); i(3);");
+        let mut counter = ShallowCallCounter::default();
+        counter.visit_program(&program);
+        assert_eq!(counter.calls, 3);
+    }
+
+    // Renames identifiers and rewrites `-<integer>` into a literal.
+    struct Rewriter;
+
+    impl Fold for Rewriter {
+        fn fold_identifier(&mut self, ident: Identifier) -> Identifier {
+            Identifier {
+                value: format!("{}_1", ident.value),
+                ..ident
+            }
+        }
+
+        fn fold_expression(&mut self, expr: Expression) -> Expression {
+            match fold_expression(self, expr) {
+                Expression::Prefix(PrefixExpression {
+                    token,
+                    operator,
+                    right,
+                }) => match *right {
+                    Expression::Integer(int) if operator == "-" => {
+                        Expression::Integer(IntegerLiteral {
+                            value: -int.value,
+                            ..int
+                        })
+                    }
+                    right => Expression::Prefix(PrefixExpression {
+                        token,
+                        operator,
+                        right: Box::new(right),
+                    }),
+                },
+                expr => expr,
+            }
+        }
+    }
+
+    struct Identity;
+
+    impl Fold for Identity {}
+
+    #[test]
+    fn test_fold() {
+        let input = "let f = fn(x) { if (x < -1) { -x } else { g(x, -2) } }; f(-3);";
+
+        let program = parse(input);
+        assert_eq!(Identity.fold_program(program.clone()), program);
+
+        let folded = Rewriter.fold_program(program);
+        assert_eq!(
+            folded.to_string(),
+            "let f_1 = fn(x_1) { if (x_1 < -1) { -x_1; } else { g_1(x_1, -2); }; };\nf_1(-3);"
+        );
+        assert_eq!(
+            Rewriter
+                .fold_program(parse("let [a, {b}, ...c] = d;"))
+                .to_string(),
+            "let [a_1, {b: b_1}, ...c_1] = d_1;"
+        );
+        assert!(matches!(
+            &folded.statements[1],
+            Statement::Expression(ExpressionStatement {
+                expression: Expression::Call(call),
+            }) if matches!(call.arguments[0], Expression::Integer(IntegerLiteral { value: -3, .. }))
+        ));
+    }
+
+    // Small xorshift generator so the round-trip property can be checked
+    // over many programs without pulling in a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn gen_expression(rng: &mut Rng, depth: u32) -> String {
+        let choice = if depth == 0 {
+            rng.below(3)
+        } else {
+            rng.below(20)
+        };
+        match choice {
+            0 => ["a", "b", "foo", "bar"][rng.below(4) as usize].to_string(),
+            1 => rng.below(1000).to_string(),
+            2 => ["true", "false"][rng.below(2) as usize].to_string(),
+            3 => {
+                let op = ["-", "!"][rng.below(2) as usize];
+                format!("{op}({})", gen_expression(rng, depth - 1))
+            }
+            4 | 5 => {
+                let op = ["+", "-", "*", "/", "<", ">", "==", "!=", "|>"][rng.below(9) as usize];
+                format!(
+                    "({}) {op} ({})",
+                    gen_expression(rng, depth - 1),
+                    gen_expression(rng, depth - 1)
+                )
+            }
+            6 => format!(
+                "if ({}) {{ {} }} else {{ {} }}",
+                gen_expression(rng, depth - 1),
+                gen_statement(rng, depth - 1),
+                gen_statement(rng, depth - 1)
+            ),
+            7 => {
+                let default = match rng.below(2) {
+                    0 => String::new(),
+                    _ => format!(" = ({})", gen_expression(rng, depth - 1)),
+                };
+                let rest = ["", ", ...r"][rng.below(2) as usize];
+                let x = gen_annotation(rng, ": ");
+                let y = gen_annotation(rng, ": ");
+                let result = gen_annotation(rng, " -> ");
+                format!(
+                    "{}fn(x{x}, y{y}{default}{rest}){result} {{ {} }}",
+                    gen_doc(rng),
+                    gen_statement(rng, depth - 1)
+                )
+            }
+            8 => {
+                let mut args: Vec<String> = (0..rng.below(3))
+                    .map(|_| gen_expression(rng, depth - 1))
+                    .collect();
+                if rng.below(2) == 0 {
+                    args.push(format!("y: ({})", gen_expression(rng, depth - 1)));
+                }
+                format!("({})({})", gen_expression(rng, depth - 1), args.join(", "))
+            }
+            9 => format!("\"s{}\"", rng.below(10)),
+            10 => {
+                let pairs: Vec<String> = (0..rng.below(3))
+                    .map(|_| {
+                        format!(
+                            "{}: {}",
+                            gen_expression(rng, depth - 1),
+                            gen_expression(rng, depth - 1)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            11 => format!(
+                "while ({}) {{ {} break; }}",
+                gen_expression(rng, depth - 1),
+                gen_statement(rng, depth - 1)
+            ),
+            12 => format!(
+                "for (x in {}) {{ continue; {} }}",
+                gen_expression(rng, depth - 1),
+                gen_statement(rng, depth - 1)
+            ),
+            13 => {
+                let op = ["=", "+=", "-=", "*=", "/="][rng.below(5) as usize];
+                let target = match rng.below(2) {
+                    0 => "x".to_string(),
+                    _ => format!(
+                        "({})[{}]",
+                        gen_expression(rng, depth - 1),
+                        gen_expression(rng, depth - 1)
+                    ),
+                };
+                format!("{target} {op} ({})", gen_expression(rng, depth - 1))
+            }
+            14 => {
+                let elements: Vec<String> = (0..rng.below(3))
+                    .map(|_| gen_expression(rng, depth - 1))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            15 => {
+                let arms: Vec<String> = (0..rng.below(3))
+                    .map(|_| {
+                        let guard = match rng.below(2) {
+                            0 => String::new(),
+                            _ => format!(" if ({})", gen_expression(rng, depth - 1)),
+                        };
+                        format!(
+                            "{}{guard} => ({})",
+                            gen_pattern(rng, 1, &mut 0),
+                            gen_expression(rng, depth - 1)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "match ({}) {{ {} }}",
+                    gen_expression(rng, depth - 1),
+                    arms.join(", ")
+                )
+            }
+            16 => {
+                let op = ["..", "..="][rng.below(2) as usize];
+                format!(
+                    "({}){op}({})",
+                    gen_expression(rng, depth - 1),
+                    gen_expression(rng, depth - 1)
+                )
+            }
+            17 => {
+                let start = match rng.below(2) {
+                    0 => String::new(),
+                    _ => format!("({})", gen_expression(rng, depth - 1)),
+                };
+                format!("({})[{start}..]", gen_expression(rng, depth - 1))
+            }
+            18 => format!("macro(x, y) {{ {} }}", gen_statement(rng, depth - 1)),
+            _ => format!(
+                "({})[{}]",
+                gen_expression(rng, depth - 1),
+                gen_expression(rng, depth - 1)
+            ),
+        }
+    }
+
+    // Patterns may not bind a name twice, so every binding gets a fresh
+    // name. Identifiers cannot contain digits, hence the letter suffix.
+    fn fresh_name(names: &mut u8) -> String {
+        *names += 1;
+        format!("v{}", (b'a' + *names % 26) as char)
+    }
+
+    fn gen_pattern(rng: &mut Rng, depth: u32, names: &mut u8) -> String {
+        let choice = if depth == 0 {
+            rng.below(3)
+        } else {
+            rng.below(5)
+        };
+        match choice {
+            0 => fresh_name(names),
+            1 => "_".to_string(),
+            2 => ["1", "-2", "\"s\"", "true"][rng.below(4) as usize].to_string(),
+            3 => {
+                let mut elements: Vec<String> = (0..rng.below(3))
+                    .map(|_| gen_pattern(rng, depth - 1, names))
+                    .collect();
+                if rng.below(2) == 0 {
+                    elements.push(format!("...{}", fresh_name(names)));
+                }
+                format!("[{}]", elements.join(", "))
+            }
+            _ => {
+                let fields: Vec<String> = (0..rng.below(3))
+                    .map(|_| match rng.below(2) {
+                        0 => fresh_name(names),
+                        _ => format!("k: {}", gen_pattern(rng, depth - 1, names)),
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+        }
+    }
+
+    fn gen_doc(rng: &mut Rng) -> &'static str {
+        ["", "", "/// doc\n", "/// two\n///\n/// lines\n"][rng.below(4) as usize]
+    }
+
+    // A type after `marker`, or nothing.
+    fn gen_annotation(rng: &mut Rng, marker: &str) -> String {
+        match rng.below(2) {
+            0 => String::new(),
+            _ => format!("{marker}{}", gen_type(rng, 2)),
+        }
+    }
+
+    fn gen_type(rng: &mut Rng, depth: u32) -> String {
+        let choice = if depth == 0 { 0 } else { rng.below(4) };
+        match choice {
+            0 => ["int", "bool", "string", "null", "range"][rng.below(5) as usize].to_string(),
+            1 => format!("[{}]", gen_type(rng, depth - 1)),
+            2 => format!(
+                "{{{}: {}}}",
+                gen_type(rng, depth - 1),
+                gen_type(rng, depth - 1)
+            ),
+            _ => {
+                let params: Vec<String> = (0..rng.below(3))
+                    .map(|_| gen_type(rng, depth - 1))
+                    .collect();
+                format!("fn({}) -> {}", params.join(", "), gen_type(rng, depth - 1))
+            }
+        }
+    }
+
+    fn gen_statement(rng: &mut Rng, depth: u32) -> String {
+        match rng.below(3) {
+            0 => {
+                // A bare literal is not accepted on the left of a `let`.
+                let pattern = match gen_pattern(rng, 2, &mut 0) {
+                    p if p.starts_with(['1', '-', '"', 't']) => "_".to_string(),
+                    p => p,
+                };
+                format!(
+                    "{}let {pattern}{} = {};",
+                    gen_doc(rng),
+                    gen_annotation(rng, ": "),
+                    gen_expression(rng, depth)
+                )
+            }
+            1 => format!("return {};", gen_expression(rng, depth)),
+            _ => format!("{};", gen_expression(rng, depth)),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..500 {
+            let input: Vec<String> = (0..3).map(|_| gen_statement(&mut rng, 4)).collect();
+            let input = input.join("\n");
+
+            let first = parse(&input);
+            let printed = first.to_string();
+            let second = parse(&printed);
+
+            assert_eq!(
+                first, second,
+                "round trip changed the AST of {input:?}, printed as {printed:?}"
+            );
+            assert_eq!(printed, second.to_string());
+        }
+    }
+}
@@ -0,0 +1,1733 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::{
+    walk_expression, walk_statement, AssignExpression, CallExpression, Expression, ForExpression,
+    FunctionLiteral, Identifier, MatchArm, MatchExpression, Pattern, Program, Statement,
+    TypeAnnotation, Visitor, WhileExpression,
+};
+use crate::token::Span;
+use crate::typecheck::Typing;
+
+// The core language the surface AST lowers to, small enough for both an
+// interpreter and a compiler to target: literals, variables, a
+// non-recursive `let`, lambdas, application, `if` and primitives. All the
+// surface sugar (blocks, assignment, loops, jumps, patterns, parameters,
+// `match`) is spelled out in terms of these.
+//
+// The lowering relies on a few conventions:
+//
+// - Every surface function is a lambda of two parameters, the array of
+//   positional arguments and the hash of named ones, and every call passes
+//   both. `bind-args` matches them against the parameters.
+// - Names that may change or be bound late live in cells. Each scope (the
+//   program, a function body, a loop iteration, a match arm) creates a cell
+//   up front for every name it declares with `let`, so functions can refer
+//   to each other before they are defined. A cell read before it is set
+//   falls back to the binding it shadows.
+// - `return`, `break` and `continue` throw to a `catch` with the matching
+//   tag.
+// - A value with a type annotation is checked when it is bound or returned,
+//   with `type` and, for the elements of arrays and hashes, `each`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Core {
+    Literal(Literal),
+    Var(String),
+    // `name` is bound to `value` in `body` only.
+    Let {
+        name: String,
+        value: Box<Core>,
+        body: Box<Core>,
+    },
+    Lambda {
+        params: Vec<String>,
+        body: Box<Core>,
+        doc: Option<String>,
+    },
+    Apply {
+        function: Box<Core>,
+        arguments: Vec<Core>,
+    },
+    // Only `false` and `null` are falsy.
+    If {
+        condition: Box<Core>,
+        consequence: Box<Core>,
+        alternative: Box<Core>,
+    },
+    // Arguments are evaluated left to right before the primitive runs.
+    Prim {
+        op: Prim,
+        arguments: Vec<Core>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Null,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prim {
+    // The surface operators, with the surface semantics.
+    Negate,
+    Not,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    Greater,
+    Equal,
+    NotEqual,
+    // array(elements...), hash(key, value, ...) and range(start, end,
+    // inclusive), where a missing bound is null.
+    Array,
+    Hash,
+    Range,
+    // index(collection, index) and set-index(collection, index, value),
+    // which evaluates to the value.
+    Index,
+    SetIndex,
+    // length(array), has-key(hash, key), type(value) gives the type name as
+    // a string and inspect(value) the printed value.
+    Length,
+    HasKey,
+    Type,
+    Inspect,
+    // cell(name) makes an unset cell. define(cell, value) sets it,
+    // assign(cell, value) updates it and fails on an unset cell, and both
+    // evaluate to the value. get(cell) reads it; an unset cell reads as the
+    // builtin of the same name, or fails with `identifier not found: name`.
+    Cell,
+    Define,
+    Assign,
+    Get,
+    IsDefined,
+    // bind-args(args, named, required, has_rest, names...) binds the
+    // arguments of a call to the parameters `names`, the first `required`
+    // of which have no default, and fails as described on FunctionLiteral.
+    // It evaluates to an array holding each parameter's value, or a missing
+    // marker where the default applies, followed by the rest array if
+    // `has_rest`.
+    BindArguments,
+    IsMissing,
+    // loop(thunk) calls the thunk until it returns false, each(iterable, f)
+    // calls `f` with every element a `for` loop iterates over. Both evaluate
+    // to null.
+    Loop,
+    Each,
+    // catch(tag, thunk) calls the thunk and evaluates to the value of a
+    // throw(tag, value) that escapes it, if any. fail(message) raises a
+    // runtime error.
+    Catch,
+    Throw,
+    Fail,
+}
+
+impl Prim {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Prim::Negate => "neg",
+            Prim::Not => "not",
+            Prim::Add => "+",
+            Prim::Subtract => "-",
+            Prim::Multiply => "*",
+            Prim::Divide => "/",
+            Prim::Less => "<",
+            Prim::Greater => ">",
+            Prim::Equal => "==",
+            Prim::NotEqual => "!=",
+            Prim::Array => "array",
+            Prim::Hash => "hash",
+            Prim::Range => "range",
+            Prim::Index => "index",
+            Prim::SetIndex => "set-index",
+            Prim::Length => "length",
+            Prim::HasKey => "has-key",
+            Prim::Type => "type",
+            Prim::Inspect => "inspect",
+            Prim::Cell => "cell",
+            Prim::Define => "define",
+            Prim::Assign => "assign",
+            Prim::Get => "get",
+            Prim::IsDefined => "defined?",
+            Prim::BindArguments => "bind-args",
+            Prim::IsMissing => "missing?",
+            Prim::Loop => "loop",
+            Prim::Each => "each",
+            Prim::Catch => "catch",
+            Prim::Throw => "throw",
+            Prim::Fail => "fail",
+        }
+    }
+}
+
+impl fmt::Display for Core {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Core::Literal(literal) => write!(f, "{literal}"),
+            Core::Var(name) => write!(f, "{name}"),
+            Core::Let { name, value, body } => write!(f, "(let {name} {value} {body})"),
+            Core::Lambda { params, body, .. } => {
+                write!(f, "(lambda ({}) {body})", params.join(" "))
+            }
+            Core::Apply {
+                function,
+                arguments,
+            } => write_list(f, &format!("apply {function}"), arguments),
+            Core::If {
+                condition,
+                consequence,
+                alternative,
+            } => write!(f, "(if {condition} {consequence} {alternative})"),
+            Core::Prim { op, arguments } => write_list(f, op.name(), arguments),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, head: &str, items: &[Core]) -> fmt::Result {
+    write!(f, "({head}")?;
+    for item in items {
+        write!(f, " {item}")?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{value}"),
+            Literal::Boolean(value) => write!(f, "{value}"),
+            Literal::String(value) => write!(f, "{value:?}"),
+            Literal::Null => write!(f, "null"),
+        }
+    }
+}
+
+// Lower a parsed program, after macro expansion, to core. The result
+// evaluates to the value of the program. A macro literal left in the program
+// lowers to a runtime error.
+pub fn lower(program: &Program) -> Core {
+    lower_checking(program, HashSet::new())
+}
+
+// Lower a program that type checked as `typing`, leaving out the runtime
+// checks of the annotations it proved.
+pub fn lower_typed(program: &Program, typing: &Typing) -> Core {
+    lower_checking(program, typing.proven.clone())
+}
+
+fn lower_checking(program: &Program, proven: HashSet<Span>) -> Core {
+    let mut assigned = Assigned::default();
+    assigned.visit_program(program);
+    let mut lowering = Lowering {
+        scopes: Vec::new(),
+        assigned: assigned.names,
+        proven,
+        temps: 0,
+    };
+
+    lowering.push_scope();
+    for name in declared_names(&program.statements) {
+        lowering.declare(&name, true);
+    }
+    let mut body = lowering.lower_statements(&program.statements);
+    if jumps(&program.statements).returns {
+        body = catch("return", body);
+    }
+    let scope = lowering.pop_scope();
+    with_cells(&scope, body)
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    // The variable holding the value, or the cell when `cell` is set. It is
+    // the surface name unless that would hide an outer binding the lowered
+    // code still needs to reach.
+    core: String,
+    cell: bool,
+}
+
+struct Lowering {
+    scopes: Vec<Vec<Binding>>,
+    // Every name assigned to anywhere in the program. Bindings of these names
+    // are always cells.
+    assigned: HashSet<String>,
+    // The spans of the annotations that need no runtime check.
+    proven: HashSet<Span>,
+    temps: usize,
+}
+
+// What a pattern does when the value does not fit: fail with a message, as
+// in a `let`, or evaluate some other code, as in a `match` arm.
+enum Mismatch {
+    Fail,
+    Jump(Core),
+}
+
+impl Mismatch {
+    fn otherwise(&self, message: Core) -> Core {
+        match self {
+            Mismatch::Fail => fail(message),
+            Mismatch::Jump(next) => next.clone(),
+        }
+    }
+}
+
+impl Lowering {
+    // A fresh name that cannot clash with a Monkey identifier.
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("%{}", self.temps)
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) -> Vec<Binding> {
+        self.scopes.pop().expect("no scope to pop")
+    }
+
+    // The innermost binding of `name` in the scopes below `depth`, with the
+    // index of its scope.
+    fn lookup(&self, name: &str, depth: usize) -> Option<(usize, &Binding)> {
+        self.scopes[..depth]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| scope.iter().find(|b| b.name == name).map(|b| (i, b)))
+    }
+
+    // Bind `name` in the innermost scope, unless it already is.
+    fn declare(&mut self, name: &str, cell: bool) -> Binding {
+        let depth = self.scopes.len();
+        if let Some((i, binding)) = self.lookup(name, depth) {
+            if i == depth - 1 {
+                return binding.clone();
+            }
+        }
+        let core = match self.lookup(name, depth) {
+            Some(_) => format!("{name}.{depth}"),
+            None => name.to_string(),
+        };
+        let binding = Binding {
+            name: name.to_string(),
+            core,
+            cell,
+        };
+        self.scopes[depth - 1].push(binding.clone());
+        binding
+    }
+
+    // Bind a parameter, loop variable or pattern variable, which needs a cell
+    // only if it is assigned to.
+    fn declare_variable(&mut self, name: &str) -> Binding {
+        let cell = self.assigned.contains(name);
+        self.declare(name, cell)
+    }
+
+    fn local(&self, name: &str) -> Binding {
+        match self.lookup(name, self.scopes.len()) {
+            Some((i, binding)) if i == self.scopes.len() - 1 => binding.clone(),
+            _ => panic!("{name} is not declared in the current scope"),
+        }
+    }
+
+    // Give `binding` the value of `value` for the code in `body`.
+    fn bind(&self, binding: &Binding, value: Core, body: Core) -> Core {
+        if binding.cell {
+            seq(prim(Prim::Define, vec![var(&binding.core), value]), body)
+        } else {
+            let_in(&binding.core, value, body)
+        }
+    }
+
+    fn read(&self, name: &str) -> Core {
+        self.read_below(name, self.scopes.len())
+    }
+
+    fn read_below(&self, name: &str, depth: usize) -> Core {
+        match self.lookup(name, depth) {
+            None => var(name),
+            Some((_, binding)) if !binding.cell => var(&binding.core),
+            Some((i, binding)) => {
+                let get = prim(Prim::Get, vec![var(&binding.core)]);
+                if self.lookup(name, i).is_none() {
+                    return get;
+                }
+                if_(
+                    prim(Prim::IsDefined, vec![var(&binding.core)]),
+                    get,
+                    self.read_below(name, i),
+                )
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: Core) -> Core {
+        let depth = self.scopes.len();
+        match self.lookup(name, depth) {
+            None => seq(
+                value,
+                fail(string(&format!("cannot assign to undeclared name {name}"))),
+            ),
+            Some((i, _)) if self.lookup(name, i).is_none() => self.assign_below(name, depth, value),
+            Some(_) => {
+                let temp = self.temp();
+                let_in(&temp, value, self.assign_below(name, depth, var(&temp)))
+            }
+        }
+    }
+
+    // Every binding of an assigned name is a cell. If the innermost one is
+    // still unset the assignment goes to the one it shadows.
+    fn assign_below(&self, name: &str, depth: usize, value: Core) -> Core {
+        let Some((i, binding)) = self.lookup(name, depth) else {
+            return fail(string(&format!("cannot assign to undeclared name {name}")));
+        };
+        let assign = prim(Prim::Assign, vec![var(&binding.core), value.clone()]);
+        if self.lookup(name, i).is_none() {
+            return assign;
+        }
+        if_(
+            prim(Prim::IsDefined, vec![var(&binding.core)]),
+            assign,
+            self.assign_below(name, i, value),
+        )
+    }
+
+    // The value of a block is the value of its last statement, or null when
+    // there is none or it is a `let`.
+    fn lower_statements(&mut self, statements: &[Statement]) -> Core {
+        let mut lowered: Vec<Core> = statements
+            .iter()
+            .map(|stmt| self.lower_statement(stmt))
+            .collect();
+        if matches!(statements.last(), None | Some(Statement::Let(_))) {
+            lowered.push(null());
+        }
+        let last = lowered.pop().unwrap();
+        lowered
+            .into_iter()
+            .rev()
+            .fold(last, |rest, stmt| seq(stmt, rest))
+    }
+
+    fn lower_statement(&mut self, stmt: &Statement) -> Core {
+        match stmt {
+            Statement::Let(s) => {
+                let mut value = self.lower_expression(&s.value);
+                if let Some(annotation) = &s.annotation {
+                    if !self.proven.contains(&s.token.span) {
+                        let what = match &s.pattern {
+                            Pattern::Identifier(name) => name.value.as_str(),
+                            _ => "the value",
+                        };
+                        value = self.checked(value, annotation, what);
+                    }
+                }
+                match &s.pattern {
+                    Pattern::Identifier(name) => {
+                        let binding = self.local(&name.value);
+                        prim(Prim::Define, vec![var(&binding.core), value])
+                    }
+                    pattern => {
+                        let temp = self.temp();
+                        let destructure =
+                            self.lower_pattern(pattern, &temp, &Mismatch::Fail, null());
+                        let_in(&temp, value, destructure)
+                    }
+                }
+            }
+            Statement::Return(s) => {
+                let value = self.lower_expression(&s.return_value);
+                prim(Prim::Throw, vec![string("return"), value])
+            }
+            Statement::Expression(s) => self.lower_expression(&s.expression),
+            Statement::Break(_) => prim(Prim::Throw, vec![string("break"), null()]),
+            Statement::Continue(_) => prim(Prim::Throw, vec![string("continue"), null()]),
+        }
+    }
+
+    fn lower_expression(&mut self, expr: &Expression) -> Core {
+        match expr {
+            Expression::Identifier(e) => self.read(&e.value),
+            Expression::Integer(e) => int(e.value),
+            Expression::Boolean(e) => boolean(e.value),
+            Expression::String(e) => string(&e.value),
+            Expression::Prefix(e) => {
+                let op = match e.operator.as_str() {
+                    "-" => Prim::Negate,
+                    "!" => Prim::Not,
+                    op => unreachable!("unknown prefix operator {op}"),
+                };
+                prim(op, vec![self.lower_expression(&e.right)])
+            }
+            Expression::Infix(e) => {
+                let left = self.lower_expression(&e.left);
+                let right = self.lower_expression(&e.right);
+                prim(infix_prim(&e.operator), vec![left, right])
+            }
+            Expression::If(e) => {
+                let condition = self.lower_expression(&e.condition);
+                let consequence = self.lower_statements(&e.consequence.statements);
+                let alternative = match &e.alternative {
+                    Some(alt) => self.lower_statements(&alt.statements),
+                    None => null(),
+                };
+                if_(condition, consequence, alternative)
+            }
+            Expression::Function(e) => self.lower_function(e),
+            Expression::Macro(_) => fail(string("cannot evaluate a macro literal")),
+            Expression::Call(e) => self.lower_call(e),
+            Expression::Array(e) => {
+                let elements = e
+                    .elements
+                    .iter()
+                    .map(|el| self.lower_expression(el))
+                    .collect();
+                prim(Prim::Array, elements)
+            }
+            Expression::Index(e) => {
+                let left = self.lower_expression(&e.left);
+                let index = self.lower_expression(&e.index);
+                prim(Prim::Index, vec![left, index])
+            }
+            Expression::Hash(e) => {
+                let mut pairs = Vec::new();
+                for (key, value) in &e.pairs {
+                    pairs.push(self.lower_expression(key));
+                    pairs.push(self.lower_expression(value));
+                }
+                prim(Prim::Hash, pairs)
+            }
+            Expression::While(e) => self.lower_while(e),
+            Expression::For(e) => self.lower_for(e),
+            Expression::Assign(e) => self.lower_assign(e),
+            Expression::Match(e) => self.lower_match(e),
+            Expression::Range(e) => {
+                let start = match &e.start {
+                    Some(start) => self.lower_expression(start),
+                    None => null(),
+                };
+                let end = match &e.end {
+                    Some(end) => self.lower_expression(end),
+                    None => null(),
+                };
+                prim(Prim::Range, vec![start, end, boolean(e.inclusive)])
+            }
+        }
+    }
+
+    // `fn(a, b = 1, ...rest) { body }` becomes
+    //
+    //   (lambda (%args %named)
+    //     (let %bound (bind-args %args %named 1 true "a" "b")
+    //       (let a (index %bound 0)
+    //         (let b <the bound value, or 1 if missing>
+    //           (let rest (index %bound 2) body)))))
+    fn lower_function(&mut self, func: &FunctionLiteral) -> Core {
+        let args = self.temp();
+        let named = self.temp();
+        let bound = self.temp();
+
+        self.push_scope();
+        let mut declared = Declared::default();
+        for param in &func.parameters {
+            if let Some(default) = &param.default {
+                declared.visit_expression(default);
+            }
+        }
+        for stmt in &func.body.statements {
+            declared.visit_statement(stmt);
+        }
+        for name in declared.names {
+            self.declare(&name, true);
+        }
+        let mut params = Vec::new();
+        for (i, param) in func.parameters.iter().enumerate() {
+            let slot = prim(Prim::Index, vec![var(&bound), int(i as i64)]);
+            let value = match &param.default {
+                None => slot,
+                Some(default) => {
+                    let default = self.lower_expression(default);
+                    let temp = self.temp();
+                    let missing = prim(Prim::IsMissing, vec![var(&temp)]);
+                    let_in(&temp, slot, if_(missing, default, var(&temp)))
+                }
+            };
+            let value = match &param.annotation {
+                Some(annotation) => {
+                    let what = format!("parameter {}", param.name.value);
+                    self.checked(value, annotation, &what)
+                }
+                None => value,
+            };
+            params.push((self.declare_variable(&param.name.value), value));
+        }
+        if let Some(rest) = &func.rest {
+            let slot = int(func.parameters.len() as i64);
+            let value = prim(Prim::Index, vec![var(&bound), slot]);
+            params.push((self.declare_variable(&rest.value), value));
+        }
+
+        let mut body = self.lower_statements(&func.body.statements);
+        if jumps(&func.body.statements).returns {
+            body = catch("return", body);
+        }
+        if let Some(result) = &func.result {
+            if !self.proven.contains(&func.token.span) {
+                body = self.checked(body, result, "the result");
+            }
+        }
+        for (binding, value) in params.into_iter().rev() {
+            body = self.bind(&binding, value, body);
+        }
+        let scope = self.pop_scope();
+        let body = with_cells(&scope, body);
+
+        let required = func
+            .parameters
+            .iter()
+            .take_while(|p| p.default.is_none())
+            .count();
+        let mut bind_args = vec![
+            var(&args),
+            var(&named),
+            int(required as i64),
+            boolean(func.rest.is_some()),
+        ];
+        bind_args.extend(func.parameters.iter().map(|p| string(&p.name.value)));
+
+        Core::Lambda {
+            params: vec![args, named],
+            body: Box::new(let_in(&bound, prim(Prim::BindArguments, bind_args), body)),
+            doc: func.doc.clone(),
+        }
+    }
+
+    fn lower_call(&mut self, call: &CallExpression) -> Core {
+        let function = self.lower_expression(&call.function);
+        let args = call
+            .arguments
+            .iter()
+            .map(|arg| self.lower_expression(arg))
+            .collect();
+        let mut named = Vec::new();
+        for (name, value) in &call.named_arguments {
+            named.push(string(&name.value));
+            named.push(self.lower_expression(value));
+        }
+        apply(
+            function,
+            vec![prim(Prim::Array, args), prim(Prim::Hash, named)],
+        )
+    }
+
+    fn lower_while(&mut self, e: &WhileExpression) -> Core {
+        let condition = self.lower_expression(&e.condition);
+        let mut body = self.lower_statements(&e.body.statements);
+        let jumps = jumps(&e.body.statements);
+        if jumps.continues {
+            body = catch("continue", body);
+        }
+        let step = if_(condition, seq(body, boolean(true)), boolean(false));
+        let looped = prim(Prim::Loop, vec![lambda(Vec::new(), step)]);
+        if jumps.breaks {
+            catch("break", looped)
+        } else {
+            looped
+        }
+    }
+
+    fn lower_for(&mut self, e: &ForExpression) -> Core {
+        let iterable = self.lower_expression(&e.iterable);
+
+        self.push_scope();
+        for name in declared_names(&e.body.statements) {
+            self.declare(&name, true);
+        }
+        let variable = self.declare_variable(&e.variable.value);
+        let mut body = self.lower_statements(&e.body.statements);
+        let jumps = jumps(&e.body.statements);
+        if jumps.continues {
+            body = catch("continue", body);
+        }
+        let param = if variable.cell {
+            let temp = self.temp();
+            body = self.bind(&variable, var(&temp), body);
+            temp
+        } else {
+            variable.core.clone()
+        };
+        let scope = self.pop_scope();
+        let body = with_cells(&scope, body);
+
+        let looped = prim(Prim::Each, vec![iterable, lambda(vec![param], body)]);
+        if jumps.breaks {
+            catch("break", looped)
+        } else {
+            looped
+        }
+    }
+
+    // The value is evaluated before the target's current value is read, and
+    // the collection and index of an index target only once.
+    fn lower_assign(&mut self, e: &AssignExpression) -> Core {
+        let op = match e.operator.as_str() {
+            "=" => None,
+            op => Some(infix_prim(op.trim_end_matches('='))),
+        };
+        match e.target.as_ref() {
+            Expression::Identifier(name) => {
+                let value = self.lower_expression(&e.value);
+                match op {
+                    None => self.assign(&name.value, value),
+                    Some(op) => {
+                        let temp = self.temp();
+                        let updated = prim(op, vec![self.read(&name.value), var(&temp)]);
+                        let_in(&temp, value, self.assign(&name.value, updated))
+                    }
+                }
+            }
+            Expression::Index(target) => {
+                let collection = self.lower_expression(&target.left);
+                let index = self.lower_expression(&target.index);
+                let value = self.lower_expression(&e.value);
+                let Some(op) = op else {
+                    return prim(Prim::SetIndex, vec![collection, index, value]);
+                };
+                let (c, i, v) = (self.temp(), self.temp(), self.temp());
+                let current = prim(Prim::Index, vec![var(&c), var(&i)]);
+                let updated = prim(op, vec![current, var(&v)]);
+                let set = prim(Prim::SetIndex, vec![var(&c), var(&i), updated]);
+                let_in(&c, collection, let_in(&i, index, let_in(&v, value, set)))
+            }
+            target => unreachable!("invalid assignment target {target}"),
+        }
+    }
+
+    // Each arm becomes a thunk that the previous arm calls when the subject
+    // does not fit it:
+    //
+    //   (let %s subject
+    //     (let %1 (lambda () (fail "no match arm for ..."))
+    //       <first arm, calling %1 on mismatch>))
+    fn lower_match(&mut self, e: &MatchExpression) -> Core {
+        let subject = self.lower_expression(&e.subject);
+        let value = self.temp();
+        let arms: Vec<(String, Core)> = e
+            .arms
+            .iter()
+            .map(|arm| {
+                let next = self.temp();
+                let arm = self.lower_arm(arm, &value, apply(var(&next), Vec::new()));
+                (next, arm)
+            })
+            .collect();
+
+        let no_match = fail(concat(vec![
+            string("no match arm for "),
+            prim(Prim::Inspect, vec![var(&value)]),
+        ]));
+        let arms = arms
+            .into_iter()
+            .rev()
+            .fold(no_match, |otherwise, (next, arm)| {
+                let_in(&next, lambda(Vec::new(), otherwise), arm)
+            });
+        let_in(&value, subject, arms)
+    }
+
+    fn lower_arm(&mut self, arm: &MatchArm, value: &str, next: Core) -> Core {
+        self.push_scope();
+        let mut declared = Declared::default();
+        if let Some(guard) = &arm.guard {
+            declared.visit_expression(guard);
+        }
+        declared.visit_expression(&arm.body);
+        for name in declared.names {
+            self.declare(&name, true);
+        }
+        for name in bound_names(&arm.pattern) {
+            self.declare_variable(&name);
+        }
+
+        let guard = arm.guard.as_ref().map(|guard| self.lower_expression(guard));
+        let body = self.lower_expression(&arm.body);
+        let body = match guard {
+            Some(guard) => if_(guard, body, next.clone()),
+            None => body,
+        };
+        let arm = self.lower_pattern(&arm.pattern, value, &Mismatch::Jump(next), body);
+        let scope = self.pop_scope();
+        with_cells(&scope, arm)
+    }
+
+    // Match the value of the variable `value` against `pattern`, binding its
+    // names for `then`. The names must already be declared in the innermost
+    // scope.
+    fn lower_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: &str,
+        mismatch: &Mismatch,
+        then: Core,
+    ) -> Core {
+        match pattern {
+            Pattern::Identifier(name) => {
+                let binding = self.local(&name.value);
+                self.bind(&binding, var(value), then)
+            }
+            Pattern::Wildcard(_) => then,
+            Pattern::Literal(literal) => {
+                let equal = prim(
+                    Prim::Equal,
+                    vec![var(value), self.lower_expression(literal)],
+                );
+                let message = concat(vec![
+                    prim(Prim::Inspect, vec![var(value)]),
+                    string(&format!(" does not match pattern {literal}")),
+                ]);
+                if_(equal, then, mismatch.otherwise(message))
+            }
+            Pattern::Array(p) => {
+                let n = p.elements.len();
+                let temps: Vec<String> = (0..n).map(|_| self.temp()).collect();
+
+                let mut then = then;
+                if let Some(rest) = &p.rest {
+                    let binding = self.local(&rest.value);
+                    let tail = prim(Prim::Range, vec![int(n as i64), null(), boolean(false)]);
+                    then = self.bind(&binding, prim(Prim::Index, vec![var(value), tail]), then);
+                }
+                for (i, (element, temp)) in p.elements.iter().zip(&temps).enumerate().rev() {
+                    let matched = self.lower_pattern(element, temp, mismatch, then);
+                    then = let_in(
+                        temp,
+                        prim(Prim::Index, vec![var(value), int(i as i64)]),
+                        matched,
+                    );
+                }
+
+                let length = prim(Prim::Length, vec![var(value)]);
+                let checked = match p.rest {
+                    None => {
+                        let message = concat(vec![
+                            string(&format!("expected {n} elements to destructure, got ")),
+                            prim(Prim::Inspect, vec![length.clone()]),
+                        ]);
+                        let fits = prim(Prim::Equal, vec![length, int(n as i64)]);
+                        if_(fits, then, mismatch.otherwise(message))
+                    }
+                    Some(_) => {
+                        let message = concat(vec![
+                            string(&format!(
+                                "expected at least {n} elements to destructure, got "
+                            )),
+                            prim(Prim::Inspect, vec![length.clone()]),
+                        ]);
+                        let short = prim(Prim::Less, vec![length, int(n as i64)]);
+                        if_(short, mismatch.otherwise(message), then)
+                    }
+                };
+                self.check_type(value, "ARRAY", "an array", mismatch, checked)
+            }
+            Pattern::Hash(p) => {
+                let temps: Vec<String> = p.fields.iter().map(|_| self.temp()).collect();
+
+                let mut then = then;
+                for ((key, element), temp) in p.fields.iter().zip(&temps).rev() {
+                    let matched = self.lower_pattern(element, temp, mismatch, then);
+                    let field = prim(Prim::Index, vec![var(value), string(&key.value)]);
+                    let has_key = prim(Prim::HasKey, vec![var(value), string(&key.value)]);
+                    let message = string(&format!("key \"{}\" not found in hash", key.value));
+                    then = if_(
+                        has_key,
+                        let_in(temp, field, matched),
+                        mismatch.otherwise(message),
+                    );
+                }
+                self.check_type(value, "HASH", "a hash", mismatch, then)
+            }
+        }
+    }
+
+    // Evaluate `value` and fail unless it fits `annotation`, giving the
+    // value.
+    fn checked(&mut self, value: Core, annotation: &TypeAnnotation, what: &str) -> Core {
+        let temp = self.temp();
+        let check = self.check_annotation(&temp, annotation, what);
+        let_in(&temp, value, seq(check, var(&temp)))
+    }
+
+    // Fail unless the value of the variable `value` fits `annotation`,
+    // naming the value `what` in the message. Arrays and hashes are checked
+    // element by element; a function only has to be a function.
+    fn check_annotation(&mut self, value: &str, annotation: &TypeAnnotation, what: &str) -> Core {
+        let actual = prim(Prim::Type, vec![var(value)]);
+        let is = |type_name: &str| prim(Prim::Equal, vec![actual.clone(), string(type_name)]);
+        let (fits, then) = match annotation {
+            TypeAnnotation::Named(name) => {
+                let type_name = match name.value.as_str() {
+                    "int" => "INTEGER",
+                    "bool" => "BOOLEAN",
+                    "string" => "STRING",
+                    "null" => "NULL",
+                    "range" => "RANGE",
+                    name => return fail(string(&format!("unknown type {name}"))),
+                };
+                (is(type_name), null())
+            }
+            TypeAnnotation::Array(element) => {
+                let item = self.temp();
+                let check = self.check_annotation(&item, element, &format!("an element of {what}"));
+                let each = prim(Prim::Each, vec![var(value), lambda(vec![item], check)]);
+                (is("ARRAY"), each)
+            }
+            TypeAnnotation::Hash(key, element) => {
+                let (k, v) = (self.temp(), self.temp());
+                let check_key = self.check_annotation(&k, key, &format!("a key of {what}"));
+                let check_value = self.check_annotation(&v, element, &format!("a value of {what}"));
+                let field = prim(Prim::Index, vec![var(value), var(&k)]);
+                let body = seq(check_key, let_in(&v, field, check_value));
+                let each = prim(Prim::Each, vec![var(value), lambda(vec![k], body)]);
+                (is("HASH"), each)
+            }
+            TypeAnnotation::Function(..) => {
+                (if_(is("FUNCTION"), boolean(true), is("BUILTIN")), null())
+            }
+        };
+        let message = concat(vec![
+            string(&format!("expected {annotation} for {what}, got ")),
+            actual.clone(),
+        ]);
+        if_(fits, then, fail(message))
+    }
+
+    fn check_type(
+        &self,
+        value: &str,
+        type_name: &str,
+        described: &str,
+        mismatch: &Mismatch,
+        then: Core,
+    ) -> Core {
+        let actual = prim(Prim::Type, vec![var(value)]);
+        let message = concat(vec![
+            string("cannot destructure "),
+            actual.clone(),
+            string(&format!(" as {described}")),
+        ]);
+        if_(
+            prim(Prim::Equal, vec![actual, string(type_name)]),
+            then,
+            mismatch.otherwise(message),
+        )
+    }
+}
+
+fn infix_prim(operator: &str) -> Prim {
+    match operator {
+        "+" => Prim::Add,
+        "-" => Prim::Subtract,
+        "*" => Prim::Multiply,
+        "/" => Prim::Divide,
+        "<" => Prim::Less,
+        ">" => Prim::Greater,
+        "==" => Prim::Equal,
+        "!=" => Prim::NotEqual,
+        op => unreachable!("unknown infix operator {op}"),
+    }
+}
+
+// Create the cells of a scope around the code that uses them.
+fn with_cells(scope: &[Binding], body: Core) -> Core {
+    scope
+        .iter()
+        .rev()
+        .filter(|binding| binding.cell)
+        .fold(body, |body, binding| {
+            let cell = prim(Prim::Cell, vec![string(&binding.name)]);
+            let_in(&binding.core, cell, body)
+        })
+}
+
+fn var(name: &str) -> Core {
+    Core::Var(name.to_string())
+}
+
+fn int(value: i64) -> Core {
+    Core::Literal(Literal::Integer(value))
+}
+
+fn boolean(value: bool) -> Core {
+    Core::Literal(Literal::Boolean(value))
+}
+
+fn string(value: &str) -> Core {
+    Core::Literal(Literal::String(value.to_string()))
+}
+
+fn null() -> Core {
+    Core::Literal(Literal::Null)
+}
+
+fn let_in(name: &str, value: Core, body: Core) -> Core {
+    Core::Let {
+        name: name.to_string(),
+        value: Box::new(value),
+        body: Box::new(body),
+    }
+}
+
+// Evaluate `first` for its effects, then `then`.
+fn seq(first: Core, then: Core) -> Core {
+    let_in("_", first, then)
+}
+
+fn lambda(params: Vec<String>, body: Core) -> Core {
+    Core::Lambda {
+        params,
+        body: Box::new(body),
+        doc: None,
+    }
+}
+
+fn apply(function: Core, arguments: Vec<Core>) -> Core {
+    Core::Apply {
+        function: Box::new(function),
+        arguments,
+    }
+}
+
+fn if_(condition: Core, consequence: Core, alternative: Core) -> Core {
+    Core::If {
+        condition: Box::new(condition),
+        consequence: Box::new(consequence),
+        alternative: Box::new(alternative),
+    }
+}
+
+fn prim(op: Prim, arguments: Vec<Core>) -> Core {
+    Core::Prim { op, arguments }
+}
+
+fn fail(message: Core) -> Core {
+    prim(Prim::Fail, vec![message])
+}
+
+fn catch(tag: &str, body: Core) -> Core {
+    prim(Prim::Catch, vec![string(tag), lambda(Vec::new(), body)])
+}
+
+// Join strings with `+`.
+fn concat(parts: Vec<Core>) -> Core {
+    parts
+        .into_iter()
+        .reduce(|left, right| prim(Prim::Add, vec![left, right]))
+        .unwrap_or_else(|| string(""))
+}
+
+// The names `let` statements declare in the scope that runs `statements`.
+// Function bodies, loop iterations and match arms have scopes of their own.
+fn declared_names(statements: &[Statement]) -> Vec<String> {
+    let mut declared = Declared::default();
+    for stmt in statements {
+        declared.visit_statement(stmt);
+    }
+    declared.names
+}
+
+#[derive(Default)]
+struct Declared {
+    names: Vec<String>,
+}
+
+impl Visitor for Declared {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Let(s) = stmt {
+            for name in bound_names(&s.pattern) {
+                if !self.names.contains(&name) {
+                    self.names.push(name);
+                }
+            }
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Function(_) | Expression::Macro(_) => {}
+            Expression::For(e) => self.visit_expression(&e.iterable),
+            Expression::Match(e) => self.visit_expression(&e.subject),
+            _ => walk_expression(self, expr),
+        }
+    }
+}
+
+fn bound_names(pattern: &Pattern) -> Vec<String> {
+    struct Bound(Vec<String>);
+
+    impl Visitor for Bound {
+        fn visit_identifier(&mut self, ident: &Identifier) {
+            self.0.push(ident.value.clone());
+        }
+    }
+
+    let mut bound = Bound(Vec::new());
+    bound.visit_pattern(pattern);
+    bound.0
+}
+
+#[derive(Default)]
+struct Assigned {
+    names: HashSet<String>,
+}
+
+impl Visitor for Assigned {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Assign(e) = expr {
+            if let Expression::Identifier(name) = e.target.as_ref() {
+                self.names.insert(name.value.clone());
+            }
+        }
+        walk_expression(self, expr)
+    }
+}
+
+// Which jumps can leave `statements`: a `return` not inside a nested
+// function, and a `break` or `continue` not inside a nested loop either.
+// Only those need a `catch`.
+fn jumps(statements: &[Statement]) -> Jumps {
+    let mut jumps = Jumps::default();
+    for stmt in statements {
+        jumps.visit_statement(stmt);
+    }
+    jumps
+}
+
+#[derive(Default)]
+struct Jumps {
+    returns: bool,
+    breaks: bool,
+    continues: bool,
+    loop_depth: usize,
+}
+
+impl Visitor for Jumps {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Return(_) => self.returns = true,
+            Statement::Break(_) if self.loop_depth == 0 => self.breaks = true,
+            Statement::Continue(_) if self.loop_depth == 0 => self.continues = true,
+            _ => {}
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Function(_) | Expression::Macro(_) => {}
+            Expression::While(e) => {
+                self.visit_expression(&e.condition);
+                self.loop_depth += 1;
+                self.visit_block_statement(&e.body);
+                self.loop_depth -= 1;
+            }
+            Expression::For(e) => {
+                self.visit_expression(&e.iterable);
+                self.loop_depth += 1;
+                self.visit_block_statement(&e.body);
+                self.loop_depth -= 1;
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::Ast;
+    use crate::parser::tests::parse;
+    use crate::{evaluator, resolver, typecheck};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A direct interpreter for core, enough to check that lowered programs
+    // behave as the surface language is specified to.
+    #[derive(Clone)]
+    enum Value {
+        Integer(i64),
+        Boolean(bool),
+        Str(Rc<str>),
+        Null,
+        Array(Rc<RefCell<Vec<Value>>>),
+        Hash(Rc<RefCell<Vec<(Value, Value)>>>),
+        Range(Option<i64>, Option<i64>, bool),
+        Closure(Rc<Closure>),
+        Builtin(&'static str),
+        Cell(Rc<str>, Rc<RefCell<Option<Value>>>),
+        Missing,
+    }
+
+    struct Closure {
+        params: Vec<String>,
+        body: Core,
+        env: Env,
+        doc: Option<String>,
+    }
+
+    type Env = Option<Rc<Frame>>;
+
+    struct Frame {
+        name: String,
+        value: Value,
+        parent: Env,
+    }
+
+    enum Unwind {
+        Throw(String, Value),
+        Error(String),
+    }
+
+    type Result<T> = std::result::Result<T, Unwind>;
+
+    fn error<T>(message: String) -> Result<T> {
+        Err(Unwind::Error(message))
+    }
+
+    fn builtin(name: &str) -> Option<Value> {
+        match name {
+            "len" => Some(Value::Builtin("len")),
+            "doc" => Some(Value::Builtin("doc")),
+            _ => None,
+        }
+    }
+
+    fn lookup(env: &Env, name: &str) -> Option<Value> {
+        let mut env = env;
+        while let Some(frame) = env {
+            if frame.name == name {
+                return Some(frame.value.clone());
+            }
+            env = &frame.parent;
+        }
+        builtin(name)
+    }
+
+    fn extend(env: &Env, name: &str, value: Value) -> Env {
+        Some(Rc::new(Frame {
+            name: name.to_string(),
+            value,
+            parent: env.clone(),
+        }))
+    }
+
+    fn type_name(value: &Value) -> &'static str {
+        match value {
+            Value::Integer(_) => "INTEGER",
+            Value::Boolean(_) => "BOOLEAN",
+            Value::Str(_) => "STRING",
+            Value::Null => "NULL",
+            Value::Array(_) => "ARRAY",
+            Value::Hash(_) => "HASH",
+            Value::Range(..) => "RANGE",
+            Value::Closure(_) => "FUNCTION",
+            Value::Builtin(_) => "BUILTIN",
+            Value::Cell(..) => "CELL",
+            Value::Missing => "MISSING",
+        }
+    }
+
+    fn inspect(value: &Value) -> String {
+        match value {
+            Value::Integer(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Str(s) => s.to_string(),
+            Value::Null => "null".to_string(),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.borrow().iter().map(inspect).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", inspect(k), inspect(v)))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Value::Range(start, end, inclusive) => {
+                let bound = |b: &Option<i64>| b.map(|b| b.to_string()).unwrap_or_default();
+                let dots = if *inclusive { "..=" } else { ".." };
+                format!("{}{dots}{}", bound(start), bound(end))
+            }
+            Value::Closure(_) => "fn".to_string(),
+            Value::Builtin(name) => format!("builtin {name}"),
+            Value::Cell(name, _) => format!("cell {name}"),
+            Value::Missing => "missing".to_string(),
+        }
+    }
+
+    fn truthy(value: &Value) -> bool {
+        !matches!(value, Value::Null | Value::Boolean(false))
+    }
+
+    fn equal(left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Hash(a), Value::Hash(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    fn hash_key(key: &Value) -> Result<()> {
+        match key {
+            Value::Integer(_) | Value::Boolean(_) | Value::Str(_) => Ok(()),
+            other => error(format!("unusable as hash key: {}", type_name(other))),
+        }
+    }
+
+    fn hash_get(pairs: &[(Value, Value)], key: &Value) -> Option<Value> {
+        pairs
+            .iter()
+            .find(|(k, _)| equal(k, key))
+            .map(|(_, v)| v.clone())
+    }
+
+    fn hash_insert(pairs: &mut Vec<(Value, Value)>, key: Value, value: Value) {
+        match pairs.iter_mut().find(|(k, _)| equal(k, &key)) {
+            Some(pair) => pair.1 = value,
+            None => pairs.push((key, value)),
+        }
+    }
+
+    // The position of a possibly negative index in a sequence of `len`.
+    fn position(index: i64, len: usize) -> Option<usize> {
+        let index = if index < 0 { index + len as i64 } else { index };
+        (0..len as i64).contains(&index).then_some(index as usize)
+    }
+
+    fn slice_bounds(range: &Value, len: usize) -> (usize, usize) {
+        let Value::Range(start, end, inclusive) = range else {
+            unreachable!()
+        };
+        let len = len as i64;
+        let normalize = |b: i64| if b < 0 { b + len } else { b };
+        let start = normalize(start.unwrap_or(0)).clamp(0, len);
+        let end = match end {
+            Some(end) => normalize(*end) + *inclusive as i64,
+            None => len,
+        }
+        .clamp(0, len);
+        (start as usize, end.max(start) as usize)
+    }
+
+    fn range_items(value: &Value) -> Result<std::ops::Range<i64>> {
+        match value {
+            Value::Range(Some(start), Some(end), inclusive) => {
+                Ok(*start..(end + *inclusive as i64).max(*start))
+            }
+            _ => error("cannot use an open range here".to_string()),
+        }
+    }
+
+    fn eval(core: &Core, env: &Env) -> Result<Value> {
+        match core {
+            Core::Literal(literal) => Ok(match literal {
+                Literal::Integer(n) => Value::Integer(*n),
+                Literal::Boolean(b) => Value::Boolean(*b),
+                Literal::String(s) => Value::Str(s.as_str().into()),
+                Literal::Null => Value::Null,
+            }),
+            Core::Var(name) => match lookup(env, name) {
+                Some(value) => Ok(value),
+                None => error(format!("identifier not found: {name}")),
+            },
+            Core::Let { name, value, body } => {
+                let value = eval(value, env)?;
+                eval(body, &extend(env, name, value))
+            }
+            Core::Lambda { params, body, doc } => Ok(Value::Closure(Rc::new(Closure {
+                params: params.clone(),
+                body: (**body).clone(),
+                env: env.clone(),
+                doc: doc.clone(),
+            }))),
+            Core::Apply {
+                function,
+                arguments,
+            } => {
+                let function = eval(function, env)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|arg| eval(arg, env))
+                    .collect::<Result<Vec<_>>>()?;
+                call(&function, arguments)
+            }
+            Core::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                if truthy(&eval(condition, env)?) {
+                    eval(consequence, env)
+                } else {
+                    eval(alternative, env)
+                }
+            }
+            Core::Prim { op, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|arg| eval(arg, env))
+                    .collect::<Result<Vec<_>>>()?;
+                primitive(*op, arguments)
+            }
+        }
+    }
+
+    fn call(function: &Value, arguments: Vec<Value>) -> Result<Value> {
+        match function {
+            Value::Closure(closure) => {
+                assert_eq!(closure.params.len(), arguments.len(), "lambda arity");
+                let mut env = closure.env.clone();
+                for (param, arg) in closure.params.iter().zip(arguments) {
+                    env = extend(&env, param, arg);
+                }
+                eval(&closure.body, &env)
+            }
+            Value::Builtin(name) => {
+                let [Value::Array(args), Value::Hash(named)] = arguments.as_slice() else {
+                    unreachable!("builtins are called with positional and named arguments")
+                };
+                if !named.borrow().is_empty() {
+                    return error(format!("{name} does not take named arguments"));
+                }
+                let args = args.borrow();
+                let [arg] = args.as_slice() else {
+                    return error(format!(
+                        "wrong number of arguments. got={}, want=1",
+                        args.len()
+                    ));
+                };
+                match (*name, arg) {
+                    ("len", Value::Str(s)) => Ok(Value::Integer(s.chars().count() as i64)),
+                    ("len", Value::Array(a)) => Ok(Value::Integer(a.borrow().len() as i64)),
+                    ("len", Value::Hash(h)) => Ok(Value::Integer(h.borrow().len() as i64)),
+                    ("len", range @ Value::Range(..)) => {
+                        Ok(Value::Integer(range_items(range)?.count() as i64))
+                    }
+                    ("doc", Value::Closure(c)) => Ok(match &c.doc {
+                        Some(doc) => Value::Str(doc.as_str().into()),
+                        None => Value::Null,
+                    }),
+                    (name, other) => error(format!(
+                        "argument to `{name}` not supported, got {}",
+                        type_name(other)
+                    )),
+                }
+            }
+            other => error(format!("not a function: {}", type_name(other))),
+        }
+    }
+
+    fn primitive(op: Prim, args: Vec<Value>) -> Result<Value> {
+        use Value::*;
+
+        match (op, args.as_slice()) {
+            (Prim::Negate, [Integer(n)]) => Ok(Integer(n.wrapping_neg())),
+            (Prim::Negate, [other]) => error(format!("unknown operator: -{}", type_name(other))),
+            (Prim::Not, [value]) => Ok(Boolean(!truthy(value))),
+            (Prim::Equal, [a, b]) => Ok(Boolean(equal(a, b))),
+            (Prim::NotEqual, [a, b]) => Ok(Boolean(!equal(a, b))),
+            (Prim::Divide, [Integer(_), Integer(0)]) => error("division by zero".to_string()),
+            (Prim::Add, [Integer(a), Integer(b)]) => Ok(Integer(a.wrapping_add(*b))),
+            (Prim::Subtract, [Integer(a), Integer(b)]) => Ok(Integer(a.wrapping_sub(*b))),
+            (Prim::Multiply, [Integer(a), Integer(b)]) => Ok(Integer(a.wrapping_mul(*b))),
+            (Prim::Divide, [Integer(a), Integer(b)]) => Ok(Integer(a.wrapping_div(*b))),
+            (Prim::Less, [Integer(a), Integer(b)]) => Ok(Boolean(a < b)),
+            (Prim::Greater, [Integer(a), Integer(b)]) => Ok(Boolean(a > b)),
+            (Prim::Add, [Str(a), Str(b)]) => Ok(Str(format!("{a}{b}").into())),
+            (
+                Prim::Add
+                | Prim::Subtract
+                | Prim::Multiply
+                | Prim::Divide
+                | Prim::Less
+                | Prim::Greater,
+                [a, b],
+            ) => {
+                let problem = if type_name(a) == type_name(b) {
+                    "unknown operator"
+                } else {
+                    "type mismatch"
+                };
+                error(format!(
+                    "{problem}: {} {} {}",
+                    type_name(a),
+                    op.name(),
+                    type_name(b)
+                ))
+            }
+            (Prim::Array, elements) => Ok(Array(Rc::new(RefCell::new(elements.to_vec())))),
+            (Prim::Hash, flat) => {
+                let mut pairs = Vec::new();
+                for pair in flat.chunks(2) {
+                    hash_key(&pair[0])?;
+                    hash_insert(&mut pairs, pair[0].clone(), pair[1].clone());
+                }
+                Ok(Hash(Rc::new(RefCell::new(pairs))))
+            }
+            (Prim::Range, [start, end, Boolean(inclusive)]) => {
+                let bound = |b: &Value| match b {
+                    Integer(n) => Ok(Some(*n)),
+                    Null => Ok(None),
+                    other => error(format!(
+                        "range bounds must be integers, got {}",
+                        type_name(other)
+                    )),
+                };
+                Ok(Range(bound(start)?, bound(end)?, *inclusive))
+            }
+            (Prim::Index, [Array(elements), Integer(i)]) => {
+                let elements = elements.borrow();
+                Ok(position(*i, elements.len()).map_or(Null, |i| elements[i].clone()))
+            }
+            (Prim::Index, [Str(s), Integer(i)]) => {
+                let chars: Vec<char> = s.chars().collect();
+                Ok(position(*i, chars.len()).map_or(Null, |i| Str(chars[i].to_string().into())))
+            }
+            (Prim::Index, [Array(elements), range @ Range(..)]) => {
+                let elements = elements.borrow();
+                let (start, end) = slice_bounds(range, elements.len());
+                Ok(Array(Rc::new(RefCell::new(elements[start..end].to_vec()))))
+            }
+            (Prim::Index, [Str(s), range @ Range(..)]) => {
+                let chars: Vec<char> = s.chars().collect();
+                let (start, end) = slice_bounds(range, chars.len());
+                Ok(Str(chars[start..end].iter().collect::<String>().into()))
+            }
+            (Prim::Index, [Hash(pairs), key]) => {
+                hash_key(key)?;
+                Ok(hash_get(&pairs.borrow(), key).unwrap_or(Null))
+            }
+            (Prim::Index, [other, _]) => error(format!(
+                "index operator not supported: {}",
+                type_name(other)
+            )),
+            (Prim::SetIndex, [Array(elements), Integer(i), value]) => {
+                let mut elements = elements.borrow_mut();
+                let len = elements.len();
+                match position(*i, len) {
+                    Some(i) => elements[i] = value.clone(),
+                    None => return error(format!("index {i} out of range for length {len}")),
+                }
+                Ok(value.clone())
+            }
+            (Prim::SetIndex, [Hash(pairs), key, value]) => {
+                hash_key(key)?;
+                hash_insert(&mut pairs.borrow_mut(), key.clone(), value.clone());
+                Ok(value.clone())
+            }
+            (Prim::SetIndex, [other, _, _]) => error(format!(
+                "index assignment not supported: {}",
+                type_name(other)
+            )),
+            (Prim::Length, [Array(elements)]) => Ok(Integer(elements.borrow().len() as i64)),
+            (Prim::HasKey, [Hash(pairs), key]) => {
+                Ok(Boolean(hash_get(&pairs.borrow(), key).is_some()))
+            }
+            (Prim::Type, [value]) => Ok(Str(type_name(value).into())),
+            (Prim::Inspect, [value]) => Ok(Str(inspect(value).into())),
+            (Prim::Cell, [Str(name)]) => Ok(Cell(name.clone(), Rc::new(RefCell::new(None)))),
+            (Prim::Define, [Cell(_, cell), value]) => {
+                *cell.borrow_mut() = Some(value.clone());
+                Ok(value.clone())
+            }
+            (Prim::Assign, [Cell(name, cell), value]) => {
+                if cell.borrow().is_none() {
+                    return error(format!("cannot assign to undeclared name {name}"));
+                }
+                *cell.borrow_mut() = Some(value.clone());
+                Ok(value.clone())
+            }
+            (Prim::Get, [Cell(name, cell)]) => {
+                match cell.borrow().clone().or_else(|| builtin(name)) {
+                    Some(value) => Ok(value),
+                    None => error(format!("identifier not found: {name}")),
+                }
+            }
+            (Prim::IsDefined, [Cell(_, cell)]) => Ok(Boolean(cell.borrow().is_some())),
+            (
+                Prim::BindArguments,
+                [Array(args), Hash(named), Integer(required), Boolean(has_rest), names @ ..],
+            ) => {
+                let args = args.borrow();
+                let names: Vec<String> = names.iter().map(inspect).collect();
+                if !has_rest && args.len() > names.len() {
+                    return error(format!(
+                        "too many arguments: expected at most {}, got {}",
+                        names.len(),
+                        args.len()
+                    ));
+                }
+                let mut slots: Vec<Value> = names.iter().map(|_| Missing).collect();
+                for (slot, arg) in slots.iter_mut().zip(args.iter()) {
+                    *slot = arg.clone();
+                }
+                for (name, value) in named.borrow().iter() {
+                    let name = inspect(name);
+                    let Some(i) = names.iter().position(|n| *n == name) else {
+                        return error(format!("unknown parameter {name}"));
+                    };
+                    if !matches!(slots[i], Missing) {
+                        return error(format!("argument {name} given twice"));
+                    }
+                    slots[i] = value.clone();
+                }
+                for (slot, name) in slots.iter().zip(&names).take(*required as usize) {
+                    if matches!(slot, Missing) {
+                        return error(format!("missing argument for parameter {name}"));
+                    }
+                }
+                if *has_rest {
+                    let rest = args.iter().skip(names.len()).cloned().collect();
+                    slots.push(Array(Rc::new(RefCell::new(rest))));
+                }
+                Ok(Array(Rc::new(RefCell::new(slots))))
+            }
+            (Prim::IsMissing, [value]) => Ok(Boolean(matches!(value, Missing))),
+            (Prim::Loop, [thunk]) => {
+                while truthy(&call(thunk, Vec::new())?) {}
+                Ok(Null)
+            }
+            (Prim::Each, [iterable, f]) => {
+                let items: Vec<Value> = match iterable {
+                    Array(elements) => elements.borrow().clone(),
+                    Str(s) => s.chars().map(|c| Str(c.to_string().into())).collect(),
+                    Hash(pairs) => pairs.borrow().iter().map(|(k, _)| k.clone()).collect(),
+                    range @ Range(..) => range_items(range)?.map(Integer).collect(),
+                    other => return error(format!("cannot iterate over {}", type_name(other))),
+                };
+                for item in items {
+                    call(f, vec![item])?;
+                }
+                Ok(Null)
+            }
+            (Prim::Catch, [Str(tag), thunk]) => match call(thunk, Vec::new()) {
+                Err(Unwind::Throw(thrown, value)) if thrown == **tag => Ok(value),
+                result => result,
+            },
+            (Prim::Throw, [Str(tag), value]) => Err(Unwind::Throw(tag.to_string(), value.clone())),
+            (Prim::Fail, [Str(message)]) => error(message.to_string()),
+            (op, args) => panic!(
+                "bad arguments to {}: {:?}",
+                op.name(),
+                args.iter().map(type_name).collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    fn run(input: &str) -> std::result::Result<String, String> {
+        match eval(&lower(&parse(input)), &None) {
+            Ok(value) => Ok(inspect(&value)),
+            Err(Unwind::Error(message)) => Err(message),
+            Err(Unwind::Throw(tag, _)) => panic!("uncaught {tag} in {input}"),
+        }
+    }
+
+    #[test]
+    fn test_lower() {
+        let tests = vec![
+            ("1 + 2 * -3", "(+ 1 (* 2 (neg 3)))"),
+            ("if (x) { 1 }", "(if x 1 null)"),
+            (
+                "let x = 1; x",
+                r#"(let x (cell "x") (let _ (define x 1) (get x)))"#,
+            ),
+            (
+                "f(1, b: 2)[0..]",
+                r#"(index (apply f (array 1) (hash "b" 2)) (range 0 null false))"#,
+            ),
+            (
+                "fn(a, b = a) { a + b }",
+                r#"(lambda (%1 %2) (let %3 (bind-args %1 %2 1 false "a" "b") (let a (index %3 0) (let b (let %4 (index %3 1) (if (missing? %4) a %4)) (+ a b)))))"#,
+            ),
+            (
+                "fn(...r) { return r }",
+                r#"(lambda (%1 %2) (let %3 (bind-args %1 %2 0 true) (let r (index %3 0) (catch "return" (lambda () (throw "return" r))))))"#,
+            ),
+            (
+                "while (x) { break }",
+                r#"(catch "break" (lambda () (loop (lambda () (if x (let _ (throw "break" null) true) false)))))"#,
+            ),
+            ("for (c in s) { c }", "(each s (lambda (c) c))"),
+            (
+                "let [a, _] = p",
+                r#"(let a (cell "a") (let _ (let %1 p (if (== (type %1) "ARRAY") (if (== (length %1) 2) (let %2 (index %1 0) (let _ (define a %2) (let %3 (index %1 1) null))) (fail (+ "expected 2 elements to destructure, got " (inspect (length %1))))) (fail (+ (+ "cannot destructure " (type %1)) " as an array")))) null))"#,
+            ),
+            (
+                "match x { 1 => 2 }",
+                r#"(let %1 x (let %2 (lambda () (fail (+ "no match arm for " (inspect %1)))) (if (== %1 1) 2 (apply %2))))"#,
+            ),
+            // An assigned name lives in a cell; an unset inner cell falls
+            // back to the binding it shadows.
+            (
+                "let x = 1; fn() { x = 2; let x = 3; }",
+                r#"(let x (cell "x") (let _ (define x 1) (lambda (%1 %2) (let %3 (bind-args %1 %2 0 false) (let x.2 (cell "x") (let _ (let %4 2 (if (defined? x.2) (assign x.2 %4) (assign x %4))) (let _ (define x.2 3) null)))))))"#,
+            ),
+            // An annotation nobody proved is checked, element by element.
+            (
+                "let xs: [int] = ys;",
+                r#"(let xs (cell "xs") (let _ (define xs (let %1 ys (let _ (if (== (type %1) "ARRAY") (each %1 (lambda (%2) (if (== (type %2) "INTEGER") null (fail (+ "expected int for an element of xs, got " (type %2)))))) (fail (+ "expected [int] for xs, got " (type %1)))) %1))) null))"#,
+            ),
+            // The collection and the index are evaluated once, before the
+            // value.
+            (
+                "a[i] += 1",
+                "(let %1 a (let %2 i (let %3 1 (set-index %1 %2 (+ (index %1 %2) %3)))))",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(lower(&parse(input)).to_string(), expected, "input: {input}");
+        }
+    }
+
+    // Every program of the evaluator's tests gives the same value or the
+    // same error once lowered.
+    #[test]
+    fn test_semantics_preserved() {
+        for (input, _) in evaluator::tests::PROGRAMS {
+            let env = Rc::new(RefCell::new(evaluator::Environment::new()));
+            let expected = match evaluator::eval(&parse(input), &env) {
+                evaluator::Object::Error(message) => Err(message),
+                value => Ok(value.to_string()),
+            };
+            assert_eq!(run(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_proven_annotations_unchecked() {
+        let program = parse("let x: int = 1; let y: int = x; fn(n: int) -> int { n }");
+        let ast = Ast::from(&program);
+        let typing = typecheck::check(&ast, &resolver::resolve(&ast));
+        assert_eq!(
+            lower_typed(&program, &typing).to_string(),
+            r#"(let x (cell "x") (let y (cell "y") (let _ (define x 1) (let _ (define y (get x)) (lambda (%1 %2) (let %3 (bind-args %1 %2 1 false "n") (let n (let %4 (index %3 0) (let _ (if (== (type %4) "INTEGER") null (fail (+ "expected int for parameter n, got " (type %4)))) %4)) n)))))))"#
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use crate::ast::{Expression, Pattern, Program, Statement};
+
+// Render the documentation of a program as Markdown: one section for each
+// top-level `let` that binds a function or carries a doc comment, in source
+// order. Functions are headed by their signature.
+pub fn to_markdown(program: &Program) -> String {
+    let sections: Vec<String> = program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Let(s) => match &s.pattern {
+                Pattern::Identifier(name) => Some((name, &s.value, &s.doc)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter_map(|(name, value, doc)| {
+            let heading = match value {
+                Expression::Function(func) => format!("## `{name}({})`", func.signature()),
+                _ if doc.is_some() => format!("## `{name}`"),
+                _ => return None,
+            };
+            // A literal's own doc comment wins over the one on the `let`.
+            let doc = match value {
+                Expression::Function(func) => func.doc.as_ref().or(doc.as_ref()),
+                _ => doc.as_ref(),
+            };
+            Some(match doc {
+                Some(doc) => format!("{heading}\n\n{doc}\n"),
+                None => format!("{heading}\n"),
+            })
+        })
+        .collect();
+
+    sections.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    #[test]
+    fn test_to_markdown() {
+        let program = parse(
+            "/// Adds two numbers.
+             ///
+             /// Works on integers.
+             let add = fn(a, b = 1, ...more) { a + b };
+             /// The answer.
+             let answer = 42;
+             let hidden = 1;
+             let undocumented = fn() {};
+             let [x, y] = pair;
+             let inner = fn() {
+               /// Not at the top level.
+               let helper = fn() {};
+             };",
+        );
+
+        assert_eq!(
+            to_markdown(&program),
+            "## `add(a, b = 1, ...more)`
+
+Adds two numbers.
+
+Works on integers.
+
+## `answer`
+
+The answer.
+
+## `undocumented()`
+
+## `inner()`
+"
+        );
+        assert_eq!(to_markdown(&parse("1 + 2")), "");
+    }
+}
@@ -0,0 +1,805 @@
+use std::fmt::{self, Write};
+
+use crate::ast::{
+    BlockStatement, Expression, Identifier, MatchArm, Parameter, Pattern, Program, Statement,
+    TypeAnnotation,
+};
+use crate::token::Span;
+
+// Render the program as JSON. Every node is an object with a `kind` and,
+// when the node owns a token, the `span` of that token. With `pretty` the
+// output is indented by two spaces per level.
+pub fn to_json(program: &Program, pretty: bool) -> String {
+    let value = Json::Object(vec![
+        ("kind".to_string(), Json::String("Program".to_string())),
+        (
+            "statements".to_string(),
+            Json::Array(program.statements.iter().map(statement_json).collect()),
+        ),
+    ]);
+
+    if pretty {
+        format!("{value:#}")
+    } else {
+        format!("{value}")
+    }
+}
+
+// Render the program as S-expressions, one statement per line, e.g.
+// `(let x (+ 1 (* 2 3)))`.
+pub fn to_sexpr(program: &Program) -> String {
+    let statements: Vec<String> = program.statements.iter().map(statement_sexpr).collect();
+    statements.join("\n")
+}
+
+// Minimal JSON value, enough to describe the AST without a dependency.
+enum Json {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn write(&self, f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_json_string(f, s),
+            Json::Array(items) => {
+                if items.is_empty() {
+                    return write!(f, "[]");
+                }
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_newline(f, indent.map(|n| n + 1))?;
+                    item.write(f, indent.map(|n| n + 1))?;
+                }
+                write_newline(f, indent)?;
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                if fields.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_newline(f, indent.map(|n| n + 1))?;
+                    write_json_string(f, key)?;
+                    write!(f, ":")?;
+                    if indent.is_some() {
+                        write!(f, " ")?;
+                    }
+                    value.write(f, indent.map(|n| n + 1))?;
+                }
+                write_newline(f, indent)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = if f.alternate() { Some(0) } else { None };
+        self.write(f, indent)
+    }
+}
+
+fn write_newline(f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+    match indent {
+        Some(n) => write!(f, "\n{:width$}", "", width = n * 2),
+        None => Ok(()),
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+fn node(kind: &str, span: Option<Span>, fields: Vec<(&str, Json)>) -> Json {
+    let mut object = vec![("kind".to_string(), Json::String(kind.to_string()))];
+    if let Some(span) = span {
+        object.push((
+            "span".to_string(),
+            Json::Object(vec![
+                ("start".to_string(), Json::Number(span.start as i64)),
+                ("end".to_string(), Json::Number(span.end as i64)),
+                ("line".to_string(), Json::Number(span.line as i64)),
+                ("column".to_string(), Json::Number(span.column as i64)),
+            ]),
+        ));
+    }
+    object.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+    Json::Object(object)
+}
+
+fn statement_json(stmt: &Statement) -> Json {
+    match stmt {
+        Statement::Let(s) => {
+            let mut fields = vec![("pattern", pattern_json(&s.pattern))];
+            if let Some(annotation) = &s.annotation {
+                fields.push(("type", type_json(annotation)));
+            }
+            fields.push(("value", expression_json(&s.value)));
+            if let Some(doc) = &s.doc {
+                fields.push(("doc", Json::String(doc.clone())));
+            }
+            node("LetStatement", Some(s.token.span), fields)
+        }
+        Statement::Return(s) => node(
+            "ReturnStatement",
+            Some(s.token.span),
+            vec![("value", expression_json(&s.return_value))],
+        ),
+        Statement::Expression(s) => node(
+            "ExpressionStatement",
+            None,
+            vec![("expression", expression_json(&s.expression))],
+        ),
+        Statement::Break(s) => node("BreakStatement", Some(s.token.span), vec![]),
+        Statement::Continue(s) => node("ContinueStatement", Some(s.token.span), vec![]),
+    }
+}
+
+fn block_json(block: &BlockStatement) -> Json {
+    node(
+        "BlockStatement",
+        Some(block.token.span),
+        vec![(
+            "statements",
+            Json::Array(block.statements.iter().map(statement_json).collect()),
+        )],
+    )
+}
+
+fn identifier_json(ident: &Identifier) -> Json {
+    node(
+        "Identifier",
+        Some(ident.token.span),
+        vec![("value", Json::String(ident.value.clone()))],
+    )
+}
+
+fn pattern_json(pattern: &Pattern) -> Json {
+    match pattern {
+        Pattern::Identifier(p) => identifier_json(p),
+        Pattern::Wildcard(p) => node("WildcardPattern", Some(p.token.span), vec![]),
+        Pattern::Literal(p) => expression_json(p),
+        Pattern::Array(p) => {
+            let mut fields = vec![(
+                "elements",
+                Json::Array(p.elements.iter().map(pattern_json).collect()),
+            )];
+            if let Some(rest) = &p.rest {
+                fields.push(("rest", identifier_json(rest)));
+            }
+            node("ArrayPattern", Some(p.token.span), fields)
+        }
+        Pattern::Hash(p) => node(
+            "HashPattern",
+            Some(p.token.span),
+            vec![(
+                "fields",
+                Json::Array(
+                    p.fields
+                        .iter()
+                        .map(|(k, v)| {
+                            Json::Object(vec![
+                                ("key".to_string(), identifier_json(k)),
+                                ("value".to_string(), pattern_json(v)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            )],
+        ),
+    }
+}
+
+fn expression_json(expr: &Expression) -> Json {
+    match expr {
+        Expression::Identifier(e) => identifier_json(e),
+        Expression::Integer(e) => node(
+            "IntegerLiteral",
+            Some(e.token.span),
+            vec![("value", Json::Number(e.value))],
+        ),
+        Expression::Boolean(e) => node(
+            "Boolean",
+            Some(e.token.span),
+            vec![("value", Json::Bool(e.value))],
+        ),
+        Expression::String(e) => node(
+            "StringLiteral",
+            Some(e.token.span),
+            vec![("value", Json::String(e.value.clone()))],
+        ),
+        Expression::Prefix(e) => node(
+            "PrefixExpression",
+            Some(e.token.span),
+            vec![
+                ("operator", Json::String(e.operator.clone())),
+                ("right", expression_json(&e.right)),
+            ],
+        ),
+        Expression::Infix(e) => node(
+            "InfixExpression",
+            Some(e.token.span),
+            vec![
+                ("operator", Json::String(e.operator.clone())),
+                ("left", expression_json(&e.left)),
+                ("right", expression_json(&e.right)),
+            ],
+        ),
+        Expression::If(e) => {
+            let mut fields = vec![
+                ("condition", expression_json(&e.condition)),
+                ("consequence", block_json(&e.consequence)),
+            ];
+            if let Some(alt) = &e.alternative {
+                fields.push(("alternative", block_json(alt)));
+            }
+            node("IfExpression", Some(e.token.span), fields)
+        }
+        Expression::Function(e) => {
+            let mut fields = vec![(
+                "parameters",
+                Json::Array(e.parameters.iter().map(parameter_json).collect()),
+            )];
+            if let Some(rest) = &e.rest {
+                fields.push(("rest", identifier_json(rest)));
+            }
+            if let Some(result) = &e.result {
+                fields.push(("result", type_json(result)));
+            }
+            fields.push(("body", block_json(&e.body)));
+            if let Some(doc) = &e.doc {
+                fields.push(("doc", Json::String(doc.clone())));
+            }
+            node("FunctionLiteral", Some(e.token.span), fields)
+        }
+        Expression::Macro(e) => node(
+            "MacroLiteral",
+            Some(e.token.span),
+            vec![
+                (
+                    "parameters",
+                    Json::Array(e.parameters.iter().map(identifier_json).collect()),
+                ),
+                ("body", block_json(&e.body)),
+            ],
+        ),
+        Expression::Call(e) => {
+            let mut fields = vec![
+                ("function", expression_json(&e.function)),
+                (
+                    "arguments",
+                    Json::Array(e.arguments.iter().map(expression_json).collect()),
+                ),
+            ];
+            if !e.named_arguments.is_empty() {
+                fields.push((
+                    "named_arguments",
+                    Json::Array(
+                        e.named_arguments
+                            .iter()
+                            .map(|(name, value)| {
+                                Json::Object(vec![
+                                    ("name".to_string(), identifier_json(name)),
+                                    ("value".to_string(), expression_json(value)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ));
+            }
+            node("CallExpression", Some(e.token.span), fields)
+        }
+        Expression::Array(e) => node(
+            "ArrayLiteral",
+            Some(e.token.span),
+            vec![(
+                "elements",
+                Json::Array(e.elements.iter().map(expression_json).collect()),
+            )],
+        ),
+        Expression::Index(e) => node(
+            "IndexExpression",
+            Some(e.token.span),
+            vec![
+                ("left", expression_json(&e.left)),
+                ("index", expression_json(&e.index)),
+            ],
+        ),
+        Expression::Hash(e) => node(
+            "HashLiteral",
+            Some(e.token.span),
+            vec![(
+                "pairs",
+                Json::Array(
+                    e.pairs
+                        .iter()
+                        .map(|(k, v)| {
+                            Json::Object(vec![
+                                ("key".to_string(), expression_json(k)),
+                                ("value".to_string(), expression_json(v)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            )],
+        ),
+        Expression::While(e) => node(
+            "WhileExpression",
+            Some(e.token.span),
+            vec![
+                ("condition", expression_json(&e.condition)),
+                ("body", block_json(&e.body)),
+            ],
+        ),
+        Expression::For(e) => node(
+            "ForExpression",
+            Some(e.token.span),
+            vec![
+                ("variable", identifier_json(&e.variable)),
+                ("iterable", expression_json(&e.iterable)),
+                ("body", block_json(&e.body)),
+            ],
+        ),
+        Expression::Assign(e) => node(
+            "AssignExpression",
+            Some(e.token.span),
+            vec![
+                ("operator", Json::String(e.operator.clone())),
+                ("target", expression_json(&e.target)),
+                ("value", expression_json(&e.value)),
+            ],
+        ),
+        Expression::Range(e) => {
+            let mut fields = vec![("inclusive", Json::Bool(e.inclusive))];
+            if let Some(start) = &e.start {
+                fields.push(("start", expression_json(start)));
+            }
+            if let Some(end) = &e.end {
+                fields.push(("end", expression_json(end)));
+            }
+            node("RangeExpression", Some(e.token.span), fields)
+        }
+        Expression::Match(e) => node(
+            "MatchExpression",
+            Some(e.token.span),
+            vec![
+                ("subject", expression_json(&e.subject)),
+                ("arms", Json::Array(e.arms.iter().map(arm_json).collect())),
+            ],
+        ),
+    }
+}
+
+fn parameter_json(param: &Parameter) -> Json {
+    let mut fields = vec![("name", identifier_json(&param.name))];
+    if let Some(annotation) = &param.annotation {
+        fields.push(("type", type_json(annotation)));
+    }
+    if let Some(default) = &param.default {
+        fields.push(("default", expression_json(default)));
+    }
+    node("Parameter", None, fields)
+}
+
+fn type_json(annotation: &TypeAnnotation) -> Json {
+    match annotation {
+        TypeAnnotation::Named(name) => node(
+            "NamedType",
+            Some(name.token.span),
+            vec![("name", Json::String(name.value.clone()))],
+        ),
+        TypeAnnotation::Array(element) => {
+            node("ArrayType", None, vec![("element", type_json(element))])
+        }
+        TypeAnnotation::Hash(key, value) => node(
+            "HashType",
+            None,
+            vec![("key", type_json(key)), ("value", type_json(value))],
+        ),
+        TypeAnnotation::Function(params, result) => node(
+            "FunctionType",
+            None,
+            vec![
+                (
+                    "parameters",
+                    Json::Array(params.iter().map(type_json).collect()),
+                ),
+                ("result", type_json(result)),
+            ],
+        ),
+    }
+}
+
+fn arm_json(arm: &MatchArm) -> Json {
+    let mut fields = vec![("pattern".to_string(), pattern_json(&arm.pattern))];
+    if let Some(guard) = &arm.guard {
+        fields.push(("guard".to_string(), expression_json(guard)));
+    }
+    fields.push(("body".to_string(), expression_json(&arm.body)));
+    Json::Object(fields)
+}
+
+fn list(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("({})", items.join(" "))
+}
+
+fn statement_sexpr(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Let(s) => list(
+            [
+                "let".to_string(),
+                annotated_sexpr(pattern_sexpr(&s.pattern), &s.annotation),
+                expression_sexpr(&s.value),
+            ]
+            .into_iter()
+            .chain(doc_sexpr(&s.doc)),
+        ),
+        Statement::Return(s) => list(["return".to_string(), expression_sexpr(&s.return_value)]),
+        Statement::Expression(s) => expression_sexpr(&s.expression),
+        Statement::Break(_) => "(break)".to_string(),
+        Statement::Continue(_) => "(continue)".to_string(),
+    }
+}
+
+// Patterns mirror the literals they take apart: `(array a (rest r))` and
+// `(hash (key binding))`.
+fn pattern_sexpr(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Identifier(p) => p.value.clone(),
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Literal(p) => expression_sexpr(p),
+        Pattern::Array(p) => list(
+            std::iter::once("array".to_string())
+                .chain(p.elements.iter().map(pattern_sexpr))
+                .chain(
+                    p.rest
+                        .iter()
+                        .map(|rest| list(["rest".to_string(), rest.value.clone()])),
+                ),
+        ),
+        Pattern::Hash(p) => list(
+            std::iter::once("hash".to_string()).chain(
+                p.fields
+                    .iter()
+                    .map(|(k, v)| list([k.value.clone(), pattern_sexpr(v)])),
+            ),
+        ),
+    }
+}
+
+fn block_sexpr(block: &BlockStatement) -> String {
+    list(std::iter::once("block".to_string()).chain(block.statements.iter().map(statement_sexpr)))
+}
+
+fn expression_sexpr(expr: &Expression) -> String {
+    match expr {
+        Expression::Identifier(e) => e.value.clone(),
+        Expression::Integer(e) => e.value.to_string(),
+        Expression::Boolean(e) => e.value.to_string(),
+        Expression::String(e) => e.to_string(),
+        Expression::Prefix(e) => list([e.operator.clone(), expression_sexpr(&e.right)]),
+        Expression::Infix(e) => list([
+            e.operator.clone(),
+            expression_sexpr(&e.left),
+            expression_sexpr(&e.right),
+        ]),
+        Expression::If(e) => {
+            let mut items = vec![
+                "if".to_string(),
+                expression_sexpr(&e.condition),
+                block_sexpr(&e.consequence),
+            ];
+            if let Some(alt) = &e.alternative {
+                items.push(block_sexpr(alt));
+            }
+            list(items)
+        }
+        Expression::Function(e) => {
+            let params = e.parameters.iter().map(|p| {
+                let name = annotated_sexpr(p.name.value.clone(), &p.annotation);
+                match &p.default {
+                    Some(default) => list(["=".to_string(), name, expression_sexpr(default)]),
+                    None => name,
+                }
+            });
+            let rest = e
+                .rest
+                .iter()
+                .map(|rest| list(["rest".to_string(), rest.value.clone()]));
+            let result = e
+                .result
+                .iter()
+                .map(|result| list(["->".to_string(), type_sexpr(result)]));
+            list(
+                ["fn".to_string(), list(params.chain(rest))]
+                    .into_iter()
+                    .chain(result)
+                    .chain([block_sexpr(&e.body)])
+                    .chain(doc_sexpr(&e.doc)),
+            )
+        }
+        Expression::Macro(e) => list([
+            "macro".to_string(),
+            list(e.parameters.iter().map(|p| p.value.clone())),
+            block_sexpr(&e.body),
+        ]),
+        Expression::Call(e) => list(
+            ["call".to_string(), expression_sexpr(&e.function)]
+                .into_iter()
+                .chain(e.arguments.iter().map(expression_sexpr))
+                .chain(e.named_arguments.iter().map(|(name, value)| {
+                    list([":".to_string(), name.value.clone(), expression_sexpr(value)])
+                })),
+        ),
+        Expression::Array(e) => list(
+            std::iter::once("array".to_string()).chain(e.elements.iter().map(expression_sexpr)),
+        ),
+        Expression::Index(e) => list([
+            "index".to_string(),
+            expression_sexpr(&e.left),
+            expression_sexpr(&e.index),
+        ]),
+        Expression::Hash(e) => list(
+            std::iter::once("hash".to_string()).chain(
+                e.pairs
+                    .iter()
+                    .map(|(k, v)| list([expression_sexpr(k), expression_sexpr(v)])),
+            ),
+        ),
+        Expression::While(e) => list([
+            "while".to_string(),
+            expression_sexpr(&e.condition),
+            block_sexpr(&e.body),
+        ]),
+        Expression::For(e) => list([
+            "for".to_string(),
+            e.variable.value.clone(),
+            expression_sexpr(&e.iterable),
+            block_sexpr(&e.body),
+        ]),
+        Expression::Assign(e) => list([
+            e.operator.clone(),
+            expression_sexpr(&e.target),
+            expression_sexpr(&e.value),
+        ]),
+        // A missing bound is written as `_`.
+        Expression::Range(e) => {
+            let bound = |b: &Option<Box<Expression>>| match b {
+                Some(b) => expression_sexpr(b),
+                None => "_".to_string(),
+            };
+            let op = if e.inclusive { "..=" } else { ".." };
+            list([op.to_string(), bound(&e.start), bound(&e.end)])
+        }
+        Expression::Match(e) => list(
+            ["match".to_string(), expression_sexpr(&e.subject)]
+                .into_iter()
+                .chain(e.arms.iter().map(arm_sexpr)),
+        ),
+    }
+}
+
+// A binding with a type is written `(: binding type)`.
+fn annotated_sexpr(binding: String, annotation: &Option<TypeAnnotation>) -> String {
+    match annotation {
+        Some(annotation) => list([":".to_string(), binding, type_sexpr(annotation)]),
+        None => binding,
+    }
+}
+
+fn type_sexpr(annotation: &TypeAnnotation) -> String {
+    match annotation {
+        TypeAnnotation::Named(name) => name.value.clone(),
+        TypeAnnotation::Array(element) => list(["array".to_string(), type_sexpr(element)]),
+        TypeAnnotation::Hash(key, value) => {
+            list(["hash".to_string(), type_sexpr(key), type_sexpr(value)])
+        }
+        TypeAnnotation::Function(params, result) => list([
+            "fn".to_string(),
+            list(params.iter().map(type_sexpr)),
+            type_sexpr(result),
+        ]),
+    }
+}
+
+// A doc comment goes last, as `(doc "text")`.
+fn doc_sexpr(doc: &Option<String>) -> Option<String> {
+    doc.as_ref()
+        .map(|doc| list(["doc".to_string(), format!("{doc:?}")]))
+}
+
+// `(=> pattern body)`, with `(if guard)` between the two when guarded.
+fn arm_sexpr(arm: &MatchArm) -> String {
+    let mut items = vec!["=>".to_string(), pattern_sexpr(&arm.pattern)];
+    if let Some(guard) = &arm.guard {
+        items.push(list(["if".to_string(), expression_sexpr(guard)]));
+    }
+    items.push(expression_sexpr(&arm.body));
+    list(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    #[test]
+    fn test_sexpr() {
+        let tests = vec![
+            ("let x = 1 + 2 * 3;", "(let x (+ 1 (* 2 3)))"),
+            ("return -a;", "(return (- a))"),
+            ("!true == false", "(== (! true) false)"),
+            (
+                "if (x < y) { x } else { y; z }",
+                "(if (< x y) (block x) (block y z))",
+            ),
+            ("if (x) {}", "(if x (block))"),
+            (
+                "let add = fn(a, b) { a + b };",
+                "(let add (fn (a b) (block (+ a b))))",
+            ),
+            ("fn() { 1 }()", "(call (fn () (block 1)))"),
+            ("add(1, f(2))", "(call add 1 (call f 2))"),
+            ("a; b", "a\nb"),
+            ("[1, x + 2][0]", "(index (array 1 (+ x 2)) 0)"),
+            ("m[i][j]", "(index (index m i) j)"),
+            (
+                r#"{"a": 1, b: [2], 3: true}"#,
+                r#"(hash ("a" 1) (b (array 2)) (3 true))"#,
+            ),
+            (
+                "while (x < 10) { if (x == 5) { break; } continue; }",
+                "(while (< x 10) (block (if (== x 5) (block (break))) (continue)))",
+            ),
+            (
+                "for (x in [1, 2]) { f(x) }",
+                "(for x (array 1 2) (block (call f x)))",
+            ),
+            ("a[i] += b = 1", "(+= (index a i) (= b 1))"),
+            ("xs[1..n + 1]", "(index xs (.. 1 (+ n 1)))"),
+            ("s[..=2][1..]", "(index (index s (..= _ 2)) (.. 1 _))"),
+            (
+                "fn(a, b = 2, ...c) {}(1, b: 3)",
+                "(call (fn (a (= b 2) (rest c)) (block)) 1 (: b 3))",
+            ),
+            (
+                "let f: fn(int) -> [int] = fn(a: int, b: {string: bool} = {}) -> [int] { [a] }",
+                "(let (: f (fn (int) (array int))) (fn ((: a int) (= (: b (hash string bool)) (hash))) (-> (array int)) (block (array a))))",
+            ),
+            (
+                "let [a, {b, c: [d]}, ...e] = f;",
+                "(let (array a (hash (b b) (c (array d))) (rest e)) f)",
+            ),
+            (
+                "match x { -1 => a, [_, y] if y => y, _ => b }",
+                "(match x (=> (- 1) a) (=> (array _ y) (if y) y) (=> _ b))",
+            ),
+            (
+                "/// Adds \"a\"\n/// and b\nlet add = fn(a, b) { a + b };",
+                r#"(let add (fn (a b) (block (+ a b)) (doc "Adds \"a\"\nand b")) (doc "Adds \"a\"\nand b"))"#,
+            ),
+            (
+                "let m = macro(a, b) { quote(unquote(b)) }",
+                "(let m (macro (a b) (block (call quote (call unquote b)))))",
+            ),
+            (
+                "map(xs, /// Doubles\nfn(x) { x * 2 })",
+                r#"(call map xs (fn (x) (block (* x 2)) (doc "Doubles")))"#,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(to_sexpr(&parse(input)), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let program = parse("let x = -5;\nf(x);");
+
+        assert_eq!(
+            to_json(&program, false),
+            concat!(
+                r#"{"kind":"Program","statements":["#,
+                r#"{"kind":"LetStatement","span":{"start":0,"end":3,"line":1,"column":1},"#,
+                r#""pattern":{"kind":"Identifier","span":{"start":4,"end":5,"line":1,"column":5},"value":"x"},"#,
+                r#""value":{"kind":"PrefixExpression","span":{"start":8,"end":9,"line":1,"column":9},"operator":"-","#,
+                r#""right":{"kind":"IntegerLiteral","span":{"start":9,"end":10,"line":1,"column":10},"value":5}}},"#,
+                r#"{"kind":"ExpressionStatement","expression":"#,
+                r#"{"kind":"CallExpression","span":{"start":13,"end":14,"line":2,"column":2},"#,
+                r#""function":{"kind":"Identifier","span":{"start":12,"end":13,"line":2,"column":1},"value":"f"},"#,
+                r#""arguments":[{"kind":"Identifier","span":{"start":14,"end":15,"line":2,"column":3},"value":"x"}]}}"#,
+                "]}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_doc() {
+        let program = parse("/// The answer\nlet x = 42;");
+
+        assert_eq!(
+            to_json(&program, false),
+            concat!(
+                r#"{"kind":"Program","statements":["#,
+                r#"{"kind":"LetStatement","span":{"start":15,"end":18,"line":2,"column":1},"#,
+                r#""pattern":{"kind":"Identifier","span":{"start":19,"end":20,"line":2,"column":5},"value":"x"},"#,
+                r#""value":{"kind":"IntegerLiteral","span":{"start":23,"end":25,"line":2,"column":9},"value":42},"#,
+                r#""doc":"The answer"}"#,
+                "]}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_pretty() {
+        let program = parse("fn() {}");
+
+        assert_eq!(
+            to_json(&program, true),
+            r#"{
+  "kind": "Program",
+  "statements": [
+    {
+      "kind": "ExpressionStatement",
+      "expression": {
+        "kind": "FunctionLiteral",
+        "span": {
+          "start": 0,
+          "end": 2,
+          "line": 1,
+          "column": 1
+        },
+        "parameters": [],
+        "body": {
+          "kind": "BlockStatement",
+          "span": {
+            "start": 5,
+            "end": 6,
+            "line": 1,
+            "column": 6
+          },
+          "statements": []
+        }
+      }
+    }
+  ]
+}"#
+        );
+    }
+
+    #[test]
+    fn test_json_string_escaping() {
+        assert_eq!(
+            Json::String("a \"b\"\\\n\u{1}".to_string()).to_string(),
+            r#""a \"b\"\\\n\u0001""#
+        );
+    }
+}
@@ -0,0 +1,2467 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use lazy_static::lazy_static;
+
+use crate::ast::{
+    self, fold_expression, AssignExpression, BlockStatement, CallExpression, Expression, Fold,
+    ForExpression, FunctionLiteral, LetStatement, MacroLiteral, MatchExpression, Pattern, Program,
+    Statement, TypeAnnotation, WhileExpression,
+};
+use crate::token::{Span, Token, TokenType};
+
+// A runtime value. `ReturnValue`, `Break`, `Continue` and `Error` never end
+// up in a binding: they carry the value of a `return`, a jump out of a loop
+// iteration or a runtime error out of the statements they leave.
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Null,
+    ReturnValue(Box<Object>),
+    Break,
+    Continue,
+    Error(String),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    // Arrays and hashes are shared, so that assigning to an element is seen
+    // through every binding of the collection. A hash keeps its keys in the
+    // order they were first inserted.
+    Array(Rc<RefCell<Vec<Object>>>),
+    Hash(Rc<RefCell<Vec<(Object, Object)>>>),
+    // The start, the end and whether the end is included. Only a slice
+    // index leaves out a bound.
+    Range(Option<i64>, Option<i64>, bool),
+    // The code given to `quote`.
+    Quote(Box<Expression>),
+}
+
+// A function literal with the scope it was evaluated in, which its body
+// sees at every call.
+pub struct Function {
+    pub literal: FunctionLiteral,
+    pub env: Env,
+}
+
+// The scope is left out: it usually holds the function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("literal", &self.literal)
+            .finish_non_exhaustive()
+    }
+}
+
+// A function the evaluator provides, called with the positional arguments
+// of a call. Builtins take no named arguments.
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub function: fn(Vec<Object>) -> Object,
+}
+
+lazy_static! {
+    // Looked up when no binding of the name is in scope, so a program can
+    // shadow them.
+    pub static ref BUILTINS: HashMap<&'static str, Builtin> = {
+        let mut map = HashMap::new();
+        map.insert("doc", Builtin { name: "doc", function: builtin_doc });
+        map.insert("first", Builtin { name: "first", function: builtin_first });
+        map.insert("last", Builtin { name: "last", function: builtin_last });
+        map.insert("len", Builtin { name: "len", function: builtin_len });
+        map.insert("push", Builtin { name: "push", function: builtin_push });
+        map.insert("puts", Builtin { name: "puts", function: builtin_puts });
+        map.insert("rest", Builtin { name: "rest", function: builtin_rest });
+        map
+    };
+}
+
+// How many calls of Monkey functions may be running at once.
+const MAX_CALL_DEPTH: usize = 5_000;
+
+// The stack a thread needs to evaluate MAX_CALL_DEPTH nested calls. A call
+// takes up to about 150KB of it in a debug build, when its body nests as
+// deeply as the parser allows; most take less than 10KB.
+pub const STACK_SIZE: usize = 1 << 30;
+
+thread_local! {
+    // The calls of Monkey functions running on this thread.
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+impl Object {
+    // The name runtime errors use for the type of the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Break => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Error(_) => "ERROR",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Range(..) => "RANGE",
+            Object::Quote(_) => "QUOTE",
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        matches!(self, Object::Error(_))
+    }
+
+    // Whether the value stops the evaluation of the code around it: a jump
+    // or a runtime error.
+    fn is_abrupt(&self) -> bool {
+        matches!(
+            self,
+            Object::ReturnValue(_) | Object::Break | Object::Continue | Object::Error(_)
+        )
+    }
+}
+
+// Strings print without quotes.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{value}"),
+            Object::Boolean(value) => write!(f, "{value}"),
+            Object::String(value) => write!(f, "{value}"),
+            Object::Null => write!(f, "null"),
+            Object::ReturnValue(value) => write!(f, "{value}"),
+            Object::Break => write!(f, "break"),
+            Object::Continue => write!(f, "continue"),
+            Object::Error(message) => write!(f, "ERROR: {message}"),
+            Object::Function(function) => write!(f, "{}", function.literal),
+            Object::Builtin(builtin) => write!(f, "builtin {}", builtin.name),
+            Object::Array(elements) => {
+                let elements: Vec<String> =
+                    elements.borrow().iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| format!("{key}: {value}"))
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Object::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    write!(f, "{start}")?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{end}")?;
+                }
+                Ok(())
+            }
+            Object::Quote(code) => write!(f, "QUOTE({code})"),
+        }
+    }
+}
+
+pub type Env = Rc<RefCell<Environment>>;
+
+// The bindings of one scope: the program or a function call. Names not
+// bound in it are looked up in the scope around it, `outer`, which for a
+// call is the scope the function was defined in.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    pub fn new_enclosed(outer: Env) -> Environment {
+        Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match (self.store.get(name), &self.outer) {
+            (Some(value), _) => Some(value.clone()),
+            (None, Some(outer)) => outer.borrow().get(name),
+            (None, None) => None,
+        }
+    }
+
+    // The names bound in this scope.
+    pub fn names(&self) -> Vec<String> {
+        self.store.keys().cloned().collect()
+    }
+
+    // Bind `name` in this scope.
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(name.to_string(), value);
+    }
+
+    // Update the nearest binding of `name`, returning false when there is
+    // none.
+    pub fn assign(&mut self, name: &str, value: Object) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
+
+// Evaluate `program`, after macro expansion, to the value of its last
+// statement, or to the value of the first `return` or runtime error that
+// stops it. A program that ends in a `let` evaluates to null.
+pub fn eval(program: &Program, env: &Env) -> Object {
+    let mut result = Object::Null;
+    for stmt in &program.statements {
+        result = eval_statement(stmt, env);
+        match result {
+            Object::ReturnValue(value) => return *value,
+            Object::Error(_) => return result,
+            Object::Break | Object::Continue => return outside_loop(&result),
+            _ => {}
+        }
+    }
+    result
+}
+
+// Evaluate the body of the macro `m` for a call with `arguments`, binding
+// each parameter to its argument as quoted code. A macro sees no bindings
+// of the program, only the builtins. The result is the code to expand the
+// call to when it is a quote.
+pub fn eval_macro_body(m: &MacroLiteral, arguments: &[Expression]) -> Object {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    for (param, arg) in m.parameters.iter().zip(arguments) {
+        let code = Object::Quote(Box::new(arg.clone()));
+        env.borrow_mut().set(&param.value, code);
+    }
+    match eval_block(&m.body, &env) {
+        Object::ReturnValue(value) => *value,
+        jump @ (Object::Break | Object::Continue) => outside_loop(&jump),
+        result => result,
+    }
+}
+
+// Unlike a program, a block passes a `return`, `break` or `continue` on, so
+// that the blocks around it stop too, up to the function or loop it leaves.
+fn eval_block(block: &BlockStatement, env: &Env) -> Object {
+    let mut result = Object::Null;
+    for stmt in &block.statements {
+        result = eval_statement(stmt, env);
+        if result.is_abrupt() {
+            return result;
+        }
+    }
+    result
+}
+
+// The parser only accepts `break` and `continue` in a loop body, but a tree
+// built some other way may still hold one elsewhere.
+fn outside_loop(jump: &Object) -> Object {
+    Object::Error(format!("{jump} outside of a loop"))
+}
+
+fn eval_statement(stmt: &Statement, env: &Env) -> Object {
+    match stmt {
+        Statement::Let(s) => eval_let(s, env),
+        Statement::Return(s) => {
+            let value = eval_expression(&s.return_value, env);
+            if value.is_abrupt() {
+                return value;
+            }
+            Object::ReturnValue(Box::new(value))
+        }
+        Statement::Expression(s) => eval_expression(&s.expression, env),
+        Statement::Break(_) => Object::Break,
+        Statement::Continue(_) => Object::Continue,
+    }
+}
+
+fn eval_let(s: &LetStatement, env: &Env) -> Object {
+    let value = eval_expression(&s.value, env);
+    if value.is_abrupt() {
+        return value;
+    }
+    if let Some(annotation) = &s.annotation {
+        let what = match &s.pattern {
+            Pattern::Identifier(name) => name.value.as_str(),
+            _ => "the value",
+        };
+        if let Some(error) = check_annotation(&value, annotation, what) {
+            return error;
+        }
+    }
+    match bind_pattern(&s.pattern, value, env) {
+        Ok(()) => Object::Null,
+        Err(message) => Object::Error(message),
+    }
+}
+
+// Bind the names of `pattern` to the parts of `value` in `env`, or give the
+// message, as listed on Pattern, for the first part that does not fit.
+// Names bound before the mismatch stay bound.
+fn bind_pattern(pattern: &Pattern, value: Object, env: &Env) -> Result<(), String> {
+    match pattern {
+        Pattern::Identifier(name) => {
+            env.borrow_mut().set(&name.value, value);
+            Ok(())
+        }
+        Pattern::Wildcard(_) => Ok(()),
+        Pattern::Literal(literal) => match equal(&value, &eval_expression(literal, env)) {
+            true => Ok(()),
+            false => Err(format!("{value} does not match pattern {literal}")),
+        },
+        Pattern::Array(p) => {
+            let Object::Array(elements) = &value else {
+                return Err(format!(
+                    "cannot destructure {} as an array",
+                    value.type_name()
+                ));
+            };
+            let mut elements = elements.borrow().clone();
+            let (n, len) = (p.elements.len(), elements.len());
+            match &p.rest {
+                None if len != n => {
+                    return Err(format!("expected {n} elements to destructure, got {len}"))
+                }
+                Some(_) if len < n => {
+                    return Err(format!(
+                        "expected at least {n} elements to destructure, got {len}"
+                    ))
+                }
+                _ => {}
+            }
+            let rest = elements.split_off(n);
+            for (pattern, element) in p.elements.iter().zip(elements) {
+                bind_pattern(pattern, element, env)?;
+            }
+            if let Some(name) = &p.rest {
+                env.borrow_mut().set(&name.value, array(rest));
+            }
+            Ok(())
+        }
+        Pattern::Hash(p) => {
+            let Object::Hash(pairs) = &value else {
+                return Err(format!(
+                    "cannot destructure {} as a hash",
+                    value.type_name()
+                ));
+            };
+            for (key, pattern) in &p.fields {
+                let field = hash_get(&pairs.borrow(), &Object::String(key.value.clone()));
+                let Some(field) = field else {
+                    return Err(format!("key \"{}\" not found in hash", key.value));
+                };
+                bind_pattern(pattern, field, env)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// The error for a value of another type than `annotation`, as described on
+// TypeAnnotation. Any function fits a function type.
+fn check_annotation(value: &Object, annotation: &TypeAnnotation, what: &str) -> Option<Object> {
+    let fits = match (annotation, value) {
+        (TypeAnnotation::Array(element), Object::Array(elements)) => {
+            let what = format!("an element of {what}");
+            return elements
+                .borrow()
+                .iter()
+                .find_map(|e| check_annotation(e, element, &what));
+        }
+        (TypeAnnotation::Hash(key, element), Object::Hash(pairs)) => {
+            let (what_key, what_value) = (format!("a key of {what}"), format!("a value of {what}"));
+            return pairs.borrow().iter().find_map(|(k, v)| {
+                check_annotation(k, key, &what_key)
+                    .or_else(|| check_annotation(v, element, &what_value))
+            });
+        }
+        (TypeAnnotation::Named(name), value) => {
+            let type_name = match name.value.as_str() {
+                "int" => "INTEGER",
+                "bool" => "BOOLEAN",
+                "string" => "STRING",
+                "null" => "NULL",
+                "range" => "RANGE",
+                name => return Some(Object::Error(format!("unknown type {name}"))),
+            };
+            value.type_name() == type_name
+        }
+        (TypeAnnotation::Function(..), Object::Function(_) | Object::Builtin(_)) => true,
+        _ => false,
+    };
+    match fits {
+        true => None,
+        false => Some(Object::Error(format!(
+            "expected {annotation} for {what}, got {}",
+            value.type_name()
+        ))),
+    }
+}
+
+fn eval_expression(expr: &Expression, env: &Env) -> Object {
+    match expr {
+        Expression::Identifier(e) => match env.borrow().get(&e.value) {
+            Some(value) => value,
+            None => match BUILTINS.get(e.value.as_str()) {
+                Some(builtin) => Object::Builtin(*builtin),
+                None => Object::Error(format!("identifier not found: {}", e.value)),
+            },
+        },
+        Expression::Integer(e) => Object::Integer(e.value),
+        Expression::Boolean(e) => Object::Boolean(e.value),
+        Expression::String(e) => Object::String(e.value.clone()),
+        Expression::Prefix(e) => {
+            let right = eval_expression(&e.right, env);
+            if right.is_abrupt() {
+                return right;
+            }
+            eval_prefix(&e.operator, right)
+        }
+        Expression::Infix(e) => {
+            let left = eval_expression(&e.left, env);
+            if left.is_abrupt() {
+                return left;
+            }
+            let right = eval_expression(&e.right, env);
+            if right.is_abrupt() {
+                return right;
+            }
+            eval_infix(&e.operator, left, right)
+        }
+        Expression::If(e) => {
+            let condition = eval_expression(&e.condition, env);
+            if condition.is_abrupt() {
+                return condition;
+            }
+            if truthy(&condition) {
+                eval_block(&e.consequence, env)
+            } else if let Some(alternative) = &e.alternative {
+                eval_block(alternative, env)
+            } else {
+                Object::Null
+            }
+        }
+        Expression::Function(e) => Object::Function(Rc::new(Function {
+            literal: e.clone(),
+            env: Rc::clone(env),
+        })),
+        Expression::Macro(_) => Object::Error("cannot evaluate a macro literal".to_string()),
+        Expression::Call(e) => eval_call(e, env),
+        Expression::Array(e) => match eval_expressions(&e.elements, env) {
+            Ok(elements) => array(elements),
+            Err(error) => error,
+        },
+        Expression::Index(e) => {
+            let left = eval_expression(&e.left, env);
+            if left.is_abrupt() {
+                return left;
+            }
+            let index = eval_expression(&e.index, env);
+            if index.is_abrupt() {
+                return index;
+            }
+            eval_index(&left, &index)
+        }
+        Expression::Hash(e) => eval_hash_literal(&e.pairs, env),
+        Expression::While(e) => eval_while(e, env),
+        Expression::For(e) => eval_for(e, env),
+        Expression::Assign(e) => eval_assign(e, env),
+        Expression::Match(e) => eval_match(e, env),
+        Expression::Range(e) => {
+            let mut bounds = [None, None];
+            for (bound, expr) in bounds.iter_mut().zip([&e.start, &e.end]) {
+                let Some(expr) = expr else {
+                    continue;
+                };
+                match eval_expression(expr, env) {
+                    Object::Integer(value) => *bound = Some(value),
+                    value if value.is_abrupt() => return value,
+                    value => {
+                        return Object::Error(format!(
+                            "range bounds must be integers, got {}",
+                            value.type_name()
+                        ))
+                    }
+                }
+            }
+            Object::Range(bounds[0], bounds[1], e.inclusive)
+        }
+    }
+}
+
+// Like every loop, `while` evaluates to null unless a `return` or an error
+// leaves it.
+fn eval_while(e: &WhileExpression, env: &Env) -> Object {
+    loop {
+        let condition = eval_expression(&e.condition, env);
+        if condition.is_abrupt() {
+            return condition;
+        }
+        if !truthy(&condition) {
+            return Object::Null;
+        }
+        match eval_block(&e.body, env) {
+            Object::Break => return Object::Null,
+            result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+            _ => {}
+        }
+    }
+}
+
+// Each iteration runs in a scope of its own, so a closure made in the body
+// keeps the element of its iteration. Changes to an array while it is
+// iterated over are not seen by the loop.
+fn eval_for(e: &ForExpression, env: &Env) -> Object {
+    let iterable = eval_expression(&e.iterable, env);
+    if iterable.is_abrupt() {
+        return iterable;
+    }
+    let items: Box<dyn Iterator<Item = Object>> = match &iterable {
+        Object::Array(elements) => Box::new(elements.borrow().clone().into_iter()),
+        Object::String(s) => {
+            let chars: Vec<Object> = s.chars().map(|c| Object::String(c.into())).collect();
+            Box::new(chars.into_iter())
+        }
+        Object::Hash(pairs) => {
+            let keys: Vec<Object> = pairs.borrow().iter().map(|(k, _)| k.clone()).collect();
+            Box::new(keys.into_iter())
+        }
+        range @ Object::Range(..) => match range_items(range) {
+            Ok(items) => Box::new(items.map(Object::Integer)),
+            Err(error) => return error,
+        },
+        other => return Object::Error(format!("cannot iterate over {}", other.type_name())),
+    };
+    for item in items {
+        let scope = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(env))));
+        scope.borrow_mut().set(&e.variable.value, item);
+        match eval_block(&e.body, &scope) {
+            Object::Break => break,
+            result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+            _ => {}
+        }
+    }
+    Object::Null
+}
+
+// The first arm that fits the subject is evaluated in a scope of its own,
+// holding the pattern's bindings, which its guard sees too.
+fn eval_match(e: &MatchExpression, env: &Env) -> Object {
+    let subject = eval_expression(&e.subject, env);
+    if subject.is_abrupt() {
+        return subject;
+    }
+    for arm in &e.arms {
+        let scope = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(env))));
+        if bind_pattern(&arm.pattern, subject.clone(), &scope).is_err() {
+            continue;
+        }
+        if let Some(guard) = &arm.guard {
+            let guard = eval_expression(guard, &scope);
+            if guard.is_abrupt() {
+                return guard;
+            }
+            if !truthy(&guard) {
+                continue;
+            }
+        }
+        return eval_expression(&arm.body, &scope);
+    }
+    Object::Error(format!("no match arm for {subject}"))
+}
+
+// Evaluate `exprs` left to right, stopping at the first error.
+fn eval_expressions(exprs: &[Expression], env: &Env) -> Result<Vec<Object>, Object> {
+    let mut values = Vec::new();
+    for expr in exprs {
+        let value = eval_expression(expr, env);
+        if value.is_abrupt() {
+            return Err(value);
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+fn array(elements: Vec<Object>) -> Object {
+    Object::Array(Rc::new(RefCell::new(elements)))
+}
+
+// Each key is evaluated before its value. A key given twice keeps its first
+// position and its last value.
+fn eval_hash_literal(pairs: &[(Expression, Expression)], env: &Env) -> Object {
+    let mut hash = Vec::new();
+    for (key, value) in pairs {
+        let key = eval_expression(key, env);
+        if key.is_abrupt() {
+            return key;
+        }
+        if let Some(error) = check_hash_key(&key) {
+            return error;
+        }
+        let value = eval_expression(value, env);
+        if value.is_abrupt() {
+            return value;
+        }
+        hash_insert(&mut hash, key, value);
+    }
+    Object::Hash(Rc::new(RefCell::new(hash)))
+}
+
+// Only integers, booleans and strings can be hash keys.
+fn check_hash_key(key: &Object) -> Option<Object> {
+    match key {
+        Object::Integer(_) | Object::Boolean(_) | Object::String(_) => None,
+        key => Some(Object::Error(format!(
+            "unusable as hash key: {}",
+            key.type_name()
+        ))),
+    }
+}
+
+fn hash_get(pairs: &[(Object, Object)], key: &Object) -> Option<Object> {
+    pairs
+        .iter()
+        .find(|(k, _)| equal(k, key))
+        .map(|(_, v)| v.clone())
+}
+
+fn hash_insert(pairs: &mut Vec<(Object, Object)>, key: Object, value: Object) {
+    match pairs.iter_mut().find(|(k, _)| equal(k, &key)) {
+        Some(pair) => pair.1 = value,
+        None => pairs.push((key, value)),
+    }
+}
+
+// Look up `index` in `left` as described on IndexExpression.
+fn eval_index(left: &Object, index: &Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let elements = elements.borrow();
+            position(*i, elements.len()).map_or(Object::Null, |i| elements[i].clone())
+        }
+        (Object::String(s), Object::Integer(i)) => {
+            let chars: Vec<char> = s.chars().collect();
+            position(*i, chars.len()).map_or(Object::Null, |i| Object::String(chars[i].into()))
+        }
+        (Object::Array(elements), range @ Object::Range(..)) => {
+            let elements = elements.borrow();
+            let (start, end) = slice_bounds(range, elements.len());
+            array(elements[start..end].to_vec())
+        }
+        (Object::String(s), range @ Object::Range(..)) => {
+            let chars: Vec<char> = s.chars().collect();
+            let (start, end) = slice_bounds(range, chars.len());
+            Object::String(chars[start..end].iter().collect())
+        }
+        (Object::Hash(pairs), key) => match check_hash_key(key) {
+            Some(error) => error,
+            None => hash_get(&pairs.borrow(), key).unwrap_or(Object::Null),
+        },
+        (left, _) => Object::Error(format!(
+            "index operator not supported: {}",
+            left.type_name()
+        )),
+    }
+}
+
+// The part of a sequence of `len` that the range `range` slices, as
+// described on IndexExpression.
+fn slice_bounds(range: &Object, len: usize) -> (usize, usize) {
+    let Object::Range(start, end, inclusive) = range else {
+        unreachable!("slicing with {range}")
+    };
+    let len = len as i64;
+    let normalize = |bound: i64| if bound < 0 { bound + len } else { bound };
+    let start = normalize(start.unwrap_or(0)).clamp(0, len);
+    let end = match end {
+        Some(end) => normalize(*end).saturating_add(*inclusive as i64),
+        None => len,
+    }
+    .clamp(0, len);
+    (start as usize, end.max(start) as usize)
+}
+
+// The integers of a range with both bounds.
+fn range_items(range: &Object) -> Result<std::ops::Range<i64>, Object> {
+    match range {
+        Object::Range(Some(start), Some(end), inclusive) => {
+            Ok(*start..end.saturating_add(*inclusive as i64).max(*start))
+        }
+        _ => Err(Object::Error("cannot use an open range here".to_string())),
+    }
+}
+
+// The position of a possibly negative index in a sequence of `len`.
+fn position(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+// The value is evaluated before the target's current value is read, and
+// the collection and index of an index target only once.
+fn eval_assign(e: &AssignExpression, env: &Env) -> Object {
+    let operator = e.operator.strip_suffix('=').filter(|op| !op.is_empty());
+    match e.target.as_ref() {
+        Expression::Identifier(name) => {
+            let mut value = eval_expression(&e.value, env);
+            if value.is_abrupt() {
+                return value;
+            }
+            if let Some(operator) = operator {
+                let current = eval_expression(&e.target, env);
+                if current.is_abrupt() {
+                    return current;
+                }
+                value = eval_infix(operator, current, value);
+                if value.is_abrupt() {
+                    return value;
+                }
+            }
+            match env.borrow_mut().assign(&name.value, value.clone()) {
+                true => value,
+                false => Object::Error(format!("cannot assign to undeclared name {}", name.value)),
+            }
+        }
+        Expression::Index(target) => {
+            let collection = eval_expression(&target.left, env);
+            if collection.is_abrupt() {
+                return collection;
+            }
+            let index = eval_expression(&target.index, env);
+            if index.is_abrupt() {
+                return index;
+            }
+            let mut value = eval_expression(&e.value, env);
+            if value.is_abrupt() {
+                return value;
+            }
+            if let Some(operator) = operator {
+                let current = eval_index(&collection, &index);
+                if current.is_abrupt() {
+                    return current;
+                }
+                value = eval_infix(operator, current, value);
+                if value.is_abrupt() {
+                    return value;
+                }
+            }
+            eval_set_index(&collection, &index, value)
+        }
+        target => unreachable!("invalid assignment target {target}"),
+    }
+}
+
+// Store `value` at `index` in `collection`, giving the value.
+fn eval_set_index(collection: &Object, index: &Object, value: Object) -> Object {
+    match (collection, index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let mut elements = elements.borrow_mut();
+            let len = elements.len();
+            match position(*i, len) {
+                Some(i) => elements[i] = value.clone(),
+                None => return Object::Error(format!("index {i} out of range for length {len}")),
+            }
+            value
+        }
+        (Object::Hash(pairs), key) => match check_hash_key(key) {
+            Some(error) => error,
+            None => {
+                hash_insert(&mut pairs.borrow_mut(), key.clone(), value.clone());
+                value
+            }
+        },
+        (collection, _) => Object::Error(format!(
+            "index assignment not supported: {}",
+            collection.type_name()
+        )),
+    }
+}
+
+// The function, then the positional and the named arguments are evaluated
+// left to right. A call of `quote` is the exception: unless a binding
+// shadows it, its argument is code, not a value.
+fn eval_call(call: &CallExpression, env: &Env) -> Object {
+    if let Expression::Identifier(name) = call.function.as_ref() {
+        if name.value == "quote" && env.borrow().get("quote").is_none() {
+            return eval_quote(call, env);
+        }
+    }
+    let function = eval_expression(&call.function, env);
+    if function.is_abrupt() {
+        return function;
+    }
+    let arguments = match eval_expressions(&call.arguments, env) {
+        Ok(arguments) => arguments,
+        Err(error) => return error,
+    };
+    let mut named = Vec::new();
+    for (name, arg) in &call.named_arguments {
+        let value = eval_expression(arg, env);
+        if value.is_abrupt() {
+            return value;
+        }
+        named.push((name.value.as_str(), value));
+    }
+    apply_function(function, arguments, named)
+}
+
+// `quote(code)` evaluates to `code` itself, after replacing each
+// `unquote(expr)` in it with the code for the value of `expr`.
+fn eval_quote(call: &CallExpression, env: &Env) -> Object {
+    if !call.named_arguments.is_empty() {
+        return Object::Error("quote does not take named arguments".to_string());
+    }
+    let [code] = call.arguments.as_slice() else {
+        return wrong_arguments(call.arguments.len(), 1);
+    };
+    let mut unquoter = Unquoter { env, error: None };
+    let code = unquoter.fold_expression(code.clone());
+    match unquoter.error {
+        Some(error) => error,
+        None => Object::Quote(Box::new(code)),
+    }
+}
+
+// Evaluates the `unquote(expr)` calls in quoted code, stopping at the first
+// error.
+struct Unquoter<'a> {
+    env: &'a Env,
+    error: Option<Object>,
+}
+
+impl Fold for Unquoter<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        let Expression::Call(call) = &expr else {
+            return fold_expression(self, expr);
+        };
+        let arg = match (call.function.as_ref(), call.arguments.as_slice()) {
+            (Expression::Identifier(name), [arg])
+                if name.value == "unquote" && call.named_arguments.is_empty() =>
+            {
+                arg
+            }
+            _ => return fold_expression(self, expr),
+        };
+        if self.error.is_some() {
+            return expr;
+        }
+        match value_to_code(&eval_expression(arg, self.env), call.token.span) {
+            Ok(code) => code,
+            Err(error) => {
+                self.error = Some(error);
+                expr
+            }
+        }
+    }
+}
+
+// The code that evaluates to `value`, with its tokens at `span`. Quoted code
+// stands for itself; functions and the like have no code.
+fn value_to_code(value: &Object, span: Span) -> Result<Expression, Object> {
+    let token = |t_type, literal: &str| Token {
+        span,
+        ..Token::new(t_type, literal)
+    };
+    let code = match value {
+        Object::Quote(code) => (**code).clone(),
+        Object::Integer(n) if *n < 0 => Expression::Prefix(ast::PrefixExpression {
+            token: token(TokenType::Minus, "-"),
+            operator: "-".to_string(),
+            right: Box::new(Expression::Integer(ast::IntegerLiteral {
+                token: token(TokenType::Int, &n.unsigned_abs().to_string()),
+                value: n.wrapping_neg(),
+            })),
+        }),
+        Object::Integer(n) => Expression::Integer(ast::IntegerLiteral {
+            token: token(TokenType::Int, &n.to_string()),
+            value: *n,
+        }),
+        Object::Boolean(b) => Expression::Boolean(ast::Boolean {
+            token: match b {
+                true => token(TokenType::True, "true"),
+                false => token(TokenType::False, "false"),
+            },
+            value: *b,
+        }),
+        Object::String(s) => Expression::String(ast::StringLiteral {
+            token: token(TokenType::String, s),
+            value: s.clone(),
+        }),
+        Object::Array(elements) => Expression::Array(ast::ArrayLiteral {
+            token: token(TokenType::Lbracket, "["),
+            elements: elements
+                .borrow()
+                .iter()
+                .map(|e| value_to_code(e, span))
+                .collect::<Result<_, _>>()?,
+        }),
+        Object::Hash(pairs) => Expression::Hash(ast::HashLiteral {
+            token: token(TokenType::Lbrace, "{"),
+            pairs: pairs
+                .borrow()
+                .iter()
+                .map(|(k, v)| Ok((value_to_code(k, span)?, value_to_code(v, span)?)))
+                .collect::<Result<_, _>>()?,
+        }),
+        value if value.is_abrupt() => return Err(value.clone()),
+        value => {
+            return Err(Object::Error(format!(
+                "cannot unquote {}",
+                value.type_name()
+            )))
+        }
+    };
+    Ok(code)
+}
+
+// Call `function` with the arguments. Calls nest on the Rust stack, so past
+// MAX_CALL_DEPTH they fail instead of overflowing it.
+fn apply_function(function: Object, arguments: Vec<Object>, named: Vec<(&str, Object)>) -> Object {
+    let function = match function {
+        Object::Function(function) => function,
+        Object::Builtin(builtin) if !named.is_empty() => {
+            return Object::Error(format!("{} does not take named arguments", builtin.name))
+        }
+        Object::Builtin(builtin) => return (builtin.function)(arguments),
+        other => return Object::Error(format!("not a function: {}", other.type_name())),
+    };
+    let depth = CALL_DEPTH.get();
+    if depth == MAX_CALL_DEPTH {
+        return Object::Error("maximum call depth exceeded".to_string());
+    }
+    CALL_DEPTH.set(depth + 1);
+    let result = call_function(&function, arguments, named);
+    CALL_DEPTH.set(depth);
+    result
+}
+
+// Run the body of `function` in a fresh scope inside the one it was defined
+// in, holding its parameters. A `return` only leaves the function.
+fn call_function(
+    function: &Function,
+    arguments: Vec<Object>,
+    named: Vec<(&str, Object)>,
+) -> Object {
+    let literal = &function.literal;
+    let env = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(
+        &function.env,
+    ))));
+    if let Some(error) = bind_arguments(literal, arguments, named, &env) {
+        return error;
+    }
+
+    let result = match eval_block(&literal.body, &env) {
+        Object::ReturnValue(value) => *value,
+        jump @ (Object::Break | Object::Continue) => return outside_loop(&jump),
+        result => result,
+    };
+    if let (Some(annotation), false) = (&literal.result, result.is_error()) {
+        if let Some(error) = check_annotation(&result, annotation, "the result") {
+            return error;
+        }
+    }
+    result
+}
+
+// Bind the arguments of a call to the parameters of `function` in `env`,
+// failing as described on FunctionLiteral.
+fn bind_arguments(
+    function: &FunctionLiteral,
+    mut arguments: Vec<Object>,
+    named: Vec<(&str, Object)>,
+    env: &Env,
+) -> Option<Object> {
+    let parameters = &function.parameters;
+    let surplus = arguments.split_off(arguments.len().min(parameters.len()));
+    if !surplus.is_empty() && function.rest.is_none() {
+        return Some(Object::Error(format!(
+            "too many arguments: expected at most {}, got {}",
+            parameters.len(),
+            parameters.len() + surplus.len()
+        )));
+    }
+
+    let mut slots: Vec<Option<Object>> = parameters.iter().map(|_| None).collect();
+    for (slot, arg) in slots.iter_mut().zip(arguments) {
+        *slot = Some(arg);
+    }
+    for (name, value) in named {
+        let Some(i) = parameters.iter().position(|p| p.name.value == name) else {
+            return Some(Object::Error(format!("unknown parameter {name}")));
+        };
+        if slots[i].is_some() {
+            return Some(Object::Error(format!("argument {name} given twice")));
+        }
+        slots[i] = Some(value);
+    }
+    for (slot, param) in slots.iter().zip(parameters) {
+        if slot.is_none() && param.default.is_none() {
+            return Some(Object::Error(format!(
+                "missing argument for parameter {}",
+                param.name
+            )));
+        }
+    }
+
+    for (slot, param) in slots.into_iter().zip(parameters) {
+        let value = match (slot, &param.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => eval_expression(default, env),
+            (None, None) => unreachable!("missing arguments are reported above"),
+        };
+        if value.is_abrupt() {
+            return Some(value);
+        }
+        if let Some(annotation) = &param.annotation {
+            let what = format!("parameter {}", param.name);
+            if let Some(error) = check_annotation(&value, annotation, &what) {
+                return Some(error);
+            }
+        }
+        env.borrow_mut().set(&param.name.value, value);
+    }
+    if let Some(rest) = &function.rest {
+        env.borrow_mut().set(&rest.value, array(surplus));
+    }
+    None
+}
+
+// The error for a builtin called with `got` arguments instead of `want`.
+fn wrong_arguments(got: usize, want: usize) -> Object {
+    Object::Error(format!("wrong number of arguments. got={got}, want={want}"))
+}
+
+// The error for a builtin given an argument of a type it does not handle.
+fn unsupported_argument(name: &str, arg: &Object) -> Object {
+    Object::Error(format!(
+        "argument to `{name}` not supported, got {}",
+        arg.type_name()
+    ))
+}
+
+// `len(x)` is the number of characters of a string, elements of an array,
+// keys of a hash or integers of a range.
+fn builtin_len(args: Vec<Object>) -> Object {
+    let [arg] = args.as_slice() else {
+        return wrong_arguments(args.len(), 1);
+    };
+    let len = match arg {
+        Object::String(s) => s.chars().count(),
+        Object::Array(elements) => elements.borrow().len(),
+        Object::Hash(pairs) => pairs.borrow().len(),
+        range @ Object::Range(..) => match range_items(range) {
+            Ok(items) => items.count(),
+            Err(error) => return error,
+        },
+        arg => return unsupported_argument("len", arg),
+    };
+    Object::Integer(len as i64)
+}
+
+// `first(xs)` is the first element of the array `xs`, or null when it is
+// empty.
+fn builtin_first(args: Vec<Object>) -> Object {
+    let [arg] = args.as_slice() else {
+        return wrong_arguments(args.len(), 1);
+    };
+    match arg {
+        Object::Array(elements) => elements.borrow().first().cloned().unwrap_or(Object::Null),
+        arg => unsupported_argument("first", arg),
+    }
+}
+
+// `last(xs)` is the last element of the array `xs`, or null when it is
+// empty.
+fn builtin_last(args: Vec<Object>) -> Object {
+    let [arg] = args.as_slice() else {
+        return wrong_arguments(args.len(), 1);
+    };
+    match arg {
+        Object::Array(elements) => elements.borrow().last().cloned().unwrap_or(Object::Null),
+        arg => unsupported_argument("last", arg),
+    }
+}
+
+// `rest(xs)` is a new array of every element of `xs` but the first.
+fn builtin_rest(args: Vec<Object>) -> Object {
+    let [arg] = args.as_slice() else {
+        return wrong_arguments(args.len(), 1);
+    };
+    match arg {
+        Object::Array(elements) => array(elements.borrow().iter().skip(1).cloned().collect()),
+        arg => unsupported_argument("rest", arg),
+    }
+}
+
+// `push(xs, x)` is a new array of the elements of `xs` followed by `x`.
+fn builtin_push(args: Vec<Object>) -> Object {
+    let [xs, x] = args.as_slice() else {
+        return wrong_arguments(args.len(), 2);
+    };
+    match xs {
+        Object::Array(elements) => {
+            let mut elements = elements.borrow().clone();
+            elements.push(x.clone());
+            array(elements)
+        }
+        xs => unsupported_argument("push", xs),
+    }
+}
+
+// `puts(...)` prints each argument on its own line.
+fn builtin_puts(args: Vec<Object>) -> Object {
+    for arg in args {
+        println!("{arg}");
+    }
+    Object::Null
+}
+
+// `doc(f)` is the doc comment of the function `f`, or null when it has none.
+fn builtin_doc(args: Vec<Object>) -> Object {
+    let [arg] = args.as_slice() else {
+        return wrong_arguments(args.len(), 1);
+    };
+    match arg {
+        Object::Function(function) => match &function.literal.doc {
+            Some(doc) => Object::String(doc.clone()),
+            None => Object::Null,
+        },
+        arg => unsupported_argument("doc", arg),
+    }
+}
+
+// Only `false` and `null` are falsy.
+fn truthy(value: &Object) -> bool {
+    !matches!(value, Object::Null | Object::Boolean(false))
+}
+
+// Values of different types are never equal, and functions, arrays and
+// hashes are only equal to themselves. Ranges are equal when their bounds
+// are.
+fn equal(left: &Object, right: &Object) -> bool {
+    match (left, right) {
+        (Object::Integer(a), Object::Integer(b)) => a == b,
+        (Object::Boolean(a), Object::Boolean(b)) => a == b,
+        (Object::String(a), Object::String(b)) => a == b,
+        (Object::Null, Object::Null) => true,
+        (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+        (Object::Builtin(a), Object::Builtin(b)) => a.name == b.name,
+        (Object::Range(a, b, c), Object::Range(x, y, z)) => (a, b, c) == (x, y, z),
+        (Object::Array(a), Object::Array(b)) => Rc::ptr_eq(a, b),
+        (Object::Hash(a), Object::Hash(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+fn eval_prefix(operator: &str, right: Object) -> Object {
+    match (operator, right) {
+        ("!", right) => Object::Boolean(!truthy(&right)),
+        ("-", Object::Integer(value)) => Object::Integer(value.wrapping_neg()),
+        (operator, right) => {
+            Object::Error(format!("unknown operator: {operator}{}", right.type_name()))
+        }
+    }
+}
+
+// Integer arithmetic wraps around on overflow.
+fn eval_infix(operator: &str, left: Object, right: Object) -> Object {
+    use Object::{Boolean, Integer};
+
+    match (operator, &left, &right) {
+        ("==", left, right) => Boolean(equal(left, right)),
+        ("!=", left, right) => Boolean(!equal(left, right)),
+        ("/", Integer(_), Integer(0)) => Object::Error("division by zero".to_string()),
+        ("+", Integer(a), Integer(b)) => Integer(a.wrapping_add(*b)),
+        ("-", Integer(a), Integer(b)) => Integer(a.wrapping_sub(*b)),
+        ("*", Integer(a), Integer(b)) => Integer(a.wrapping_mul(*b)),
+        ("/", Integer(a), Integer(b)) => Integer(a.wrapping_div(*b)),
+        ("<", Integer(a), Integer(b)) => Boolean(a < b),
+        (">", Integer(a), Integer(b)) => Boolean(a > b),
+        ("+", Object::String(a), Object::String(b)) => Object::String(format!("{a}{b}")),
+        (operator, left, right) => {
+            let problem = match left.type_name() == right.type_name() {
+                true => "unknown operator",
+                false => "type mismatch",
+            };
+            Object::Error(format!(
+                "{problem}: {} {operator} {}",
+                left.type_name(),
+                right.type_name()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser;
+    use std::cell::RefCell;
+
+    fn test_eval(input: &str) -> Object {
+        let lex = lexer::new(input);
+        let mut p = parser::new(Rc::new(RefCell::new(lex)));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser errors: {:?}", p.errors());
+        eval(&program, &Rc::new(RefCell::new(Environment::new())))
+    }
+
+    fn assert_integer(input: &str, expected: i64) {
+        match test_eval(input) {
+            Object::Integer(value) => assert_eq!(value, expected, "input: {input}"),
+            other => panic!("expected {expected} for {input}, got {other:?}"),
+        }
+    }
+
+    fn assert_boolean(input: &str, expected: bool) {
+        match test_eval(input) {
+            Object::Boolean(value) => assert_eq!(value, expected, "input: {input}"),
+            other => panic!("expected {expected} for {input}, got {other:?}"),
+        }
+    }
+
+    fn assert_null(input: &str) {
+        match test_eval(input) {
+            Object::Null => {}
+            other => panic!("expected null for {input}, got {other:?}"),
+        }
+    }
+
+    fn assert_error(input: &str, expected: &str) {
+        match test_eval(input) {
+            Object::Error(message) => assert_eq!(message, expected, "input: {input}"),
+            other => panic!("expected error {expected:?} for {input}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = vec![
+            ("5", 5),
+            ("10", 10),
+            ("-5", -5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * 2 * 2 * 2 * 2", 32),
+            ("-50 + 100 + -50", 0),
+            ("5 * 2 + 10", 20),
+            ("5 + 2 * 10", 25),
+            ("20 + 2 * -10", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("2 * (5 + 10)", 30),
+            ("3 * 3 * 3 + 10", 37),
+            ("3 * (3 * 3) + 10", 37),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+            ("9223372036854775807 + 1", i64::MIN),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = vec![
+            ("true", true),
+            ("false", false),
+            ("1 < 2", true),
+            ("1 > 2", false),
+            ("1 < 1", false),
+            ("1 > 1", false),
+            ("1 == 1", true),
+            ("1 != 1", false),
+            ("1 == 2", false),
+            ("1 != 2", true),
+            ("true == true", true),
+            ("false == false", true),
+            ("true == false", false),
+            ("true != false", true),
+            ("false != true", true),
+            ("(1 < 2) == true", true),
+            ("(1 < 2) == false", false),
+            ("(1 > 2) == true", false),
+            ("(1 > 2) == false", true),
+            // Values of different types are unequal rather than an error.
+            ("1 == true", false),
+            (r#""a" != 1"#, true),
+            (r#""a" == "a""#, true),
+            ("let f = fn() {}; f == f", true),
+            ("fn() {} == fn() {}", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_boolean(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_bang_operator() {
+        let tests = vec![
+            ("!true", false),
+            ("!false", true),
+            ("!5", false),
+            ("!!true", true),
+            ("!!false", false),
+            ("!!5", true),
+            (r#"!"""#, false),
+            ("!if (false) { 1 }", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_boolean(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_string_expressions() {
+        let tests = vec![
+            (r#""Hello World!""#, "Hello World!"),
+            (r#""Hello" + " " + "World!""#, "Hello World!"),
+        ];
+
+        for (input, expected) in tests {
+            match test_eval(input) {
+                Object::String(value) => assert_eq!(value, expected, "input: {input}"),
+                other => panic!("expected {expected:?} for {input}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions() {
+        let tests = vec![
+            ("if (true) { 10 }", Some(10)),
+            ("if (false) { 10 }", None),
+            ("if (1) { 10 }", Some(10)),
+            ("if (1 < 2) { 10 }", Some(10)),
+            ("if (1 > 2) { 10 }", None),
+            ("if (1 > 2) { 10 } else { 20 }", Some(20)),
+            ("if (1 < 2) { 10 } else { 20 }", Some(10)),
+            // Zero is truthy: only `false` and `null` are not.
+            ("if (0) { 10 } else { 20 }", Some(10)),
+            ("if (if (false) { 1 }) { 10 } else { 20 }", Some(20)),
+        ];
+
+        for (input, expected) in tests {
+            match expected {
+                Some(expected) => assert_integer(input, expected),
+                None => assert_null(input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let tests = vec![
+            ("return 10;", 10),
+            ("return 10; 9;", 10),
+            ("return 2 * 5; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+            ("if (10 > 1) { return 10; }", 10),
+            (
+                "if (10 > 1) {
+                   if (10 > 1) {
+                     return 10;
+                   }
+
+                   return 1;
+                 }",
+                10,
+            ),
+            ("let f = fn(x) { return x; }; if (true) { return 1; }; 2", 1),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = vec![
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
+            (
+                "if (10 > 1) { true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            (
+                "if (10 > 1) {
+                   if (10 > 1) {
+                     return true + false;
+                   }
+
+                   return 1;
+                 }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "identifier not found: foobar"),
+            (r#""Hello" - "World""#, "unknown operator: STRING - STRING"),
+            ("1 < true", "type mismatch: INTEGER < BOOLEAN"),
+            ("10 / (5 - 5)", "division by zero"),
+            ("-(1 + foo)", "identifier not found: foo"),
+            ("if (x) { 1 }", "identifier not found: x"),
+            ("macro(x) { x }", "cannot evaluate a macro literal"),
+            ("1[0]", "index operator not supported: INTEGER"),
+            ("[1][true]", "index operator not supported: ARRAY"),
+            ("5()", "not a function: INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_error(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_array_literals() {
+        let Object::Array(elements) = test_eval("[1, 2 * 2, 3 + 3]") else {
+            panic!("expected an array");
+        };
+        let elements: Vec<String> = elements.borrow().iter().map(|e| e.to_string()).collect();
+        assert_eq!(elements, vec!["1", "4", "6"]);
+        assert_eq!(
+            test_eval(r#"[1, "two", [true]]"#).to_string(),
+            "[1, two, [true]]"
+        );
+        assert_error("[1, -true, foo]", "unknown operator: -BOOLEAN");
+    }
+
+    #[test]
+    fn test_array_index_expressions() {
+        let tests = vec![
+            ("[1, 2, 3][0]", Some(1)),
+            ("[1, 2, 3][1]", Some(2)),
+            ("[1, 2, 3][2]", Some(3)),
+            ("let i = 0; [1][i];", Some(1)),
+            ("[1, 2, 3][1 + 1];", Some(3)),
+            ("let myArray = [1, 2, 3]; myArray[2];", Some(3)),
+            (
+                "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
+                Some(6),
+            ),
+            (
+                "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]",
+                Some(2),
+            ),
+            ("[1, 2, 3][3]", None),
+            // Negative indexes count from the end.
+            ("[1, 2, 3][-1]", Some(3)),
+            ("[1, 2, 3][-3]", Some(1)),
+            ("[1, 2, 3][-4]", None),
+        ];
+
+        for (input, expected) in tests {
+            match expected {
+                Some(expected) => assert_integer(input, expected),
+                None => assert_null(input),
+            }
+        }
+        assert_eq!(test_eval(r#""monkey"[-1]"#).to_string(), "y");
+        assert_null(r#""monkey"[6]"#);
+    }
+
+    #[test]
+    fn test_array_assignments() {
+        let tests = vec![
+            ("let a = [1, 2]; a[0] += 5; a[-1] = 7; a", "[6, 7]"),
+            ("let a = [1]; let b = a; b[0] = 2; a", "[2]"),
+            ("let a = [1]; a[0] = 5", "5"),
+            // The collection and index are evaluated once, before the value.
+            (
+                "let i = 0; let a = [10, 20];
+                 let next = fn() { i += 1; i - 1 };
+                 a[next()] += 1; [a, i]",
+                "[[11, 20], 1]",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        assert_error("let a = [1]; a[1] = 2", "index 1 out of range for length 1");
+        assert_error(
+            "let a = [1]; a[0] += true",
+            "type mismatch: INTEGER + BOOLEAN",
+        );
+        assert_error(
+            r#"let s = "a"; s[0] = "b""#,
+            "index assignment not supported: STRING",
+        );
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = vec![
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; b;", 5),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+            ("let a = 1; let a = a + 1; a", 2),
+            ("let _ = 1; 3", 3),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+        assert_null("let a = 5;");
+        assert_error("let a = foo; a", "identifier not found: foo");
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let tests = vec![
+            (
+                "let [a, b, ...r] = [1, 2, 3, 4]; [a, b, r]",
+                "[1, 2, [3, 4]]",
+            ),
+            ("let [a, ...r] = [1]; r", "[]"),
+            ("let [] = []; 1", "1"),
+            (
+                r#"let {name, age: years} = {"name": "Ann", "age": 30}; [name, years]"#,
+                "[Ann, 30]",
+            ),
+            ("let [x, [y, _]] = [1, [2, 3]]; x + y", "3"),
+            (r#"let {p: [a, b]} = {"p": [1, 2], "q": 3}; a * b"#, "2"),
+            ("let [1, a] = [1, 3]; a", "3"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_destructuring_errors() {
+        let tests = vec![
+            ("let [a] = 1", "cannot destructure INTEGER as an array"),
+            (
+                "let [a, b] = [1, 2, 3]",
+                "expected 2 elements to destructure, got 3",
+            ),
+            (
+                "let [a, b] = [1]",
+                "expected 2 elements to destructure, got 1",
+            ),
+            (
+                "let [a, b, ...r] = [1]",
+                "expected at least 2 elements to destructure, got 1",
+            ),
+            ("let {a} = true", "cannot destructure BOOLEAN as a hash"),
+            (
+                r#"let {age} = {"name": 1}"#,
+                r#"key "age" not found in hash"#,
+            ),
+            ("let [1, a] = [2, 3]", "2 does not match pattern 1"),
+            (
+                r#"let [x, {y}] = [1, [2]]"#,
+                "cannot destructure ARRAY as a hash",
+            ),
+            ("let [a] = [-true]", "unknown operator: -BOOLEAN"),
+        ];
+
+        for (input, expected) in tests {
+            assert_error(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let tests =
+            vec![
+            (
+                "let i = 0; let s = 0;
+                 while (i < 10) {
+                   i += 1;
+                   if (i / 2 * 2 == i) { continue; }
+                   s += i;
+                   if (i == 7) { break; }
+                 }; [i, s]",
+                "[7, 16]",
+            ),
+            ("let s = 0; for (x in [1, 2, 3]) { s += x }; s", "6"),
+            (r#"let out = ""; for (c in "abc") { out = c + out }; out"#, "cba"),
+            (
+                r#"let out = ""; for (k in {"a": 1, "b": 2}) { out = out + k }; out"#,
+                "ab",
+            ),
+            (
+                "let n = 0;
+                 for (i in [0, 1, 2]) {
+                   for (j in [0, 1, 2]) { if (j == 1) { break } n += 1 }
+                   if (i == 1) { continue }
+                   n += 10
+                 };
+                 n",
+                "23",
+            ),
+            // The loop variable and the body's bindings belong to one
+            // iteration.
+            (
+                "let fs = [0, 0]; for (i in [0, 1]) { fs[i] = fn() { i } }; [fs[0](), fs[1]()]",
+                "[0, 1]",
+            ),
+            ("let x = 5; for (x in [1, 2]) { x = x * 2 }; x", "5"),
+            (
+                "let f = fn() { for (x in [1, 2, 3, 4]) { if (x == 3) { return x } } }; f()",
+                "3",
+            ),
+            ("let f = fn() { while (true) { return 5; } }; f()", "5"),
+            // A jump inside a `let` value leaves the loop too.
+            (
+                "let i = 0; while (true) { let x = if (i == 3) { break; } else { i }; i += 1 }; i",
+                "3",
+            ),
+            ("let a = [1]; for (x in a) { a[0] = 5; x }", "null"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        // Every loop evaluates to null.
+        assert_null("while (false) { 1 }");
+        assert_null("let i = 0; while (i < 3) { i += 1; i }");
+        assert_null("for (x in [1, 2]) { x }");
+        assert_null("for (x in []) { x }");
+        assert_null("while (true) { break; }");
+
+        assert_error("for (x in 5) {}", "cannot iterate over INTEGER");
+        assert_error("while (foo) {}", "identifier not found: foo");
+        assert_error(
+            "let i = 0; while (i < 5) { i += 1; if (i == 2) { -true } }",
+            "unknown operator: -BOOLEAN",
+        );
+    }
+
+    // The parser rejects these, so the statements are lifted out of a loop
+    // body by hand.
+    #[test]
+    fn test_jumps_outside_loops() {
+        let lex = lexer::new("while (false) { break; continue; }");
+        let mut p = parser::new(Rc::new(RefCell::new(lex)));
+        let program = p.parse_program();
+        let Statement::Expression(stmt) = &program.statements[0] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::While(e) = &stmt.expression else {
+            panic!("expected a while loop");
+        };
+
+        for (stmt, expected) in e.body.statements.iter().zip(["break", "continue"]) {
+            let env = Rc::new(RefCell::new(Environment::new()));
+            let program = Program {
+                statements: vec![stmt.clone()],
+            };
+            let message = format!("{expected} outside of a loop");
+            match eval(&program, &env) {
+                Object::Error(m) => assert_eq!(m, message),
+                other => panic!("expected {message:?}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ranges() {
+        let tests = vec![
+            ("1..4", "1..4"),
+            ("let n = 3; 0..=n - 1", "0..=2"),
+            ("let s = 0; for (i in 1..=4) { s += i }; s", "10"),
+            ("let s = 0; for (i in 1..4) { s += i }; s", "6"),
+            // Ranges never count down.
+            ("let s = 0; for (i in 4..1) { s += i }; s", "0"),
+            ("let r: range = 1..2; r", "1..2"),
+            ("(1..2) == (1..2)", "true"),
+            ("(1..2) == (1..=2)", "false"),
+            (
+                "let s = 0; for (i in 0..1000000000000) { if (i == 3) { break } s += i }; s",
+                "3",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        assert_error(r#"1.."a""#, "range bounds must be integers, got STRING");
+        assert_error("true..=2", "range bounds must be integers, got BOOLEAN");
+        assert_error("1..foo", "identifier not found: foo");
+        assert_error("let r: int = 1..2", "expected int for r, got RANGE");
+        assert_error("{}[1..2]", "unusable as hash key: RANGE");
+    }
+
+    #[test]
+    fn test_slices() {
+        let tests = vec![
+            ("[1, 2, 3, 4][1..3]", "[2, 3]"),
+            ("[1, 2, 3, 4][1..=3]", "[2, 3, 4]"),
+            ("[1, 2, 3][-2..]", "[2, 3]"),
+            ("[1, 2, 3][..-1]", "[1, 2]"),
+            ("[1, 2, 3][0..=-1]", "[1, 2, 3]"),
+            ("[1, 2, 3][..]", "[1, 2, 3]"),
+            ("[1, 2, 3][..=0]", "[1]"),
+            // Bounds are clamped, so slicing never fails.
+            ("[1, 2, 3][-10..10]", "[1, 2, 3]"),
+            ("[1, 2, 3][2..1]", "[]"),
+            ("[1, 2, 3][5..]", "[]"),
+            (r#""monkey"[..10]"#, "monkey"),
+            (r#""monkey"[1..=2]"#, "on"),
+            (r#""monkey"[-3..]"#, "key"),
+            (r#""monkey"[..3]"#, "mon"),
+            // A slice is a new array.
+            (
+                "let a = [1, 2]; let b = a[..]; b[0] = 5; [a, b]",
+                "[[1, 2], [5, 2]]",
+            ),
+            ("let a = [1, 2, 3]; let r = 1..3; a[r]", "[2, 3]"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_len_builtin() {
+        let tests = vec![
+            (r#"len("")"#, 0),
+            (r#"len("four")"#, 4),
+            (r#"len("hello world")"#, 11),
+            ("len([1, 2, 3])", 3),
+            ("len([])", 0),
+            (r#"len({"a": 1, "b": 2})"#, 2),
+            ("len(1..=5)", 5),
+            ("len(5..1)", 0),
+            ("[1, 2] |> len", 2),
+            ("let len = fn(x) { 7 }; len([])", 7),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+        assert_error("len(1)", "argument to `len` not supported, got INTEGER");
+        assert_error(
+            r#"len("one", "two")"#,
+            "wrong number of arguments. got=2, want=1",
+        );
+    }
+
+    #[test]
+    fn test_array_builtins() {
+        let tests = vec![
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([])", "[]"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; let b = push(a, 2); [a, b]", "[[1], [1, 2]]"),
+            ("puts(1, \"a\")", "null"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        assert_error("first(1)", "argument to `first` not supported, got INTEGER");
+        assert_error(
+            "last(\"ab\")",
+            "argument to `last` not supported, got STRING",
+        );
+        assert_error("rest([1], [2])", "wrong number of arguments. got=2, want=1");
+        assert_error(
+            "push(1, 1)",
+            "argument to `push` not supported, got INTEGER",
+        );
+        assert_error("push([])", "wrong number of arguments. got=1, want=2");
+    }
+
+    #[test]
+    fn test_match_expressions() {
+        let tests = vec![
+            (
+                r#"match 5 { 1 => "one", n if n > 3 => n * 2, _ => 0 }"#,
+                "10",
+            ),
+            (r#"match 2 { 1 => "one", 2 => "two", _ => "many" }"#, "two"),
+            ("match -1 { -1 => true, _ => false }", "true"),
+            (
+                "match [1, [2, 3]] { [a] => a, [a, [b, c]] => a + b + c }",
+                "6",
+            ),
+            ("match [1, 2, 3] { [first, ...others] => others }", "[2, 3]"),
+            (r#"match {"k": 1} { {k: 2} => 1, {k} => k + 10 }"#, "11"),
+            (r#"match "x" { [a] => a, "x" => "yes" }"#, "yes"),
+            // A guard that fails moves on to the next arm.
+            ("match 1 { n if n > 1 => 1, n => n - 1 }", "0"),
+            // Arm bindings stay in their arm.
+            ("let n = 1; match 2 { n => n }; n", "1"),
+            (
+                "let f = fn(x) { match x { 0 => if (true) { return 7; }, _ => 1 }; 2 }; f(0)",
+                "7",
+            ),
+            (
+                "let f = fn(x) { match x { 0 => fn() { 7 }, _ => 1 } }; f(0)()",
+                "7",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        assert_error("match 7 { 1 => 1 }", "no match arm for 7");
+        assert_error(r#"match "a" { [x] => x }"#, "no match arm for a");
+        assert_error("match foo { _ => 1 }", "identifier not found: foo");
+        assert_error(
+            "match 1 { n if n + true => 1 }",
+            "type mismatch: INTEGER + BOOLEAN",
+        );
+        assert_error("match 1 { n => -true }", "unknown operator: -BOOLEAN");
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let input = r#"
+let two = "two";
+{
+  "one": 10 - 9,
+  two: 1 + 1,
+  "thr" + "ee": 6 / 2,
+  4: 4,
+  true: 5,
+  false: 6
+}"#;
+        let Object::Hash(pairs) = test_eval(input) else {
+            panic!("expected a hash");
+        };
+        let pairs: Vec<String> = pairs
+            .borrow()
+            .iter()
+            .map(|(k, v)| format!("{}: {v}", k.type_name()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                "STRING: 1",
+                "STRING: 2",
+                "STRING: 3",
+                "INTEGER: 4",
+                "BOOLEAN: 5",
+                "BOOLEAN: 6"
+            ]
+        );
+        assert_eq!(
+            test_eval(r#"{"a": 1, "b": 2, "a": 3}"#).to_string(),
+            "{a: 3, b: 2}"
+        );
+        assert_error("{[1]: 2}", "unusable as hash key: ARRAY");
+        assert_error(r#"{"a": foo}"#, "identifier not found: foo");
+    }
+
+    #[test]
+    fn test_hash_index_expressions() {
+        let tests = vec![
+            (r#"{"foo": 5}["foo"]"#, Some(5)),
+            (r#"{"foo": 5}["bar"]"#, None),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, Some(5)),
+            (r#"{}["foo"]"#, None),
+            ("{5: 5}[5]", Some(5)),
+            ("{true: 5}[true]", Some(5)),
+            ("{false: 5}[false]", Some(5)),
+            (r#"let h = {}; h["x"] = 1; h["x"] *= 3; h["x"]"#, Some(3)),
+        ];
+
+        for (input, expected) in tests {
+            match expected {
+                Some(expected) => assert_integer(input, expected),
+                None => assert_null(input),
+            }
+        }
+        assert_error(
+            r#"{"name": "Monkey"}[fn(x) { x }];"#,
+            "unusable as hash key: FUNCTION",
+        );
+        assert_error("let h = {}; h[[]] = 1", "unusable as hash key: ARRAY");
+    }
+
+    #[test]
+    fn test_let_annotations() {
+        assert_integer("let x: int = 5; x", 5);
+        assert_boolean("let f: fn(int) -> int = fn(x) { x }; f == f", true);
+        assert_boolean("let xs: [int] = [1, 2]; xs == xs", true);
+        let tests = vec![
+            (r#"let x: int = "a""#, "expected int for x, got STRING"),
+            ("let s: string = 1", "expected string for s, got INTEGER"),
+            ("let n: null = false", "expected null for n, got BOOLEAN"),
+            (
+                "let f: fn() -> int = 1",
+                "expected fn() -> int for f, got INTEGER",
+            ),
+            (
+                "let [a]: [int] = 1",
+                "expected [int] for the value, got INTEGER",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_error(input, expected);
+        }
+
+        // The parser only accepts known types, so one is renamed after
+        // parsing.
+        let lex = lexer::new("let x: [int] = [1]; x");
+        let mut program = parser::new(Rc::new(RefCell::new(lex))).parse_program();
+        let Statement::Let(s) = &mut program.statements[0] else {
+            panic!("not a let statement: {}", program.statements[0]);
+        };
+        let Some(TypeAnnotation::Array(element)) = &mut s.annotation else {
+            panic!("not an array annotation: {s}");
+        };
+        let TypeAnnotation::Named(name) = element.as_mut() else {
+            panic!("not a named annotation: {element}");
+        };
+        name.value = "float".to_string();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        match eval(&program, &env) {
+            Object::Error(message) => assert_eq!(message, "unknown type float"),
+            other => panic!("expected an unknown type error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_function_object() {
+        let Object::Function(function) = test_eval("fn(x) { x + 2; };") else {
+            panic!("expected a function");
+        };
+        assert_eq!(function.literal.signature(), "x");
+        assert_eq!(function.literal.body.to_string(), "{ x + 2; }");
+        assert_eq!(
+            test_eval("fn(a: int, b = 1) -> int { a }").to_string(),
+            "fn(a: int, b = 1) -> int { a; }"
+        );
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = vec![
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            ("let f = fn(x) { return x; x + 10; }; f(10);", 10),
+            (
+                "let f = fn(x) { let result = x + 10; return result; return 10; }; f(10);",
+                20,
+            ),
+            // A `return` only leaves the function it is in.
+            ("let f = fn() { return 1; }; f(); 2", 2),
+            ("let sub = fn(a, b) { a - b }; sub(b: 1, a: 3)", 2),
+            ("let f = fn(a, b = a * 2) { a + b }; f(1)", 3),
+            ("let f = fn(a, b = a * 2) { a + b }; f(1, 5)", 6),
+            ("let f = fn(a = 1, b = 2) { a - b }; f(b: 10)", -9),
+            ("5 |> fn(x, y) { x - y }(1)", 4),
+            ("let f = fn(a, ...rest) { rest[1] }; f(1, 2, 3)", 3),
+            ("let f = fn(a, b = 2, ...rest) { a + b }; f(1)", 3),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                610,
+            ),
+            // Functions can call the ones defined after them.
+            (
+                "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+                 let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+                 if (isEven(10)) { 1 } else { 0 }",
+                1,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let tests = vec![
+            (
+                "let f = fn(a, b) { a }; f(1, 2, 3)",
+                "too many arguments: expected at most 2, got 3",
+            ),
+            (
+                "let f = fn(a, b) { a }; f(1)",
+                "missing argument for parameter b",
+            ),
+            ("let f = fn(a) { a }; f(1, c: 2)", "unknown parameter c"),
+            (
+                "let f = fn(a, b) { a }; f(1, a: 2)",
+                "argument a given twice",
+            ),
+            (
+                "let f = fn(a, ...r) { r }; f()",
+                "missing argument for parameter a",
+            ),
+            ("let f = fn(a) { a }; f(x)", "identifier not found: x"),
+            ("let f = fn(a = x) { a }; f()", "identifier not found: x"),
+            (
+                "let f = fn() { -true }; f(); 1",
+                "unknown operator: -BOOLEAN",
+            ),
+            // Parameters are only visible inside the function.
+            ("let f = fn(a) { a }; f(1); a", "identifier not found: a"),
+        ];
+
+        for (input, expected) in tests {
+            assert_error(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_doc_builtin() {
+        let tests = vec![
+            ("/// Adds.\nlet add = fn(a, b) { a + b }; doc(add)", "Adds."),
+            (
+                "/// Adds two numbers.\n///\n/// Both must be integers.\nlet add = fn(a, b) { a + b };\ndoc(add)",
+                "Adds two numbers.\n\nBoth must be integers.",
+            ),
+            ("let f = /// Inline.\nfn() {}; doc(f)", "Inline."),
+            ("doc(fn() {})", "null"),
+            ("let doc = fn(x) { 1 }; doc(doc)", "1"),
+            ("doc", "builtin doc"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+        assert_error("doc(1)", "argument to `doc` not supported, got INTEGER");
+        assert_error("doc(doc)", "argument to `doc` not supported, got BUILTIN");
+        assert_error("doc()", "wrong number of arguments. got=0, want=1");
+        assert_error("doc(f: 1)", "doc does not take named arguments");
+    }
+
+    // Whole programs with the value they evaluate to or the error they stop
+    // with. The lowering to core is checked against them too.
+    pub(crate) const PROGRAMS: &[(&str, Result<&str, &str>)] = &[
+        // Operators.
+        ("5 + 5 * 2 - 10 / 2", Ok("10")),
+        ("-(3 - 10)", Ok("7")),
+        ("!5 == false", Ok("true")),
+        ("1 < 2 == true", Ok("true")),
+        (r#""mon" + "key""#, Ok("monkey")),
+        (r#""a" == "a""#, Ok("true")),
+        ("1 == true", Ok("false")),
+        ("1 / 0", Err("division by zero")),
+        ("5 + true", Err("type mismatch: INTEGER + BOOLEAN")),
+        ("true + false", Err("unknown operator: BOOLEAN + BOOLEAN")),
+        ("-true", Err("unknown operator: -BOOLEAN")),
+        ("foobar", Err("identifier not found: foobar")),
+        // Blocks and conditionals.
+        ("if (1 > 2) { 10 }", Ok("null")),
+        ("if (false) { 1 } else { 2 }", Ok("2")),
+        ("if (1) { 1; 2 }", Ok("2")),
+        ("if (true) { let a = 1 }", Ok("null")),
+        ("let f = fn(c) { if (c) { let v = 1 }; v }; f(true)", Ok("1")),
+        (
+            "let f = fn(c) { if (c) { let v = 1 }; v }; f(false)",
+            Err("identifier not found: v"),
+        ),
+        // Functions, closures and recursion.
+        ("let newAdder = fn(x) { fn(y) { x + y } }; newAdder(2)(3)", Ok("5")),
+        (
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            Ok("610"),
+        ),
+        (
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             [even(10), odd(7), even(3)]",
+            Ok("[true, true, false]"),
+        ),
+        ("let f = fn() { while (true) { return 5; } }; f()", Ok("5")),
+        ("return 1; 2", Ok("1")),
+        ("5 |> fn(x, y = 1) { x + y }", Ok("6")),
+        ("let len = 3; len", Ok("3")),
+        (r#"let f = fn() { let a = len("ab"); let len = 5; a + len }; f()"#, Ok("7")),
+        ("/// Adds.\nlet add = fn(a, b) { a + b }; doc(add)", Ok("Adds.")),
+        ("doc(fn() {})", Ok("null")),
+        // Parameters.
+        (
+            "let f = fn(a, b = a * 2, ...rest) { [a, b, rest] };
+             [f(1), f(1, 5), f(1, 2, 3, 4), f(b: 3, a: 1)]",
+            Ok("[[1, 2, []], [1, 5, []], [1, 2, [3, 4]], [1, 3, []]]"),
+        ),
+        ("let b = 10; fn(a = b, b = 1) { a + b }()", Ok("11")),
+        (
+            "fn(a, b) {}(1, 2, 3)",
+            Err("too many arguments: expected at most 2, got 3"),
+        ),
+        ("fn(a) {}(c: 1)", Err("unknown parameter c")),
+        ("fn(a, b) {}(1, 2, b: 3)", Err("argument b given twice")),
+        ("fn(a, b = 1) {}(b: 2)", Err("missing argument for parameter a")),
+        ("len(1, 2)", Err("wrong number of arguments. got=2, want=1")),
+        ("5(1)", Err("not a function: INTEGER")),
+        // Assignment.
+        (
+            "let counter = fn() { let n = 0; fn() { n += 1 } };
+             let c = counter(); c(); c(); c()",
+            Ok("3"),
+        ),
+        ("let x = 1; let f = fn() { x = 5 }; f(); x", Ok("5")),
+        ("let x = 1; x = x * 10", Ok("10")),
+        ("y = 1", Err("cannot assign to undeclared name y")),
+        ("let f = fn(a) { a = a + 1; a }; f(1)", Ok("2")),
+        (
+            "let x = 1; let f = fn() { let y = x; let x = 2; [y, x] }; f()",
+            Ok("[1, 2]"),
+        ),
+        (
+            "let x = 1; let f = fn() { x = 10; let x = 2; x }; [f(), x]",
+            Ok("[2, 10]"),
+        ),
+        ("let a = [1, 2]; a[0] += 5; a[-1] = 7; a", Ok("[6, 7]")),
+        (r#"let h = {}; h["x"] = 1; h["x"] *= 3; h"#, Ok("{x: 3}")),
+        (
+            "let i = 0; let a = [10, 20];
+             let next = fn() { i += 1; i - 1 };
+             a[next()] += 1; [a, i]",
+            Ok("[[11, 20], 1]"),
+        ),
+        ("let a = [1]; let b = a; b[0] = 2; a", Ok("[2]")),
+        // Loops.
+        (
+            "let i = 0; let s = 0;
+             while (i < 10) {
+               i += 1;
+               if (i / 2 * 2 == i) { continue; }
+               s += i;
+               if (i == 7) { break; }
+             }; [i, s]",
+            Ok("[7, 16]"),
+        ),
+        ("while (false) { 1 }", Ok("null")),
+        ("let s = 0; for (x in [1, 2, 3]) { s += x }; s", Ok("6")),
+        (r#"let out = ""; for (c in "abc") { out = c + out }; out"#, Ok("cba")),
+        (r#"let out = ""; for (k in {"a": 1, "b": 2}) { out = out + k }; out"#, Ok("ab")),
+        ("let s = 0; for (i in 1..=4) { s += i }; s", Ok("10")),
+        ("for (x in 5) {}", Err("cannot iterate over INTEGER")),
+        (
+            "let fs = [0, 0]; for (i in 0..2) { fs[i] = fn() { i } }; [fs[0](), fs[1]()]",
+            Ok("[0, 1]"),
+        ),
+        (
+            "let n = 0;
+             for (i in 0..3) { for (j in 0..3) { if (j == 1) { break } n += 1 } if (i == 1) { continue } n += 10 };
+             n",
+            Ok("23"),
+        ),
+        ("for (x in [1, 2]) { x = x * 2; x }", Ok("null")),
+        ("let f = fn() { for (x in 1..10) { if (x == 4) { return x } } }; f()", Ok("4")),
+        // Collections and ranges.
+        ("[1, 2, 3][-1]", Ok("3")),
+        ("[1, 2, 3][5]", Ok("null")),
+        ("[1, 2, 3][-2..]", Ok("[2, 3]")),
+        ("[1, 2, 3][0..=-1]", Ok("[1, 2, 3]")),
+        (r#""monkey"[..10]"#, Ok("monkey")),
+        (r#""monkey"[1..=2]"#, Ok("on")),
+        (r#"{"a": 1, "a": 2}["a"]"#, Ok("2")),
+        ("len(1..=5)", Ok("5")),
+        ("len(5..1)", Ok("0")),
+        ("[1, 2] |> len", Ok("2")),
+        ("{[1]: 2}", Err("unusable as hash key: ARRAY")),
+        // Destructuring.
+        ("let [a, b, ...r] = [1, 2, 3, 4]; [a, b, r]", Ok("[1, 2, [3, 4]]")),
+        (
+            r#"let {name, age: years} = {"name": "Ann", "age": 30}; [name, years]"#,
+            Ok("[Ann, 30]"),
+        ),
+        ("let [x, [y, _]] = [1, [2, 3]]; x + y", Ok("3")),
+        ("let [a] = 1", Err("cannot destructure INTEGER as an array")),
+        ("let [a, b] = [1, 2, 3]", Err("expected 2 elements to destructure, got 3")),
+        (
+            "let [a, b, ...r] = [1]",
+            Err("expected at least 2 elements to destructure, got 1"),
+        ),
+        ("let {a} = 1", Err("cannot destructure INTEGER as a hash")),
+        (r#"let {age} = {"name": 1}"#, Err(r#"key "age" not found in hash"#)),
+        ("let [1, a] = [2, 3]", Err("2 does not match pattern 1")),
+        // Match.
+        (
+            r#"match 5 { 1 => "one", n if n > 3 => n * 2, _ => 0 }"#,
+            Ok("10"),
+        ),
+        ("match [1, [2, 3]] { [a] => a, [a, [b, c]] => a + b + c }", Ok("6")),
+        (r#"match {"k": 1} { {k: 2} => 1, {k} => k + 10 }"#, Ok("11")),
+        (r#"match "x" { [a] => a, "x" => "yes" }"#, Ok("yes")),
+        ("match 7 { 1 => 1 }", Err("no match arm for 7")),
+        ("let n = 1; match 2 { n => n }; n", Ok("1")),
+        // Annotations.
+        ("let x: int = 5; x", Ok("5")),
+        (r#"let x: int = "a""#, Err("expected int for x, got STRING")),
+        (
+            "let f = fn(a: int, b: [int] = [1]) -> int { a + len(b) }; f(1)",
+            Ok("2"),
+        ),
+        (
+            r#"let f = fn(xs: [int]) { xs }; f([1, true])"#,
+            Err("expected int for an element of parameter xs, got BOOLEAN"),
+        ),
+        (
+            r#"let f = fn(h: {string: int}) { h }; f({"a": "b"})"#,
+            Err("expected int for a value of parameter h, got STRING"),
+        ),
+        (
+            "let f = fn(n) -> bool { return n; }; f(1)",
+            Err("expected bool for the result, got INTEGER"),
+        ),
+        ("let g: fn(int) -> int = len; g", Ok("builtin len")),
+        ("let g: fn(int) -> int = 1", Err("expected fn(int) -> int for g, got INTEGER")),
+        (
+            "let [a]: [string] = [1]",
+            Err("expected string for an element of the value, got INTEGER"),
+        ),
+        // Macros are expanded before evaluation.
+        ("macro(x) { x }", Err("cannot evaluate a macro literal")),
+    ];
+
+    #[test]
+    fn test_programs() {
+        for (input, expected) in PROGRAMS {
+            let result = match test_eval(input) {
+                Object::Error(message) => Err(message),
+                value => Ok(value.to_string()),
+            };
+            let expected = expected.map(str::to_string).map_err(str::to_string);
+            assert_eq!(result, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_quote_unquote() {
+        let tests = vec![
+            ("quote(5)", "5"),
+            ("quote(5 + 8)", "5 + 8"),
+            ("quote(foobar)", "foobar"),
+            ("quote(foobar + barfoo)", "foobar + barfoo"),
+            ("quote(unquote(4))", "4"),
+            ("quote(unquote(4 + 4))", "8"),
+            ("quote(8 + unquote(4 + 4))", "8 + 8"),
+            ("quote(unquote(4 + 4) + 8)", "8 + 8"),
+            ("let foobar = 8; quote(foobar)", "foobar"),
+            ("let foobar = 8; quote(unquote(foobar))", "8"),
+            ("quote(unquote(-3))", "-3"),
+            ("quote(unquote(true))", "true"),
+            ("quote(unquote(true == false))", "false"),
+            ("quote(unquote(quote(4 + 4)))", "4 + 4"),
+            (
+                "let quotedInfixExpression = quote(4 + 4);
+                 quote(unquote(4 + 4) + unquote(quotedInfixExpression))",
+                "8 + (4 + 4)",
+            ),
+            (r#"quote(unquote([1, "a"]))"#, r#"[1, "a"]"#),
+            ("quote(quote(unquote(1 + 1)))", "quote(2)"),
+            ("let quote = fn(x) { x * 2 }; quote(3)", "6"),
+        ];
+
+        for (input, expected) in tests {
+            match test_eval(input) {
+                Object::Quote(code) => assert_eq!(code.to_string(), expected, "input: {input}"),
+                Object::Integer(n) => assert_eq!(n.to_string(), expected, "input: {input}"),
+                other => panic!("object is not Quote. got={other:?}, input: {input}"),
+            }
+        }
+        assert_error("quote(unquote(fn() { 1 }))", "cannot unquote FUNCTION");
+        assert_error("quote(unquote(x))", "identifier not found: x");
+        assert_error("quote(1, 2)", "wrong number of arguments. got=2, want=1");
+        assert_error("quote(a: 1)", "quote does not take named arguments");
+    }
+
+    #[test]
+    fn test_rest_parameters() {
+        let tests = vec![
+            (
+                "let f = fn(a, b = a * 2, ...rest) { [a, b, rest] };
+                 [f(1), f(1, 5), f(1, 2, 3, 4), f(b: 3, a: 1)]",
+                "[[1, 2, []], [1, 5, []], [1, 2, [3, 4]], [1, 3, []]]",
+            ),
+            ("fn(...all) { all }()", "[]"),
+            ("fn(...all) { all }(1, true)", "[1, true]"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parameter_annotations() {
+        assert_integer("let f = fn(a: int, b: int = 1) -> int { a + b }; f(2)", 3);
+        let tests = vec![
+            (
+                r#"let f = fn(a: int) { a }; f("x")"#,
+                "expected int for parameter a, got STRING",
+            ),
+            (
+                r#"let f = fn(a: int = "x") { a }; f()"#,
+                "expected int for parameter a, got STRING",
+            ),
+            (
+                "let f = fn(g: fn(int) -> int) { g(1) }; f(1)",
+                "expected fn(int) -> int for parameter g, got INTEGER",
+            ),
+            (
+                "let f = fn(xs: [int]) { xs }; f([1, true])",
+                "expected int for an element of parameter xs, got BOOLEAN",
+            ),
+            (
+                r#"let f = fn(h: {string: int}) { h }; f({"a": "b"})"#,
+                "expected int for a value of parameter h, got STRING",
+            ),
+            (
+                "let f = fn(h: {string: int}) { h }; f({1: 1})",
+                "expected string for a key of parameter h, got INTEGER",
+            ),
+            (
+                "let f = fn(xs: [[int]]) { xs }; f([[1], 2])",
+                "expected [int] for an element of parameter xs, got INTEGER",
+            ),
+            (
+                "let f = fn(n) -> bool { if (n) { return 1; } true }; f(true)",
+                "expected bool for the result, got INTEGER",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_error(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let test = || {
+            let f = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+            assert_integer(&format!("{f} f({})", MAX_CALL_DEPTH - 1), 4_999);
+            assert_error(
+                &format!("{f} f({})", MAX_CALL_DEPTH),
+                "maximum call depth exceeded",
+            );
+            // The calls that failed are no longer counted.
+            assert_integer(&format!("{f} f(2)"), 2);
+        };
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            (
+                "let newAdder = fn(x) { fn(y) { x + y }; };
+                 let addTwo = newAdder(2); addTwo(2);",
+                4,
+            ),
+            (
+                "let newAdder = fn(x) { fn(y) { x + y } }; newAdder(2)(3)",
+                5,
+            ),
+            (
+                "let add = fn(a, b) { a + b };
+                 let applyFunc = fn(a, b, func) { func(a, b) };
+                 applyFunc(2, 2, add);",
+                4,
+            ),
+            (
+                "let compose = fn(f, g) { fn(x) { g(f(x)) } };
+                 let inc = fn(x) { x + 1 };
+                 compose(inc, fn(x) { x * 10 })(4)",
+                50,
+            ),
+            // A closure sees the scope it was defined in, not the caller's.
+            (
+                "let x = 1; let get = fn() { x }; let call = fn(x) { get() }; call(99)",
+                1,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_counter_closures() {
+        let counter = "
+let newCounter = fn() {
+  let count = 0;
+  fn() { count += 1; count }
+};";
+        assert_integer(&format!("{counter} let c = newCounter(); c(); c(); c()"), 3);
+        // Each call of newCounter makes a count of its own.
+        assert_integer(
+            &format!("{counter} let a = newCounter(); let b = newCounter(); a(); a(); b(); a() * 10 + b()"),
+            32,
+        );
+    }
+
+    #[test]
+    fn test_enclosing_environments() {
+        let input = "
+let first = 10;
+let second = 10;
+let third = 10;
+
+let ourFunction = fn(first) {
+  let second = 20;
+
+  first + second + third;
+};
+
+ourFunction(20) + first + second;";
+        assert_integer(input, 70);
+    }
+
+    #[test]
+    fn test_assignments() {
+        let tests = vec![
+            ("let x = 1; x = 2; x", 2),
+            ("let x = 1; x += 2", 3),
+            ("let x = 10; x -= 2; x *= 3; x /= 4; x", 6),
+            // Assignment updates the nearest binding, even outside the
+            // function.
+            ("let x = 1; let f = fn() { x = 5 }; f(); x", 5),
+            ("let x = 1; let f = fn(x) { x = 5 }; f(2); x", 1),
+        ];
+
+        for (input, expected) in tests {
+            assert_integer(input, expected);
+        }
+        assert_error("y = 1", "cannot assign to undeclared name y");
+        assert_error("let x = 1; x += true", "type mismatch: INTEGER + BOOLEAN");
+        assert_error("y += 1", "identifier not found: y");
+    }
+}
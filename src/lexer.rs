@@ -1,19 +1,33 @@
-use crate::token::{lookup_ident, Token, TokenType};
+use std::collections::HashMap;
+
+use crate::token::{lookup_ident, Span, Token, TokenType};
 
 pub struct Lexer<'a> {
     input: &'a str,
+    // Byte offsets of `ch` and of the character after it.
     position: usize,
     read_position: usize,
     ch: char,
+    // 1-based location of `ch`, used to build token spans.
+    line: usize,
+    column: usize,
+    // `///` lines read since the last token, and the doc comments found so
+    // far keyed by the offset of the token they precede.
+    doc_lines: Vec<String>,
+    docs: HashMap<usize, String>,
 }
 
 // Create new lexer instance.
-pub fn new(input: &str) -> Lexer {
+pub fn new(input: &str) -> Lexer<'_> {
     let mut lex = Lexer {
         input,
         position: 0,
         read_position: 0,
         ch: char::default(),
+        line: 1,
+        column: 0,
+        doc_lines: Vec::new(),
+        docs: HashMap::new(),
     };
     lex.read_char();
     lex
@@ -22,18 +36,37 @@ pub fn new(input: &str) -> Lexer {
 impl<'a> Lexer<'a> {
     // Read the next character and move the pointer forward.
     fn read_char(&mut self) {
-        if self.read_position < self.input.len() {
-            self.ch = self
-                .input
-                .chars()
-                .nth(self.read_position)
-                .expect("Invalid input index");
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            // EOF
-            self.ch = '\x00';
+            self.column += 1;
         }
+
         self.position = self.read_position;
-        self.read_position += 1;
+        match self
+            .input
+            .get(self.read_position..)
+            .and_then(|rest| rest.chars().next())
+        {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => {
+                // EOF
+                self.ch = '\x00';
+                self.read_position = self.input.len() + 1;
+            }
+        }
+    }
+
+    // Method to look at the next character without consuming it.
+    fn peek_char(&self) -> char {
+        self.input
+            .get(self.read_position..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\x00')
     }
 
     // Method to read identifier.
@@ -60,150 +93,195 @@ impl<'a> Lexer<'a> {
         &self.input[pos..self.position]
     }
 
-    // Method to skip the space by moving the pointer forward.
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() {
-            self.read_char()
+    // Method to read a string literal. The current character is the opening
+    // quote; reading stops at the closing quote or at the end of input.
+    fn read_string(&mut self) -> String {
+        let mut s = String::new();
+        loop {
+            self.read_char();
+            if self.ch == '"' || self.ch == '\x00' {
+                break;
+            }
+            s.push(self.ch);
+        }
+        s
+    }
+
+    // Method to skip the space and comments by moving the pointer forward.
+    // Reports whether a line break was skipped.
+    fn skip_whitespace(&mut self) -> bool {
+        let mut newline = false;
+        loop {
+            if self.ch.is_whitespace() {
+                newline |= self.ch == '\n';
+                self.read_char()
+            } else if self.ch == '/' && self.peek_char() == '/' {
+                self.read_comment();
+            } else {
+                return newline;
+            }
+        }
+    }
+
+    // Method to read a `//` comment up to the end of the line. A comment
+    // starting with exactly three slashes is a doc comment: its text, minus
+    // one leading space, is kept for the next token.
+    fn read_comment(&mut self) {
+        let pos = self.position;
+        while self.ch != '\n' && self.ch != '\x00' {
+            self.read_char();
         }
+        let comment = self.input[pos..self.position].trim_end_matches('\r');
+
+        if let Some(text) = comment.strip_prefix("///") {
+            if !text.starts_with('/') {
+                let text = text.strip_prefix(' ').unwrap_or(text);
+                self.doc_lines.push(text.to_string());
+            }
+        }
+    }
+
+    // Take the doc comment written right before the token starting at
+    // `offset`, if there is one.
+    pub fn take_doc(&mut self, offset: usize) -> Option<String> {
+        self.docs.remove(&offset)
     }
 
-    // Method to tokenize the next token.
+    // Method to tokenize the next token and record where it was found.
     pub fn next_token(&mut self) -> Token {
-        let tok: Token;
+        let newline_before = self.skip_whitespace();
 
-        self.skip_whitespace();
+        let start = self.position;
+        let line = self.line;
+        let column = self.column;
+        if !self.doc_lines.is_empty() {
+            let doc = self.doc_lines.join("\n");
+            self.doc_lines.clear();
+            self.docs.insert(start, doc);
+        }
+
+        let mut tok = self.read_token();
+        tok.span = Span {
+            start,
+            end: self.position,
+            line,
+            column,
+        };
+        tok.newline_before = newline_before;
+        tok
+    }
+
+    // Method to read the token starting at the current character.
+    fn read_token(&mut self) -> Token {
+        let tok: Token;
 
         'outer: {
             match self.ch {
                 '=' => {
-                    if let Some(c) = self.input.chars().nth(self.read_position) {
-                        if c == '=' {
-                            tok = Token {
-                                t_type: TokenType::Eq,
-                                literal: "==".to_string(),
-                            };
-                            self.read_char();
-                            break 'outer;
-                        }
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::Eq, "==");
+                        self.read_char();
+                        break 'outer;
                     }
-                    tok = Token {
-                        t_type: TokenType::Assign,
-                        literal: "=".to_string(),
+                    if self.peek_char() == '>' {
+                        tok = Token::new(TokenType::FatArrow, "=>");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Assign, "=")
                 }
-                ';' => {
-                    tok = Token {
-                        t_type: TokenType::Semicolon,
-                        literal: ";".to_string(),
+                ';' => tok = Token::new(TokenType::Semicolon, ";"),
+                ':' => tok = Token::new(TokenType::Colon, ":"),
+                '.' => {
+                    if self.peek_char() != '.' {
+                        tok = Token::new(TokenType::Illegal, "");
+                        break 'outer;
                     }
-                }
-                '(' => {
-                    tok = Token {
-                        t_type: TokenType::Lparen,
-                        literal: "(".to_string(),
+                    self.read_char();
+                    if self.peek_char() == '.' {
+                        tok = Token::new(TokenType::Ellipsis, "...");
+                        self.read_char();
+                        break 'outer;
                     }
-                }
-                ')' => {
-                    tok = Token {
-                        t_type: TokenType::Rparen,
-                        literal: ")".to_string(),
-                    }
-                }
-                ',' => {
-                    tok = Token {
-                        t_type: TokenType::Comma,
-                        literal: ",".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::DotDotEq, "..=");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::DotDot, "..")
                 }
+                '"' => tok = Token::new(TokenType::String, &self.read_string()),
+                '(' => tok = Token::new(TokenType::Lparen, "("),
+                ')' => tok = Token::new(TokenType::Rparen, ")"),
+                ',' => tok = Token::new(TokenType::Comma, ","),
                 '+' => {
-                    tok = Token {
-                        t_type: TokenType::Plus,
-                        literal: "+".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::PlusAssign, "+=");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Plus, "+")
                 }
                 '-' => {
-                    tok = Token {
-                        t_type: TokenType::Minus,
-                        literal: "-".to_string(),
-                    }
-                }
-                '{' => {
-                    tok = Token {
-                        t_type: TokenType::Lbrace,
-                        literal: "{".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::MinusAssign, "-=");
+                        self.read_char();
+                        break 'outer;
                     }
-                }
-                '}' => {
-                    tok = Token {
-                        t_type: TokenType::Rbrace,
-                        literal: "}".to_string(),
+                    if self.peek_char() == '>' {
+                        tok = Token::new(TokenType::Arrow, "->");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Minus, "-")
                 }
-                '<' => {
-                    tok = Token {
-                        t_type: TokenType::Lt,
-                        literal: "<".to_string(),
-                    }
-                }
-                '>' => {
-                    tok = Token {
-                        t_type: TokenType::Gt,
-                        literal: ">".to_string(),
+                '{' => tok = Token::new(TokenType::Lbrace, "{"),
+                '}' => tok = Token::new(TokenType::Rbrace, "}"),
+                '[' => tok = Token::new(TokenType::Lbracket, "["),
+                ']' => tok = Token::new(TokenType::Rbracket, "]"),
+                '|' => {
+                    if self.peek_char() == '>' {
+                        tok = Token::new(TokenType::Pipe, "|>");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Illegal, "")
                 }
+                '<' => tok = Token::new(TokenType::Lt, "<"),
+                '>' => tok = Token::new(TokenType::Gt, ">"),
                 '!' => {
-                    if let Some(c) = self.input.chars().nth(self.read_position) {
-                        if c == '=' {
-                            tok = Token {
-                                t_type: TokenType::NotEq,
-                                literal: "!=".to_string(),
-                            };
-                            self.read_char();
-                            break 'outer;
-                        }
-                    }
-                    tok = Token {
-                        t_type: TokenType::Bang,
-                        literal: "!".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::NotEq, "!=");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Bang, "!")
                 }
                 '/' => {
-                    tok = Token {
-                        t_type: TokenType::Slash,
-                        literal: "/".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::SlashAssign, "/=");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Slash, "/")
                 }
                 '*' => {
-                    tok = Token {
-                        t_type: TokenType::Asterisk,
-                        literal: "*".to_string(),
-                    }
-                }
-                '\x00' => {
-                    tok = Token {
-                        t_type: TokenType::Eof,
-                        literal: "".to_string(),
+                    if self.peek_char() == '=' {
+                        tok = Token::new(TokenType::AsteriskAssign, "*=");
+                        self.read_char();
+                        break 'outer;
                     }
+                    tok = Token::new(TokenType::Asterisk, "*")
                 }
+                '\x00' => tok = Token::new(TokenType::Eof, ""),
                 _ => {
                     if self.ch.is_alphabetic() || self.ch == '_' {
                         let literal = self.read_identifier();
-                        tok = Token {
-                            literal: literal.to_string(),
-                            t_type: lookup_ident(literal),
-                        };
-                        return tok;
+                        return Token::new(lookup_ident(literal), literal);
                     } else if self.ch.is_ascii_digit() {
-                        tok = Token {
-                            t_type: TokenType::Int,
-                            literal: self.read_number().to_string(),
-                        };
-                        return tok;
+                        return Token::new(TokenType::Int, self.read_number());
                     } else {
-                        tok = Token {
-                            t_type: TokenType::Illegal,
-                            literal: "".to_string(),
-                        }
+                        tok = Token::new(TokenType::Illegal, "")
                     }
                 }
             };
@@ -240,301 +318,131 @@ mod tests {
 
             10 == 10;
             10 != 9;
+            [1, 2];
+            x += 1; x -= 1; x *= 2; x /= 2;
+            "foobar"
+            "foo bar"
+            {"foo": "bar"}
+            macro(x, y) { x + y; };
             "#;
 
         let tests: Vec<Token> = vec![
-            Token {
-                t_type: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "five".to_string(),
-            },
-            Token {
-                t_type: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "ten".to_string(),
-            },
-            Token {
-                t_type: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "add".to_string(),
-            },
-            Token {
-                t_type: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t_type: TokenType::Function,
-                literal: "fn".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lparen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "x".to_string(),
-            },
-            Token {
-                t_type: TokenType::Comma,
-                literal: ",".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "y".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rparen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lbrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "x".to_string(),
-            },
-            Token {
-                t_type: TokenType::Plus,
-                literal: "+".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "y".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rbrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "result".to_string(),
-            },
-            Token {
-                t_type: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "add".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lparen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "five".to_string(),
-            },
-            Token {
-                t_type: TokenType::Comma,
-                literal: ",".to_string(),
-            },
-            Token {
-                t_type: TokenType::Ident,
-                literal: "ten".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rparen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Bang,
-                literal: "!".to_string(),
-            },
-            Token {
-                t_type: TokenType::Minus,
-                literal: "-".to_string(),
-            },
-            Token {
-                t_type: TokenType::Slash,
-                literal: "/".to_string(),
-            },
-            Token {
-                t_type: TokenType::Asterisk,
-                literal: "*".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lt,
-                literal: "<".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::Gt,
-                literal: ">".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::If,
-                literal: "if".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lparen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lt,
-                literal: "<".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rparen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lbrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t_type: TokenType::Return,
-                literal: "return".to_string(),
-            },
-            Token {
-                t_type: TokenType::True,
-                literal: "true".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rbrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t_type: TokenType::Else,
-                literal: "else".to_string(),
-            },
-            Token {
-                t_type: TokenType::Lbrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t_type: TokenType::Return,
-                literal: "return".to_string(),
-            },
-            Token {
-                t_type: TokenType::False,
-                literal: "false".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Rbrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::Eq,
-                literal: "==".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t_type: TokenType::NotEq,
-                literal: "!=".to_string(),
-            },
-            Token {
-                t_type: TokenType::Int,
-                literal: "9".to_string(),
-            },
-            Token {
-                t_type: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "five"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "ten"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "add"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Function, "fn"),
+            Token::new(TokenType::Lparen, "("),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Comma, ","),
+            Token::new(TokenType::Ident, "y"),
+            Token::new(TokenType::Rparen, ")"),
+            Token::new(TokenType::Lbrace, "{"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Plus, "+"),
+            Token::new(TokenType::Ident, "y"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Rbrace, "}"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "result"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Ident, "add"),
+            Token::new(TokenType::Lparen, "("),
+            Token::new(TokenType::Ident, "five"),
+            Token::new(TokenType::Comma, ","),
+            Token::new(TokenType::Ident, "ten"),
+            Token::new(TokenType::Rparen, ")"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Bang, "!"),
+            Token::new(TokenType::Minus, "-"),
+            Token::new(TokenType::Slash, "/"),
+            Token::new(TokenType::Asterisk, "*"),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Lt, "<"),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Gt, ">"),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::If, "if"),
+            Token::new(TokenType::Lparen, "("),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Lt, "<"),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Rparen, ")"),
+            Token::new(TokenType::Lbrace, "{"),
+            Token::new(TokenType::Return, "return"),
+            Token::new(TokenType::True, "true"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Rbrace, "}"),
+            Token::new(TokenType::Else, "else"),
+            Token::new(TokenType::Lbrace, "{"),
+            Token::new(TokenType::Return, "return"),
+            Token::new(TokenType::False, "false"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Rbrace, "}"),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Eq, "=="),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::NotEq, "!="),
+            Token::new(TokenType::Int, "9"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Lbracket, "["),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Comma, ","),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::Rbracket, "]"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::PlusAssign, "+="),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::MinusAssign, "-="),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::AsteriskAssign, "*="),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::SlashAssign, "/="),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::String, "foobar"),
+            Token::new(TokenType::String, "foo bar"),
+            Token::new(TokenType::Lbrace, "{"),
+            Token::new(TokenType::String, "foo"),
+            Token::new(TokenType::Colon, ":"),
+            Token::new(TokenType::String, "bar"),
+            Token::new(TokenType::Rbrace, "}"),
+            Token::new(TokenType::Macro, "macro"),
+            Token::new(TokenType::Lparen, "("),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Comma, ","),
+            Token::new(TokenType::Ident, "y"),
+            Token::new(TokenType::Rparen, ")"),
+            Token::new(TokenType::Lbrace, "{"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Plus, "+"),
+            Token::new(TokenType::Ident, "y"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Rbrace, "}"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Eof, ""),
         ];
 
         let mut l = new(input);
@@ -546,4 +454,233 @@ mod tests {
             assert_eq!(tok.literal, tkn.literal);
         }
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  x == 10;";
+
+        let expected = vec![
+            (TokenType::Let, 0, 3, 1, 1),
+            (TokenType::Ident, 4, 5, 1, 5),
+            (TokenType::Assign, 6, 7, 1, 7),
+            (TokenType::Int, 8, 10, 1, 9),
+            (TokenType::Semicolon, 10, 11, 1, 11),
+            (TokenType::Ident, 14, 15, 2, 3),
+            (TokenType::Eq, 16, 18, 2, 5),
+            (TokenType::Int, 19, 21, 2, 8),
+            (TokenType::Semicolon, 21, 22, 2, 10),
+        ];
+
+        let mut l = new(input);
+
+        for (t_type, start, end, line, column) in expected {
+            let tok = l.next_token();
+
+            assert_eq!(tok.t_type, t_type);
+            assert_eq!(
+                tok.span,
+                Span {
+                    start,
+                    end,
+                    line,
+                    column
+                },
+                "span of {:?}",
+                tok.literal
+            );
+        }
+    }
+
+    #[test]
+    fn test_dots() {
+        let mut l = new("[a, ...b] 1..2 ..=c .");
+
+        for t_type in [
+            TokenType::Lbracket,
+            TokenType::Ident,
+            TokenType::Comma,
+            TokenType::Ellipsis,
+            TokenType::Ident,
+            TokenType::Rbracket,
+            TokenType::Int,
+            TokenType::DotDot,
+            TokenType::Int,
+            TokenType::DotDotEq,
+            TokenType::Ident,
+            TokenType::Illegal,
+            TokenType::Eof,
+        ] {
+            assert_eq!(l.next_token().t_type, t_type);
+        }
+    }
+
+    #[test]
+    fn test_annotation_tokens() {
+        let mut l = new("let x: int = fn(a: [int]) -> bool {} - >");
+
+        for (t_type, literal) in [
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "x"),
+            (TokenType::Colon, ":"),
+            (TokenType::Ident, "int"),
+            (TokenType::Assign, "="),
+            (TokenType::Function, "fn"),
+            (TokenType::Lparen, "("),
+            (TokenType::Ident, "a"),
+            (TokenType::Colon, ":"),
+            (TokenType::Lbracket, "["),
+            (TokenType::Ident, "int"),
+            (TokenType::Rbracket, "]"),
+            (TokenType::Rparen, ")"),
+            (TokenType::Arrow, "->"),
+            (TokenType::Ident, "bool"),
+            (TokenType::Lbrace, "{"),
+            (TokenType::Rbrace, "}"),
+            (TokenType::Minus, "-"),
+            (TokenType::Gt, ">"),
+        ] {
+            let tok = l.next_token();
+            assert_eq!(tok.t_type, t_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let mut l = new("match x { _ => _a, y=>1 }");
+
+        for (t_type, literal) in [
+            (TokenType::Match, "match"),
+            (TokenType::Ident, "x"),
+            (TokenType::Lbrace, "{"),
+            (TokenType::Underscore, "_"),
+            (TokenType::FatArrow, "=>"),
+            (TokenType::Ident, "_a"),
+            (TokenType::Comma, ","),
+            (TokenType::Ident, "y"),
+            (TokenType::FatArrow, "=>"),
+            (TokenType::Int, "1"),
+            (TokenType::Rbrace, "}"),
+        ] {
+            let tok = l.next_token();
+            assert_eq!(tok.t_type, t_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn test_newline_before() {
+        let mut l = new("a b\n  c\n\nd \te");
+
+        for (literal, newline_before) in [
+            ("a", false),
+            ("b", false),
+            ("c", true),
+            ("d", true),
+            ("e", false),
+        ] {
+            let tok = l.next_token();
+            assert_eq!(tok.literal, literal);
+            assert_eq!(tok.newline_before, newline_before, "token {literal:?}");
+        }
+    }
+
+    #[test]
+    fn test_pipe() {
+        let mut l = new("xs |> f | g");
+
+        for t_type in [
+            TokenType::Ident,
+            TokenType::Pipe,
+            TokenType::Ident,
+            TokenType::Illegal,
+            TokenType::Ident,
+            TokenType::Eof,
+        ] {
+            assert_eq!(l.next_token().t_type, t_type);
+        }
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let mut l = new("let café = \"naïve ✓\"; größe € 1");
+
+        for (t_type, literal) in [
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "café"),
+            (TokenType::Assign, "="),
+            (TokenType::String, "naïve ✓"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Ident, "größe"),
+            (TokenType::Illegal, ""),
+            (TokenType::Int, "1"),
+            (TokenType::Eof, ""),
+        ] {
+            assert_eq!(l.next_token(), Token::new(t_type, literal));
+        }
+
+        // Spans are byte offsets into the input.
+        let mut l = new("é + x");
+        assert_eq!(l.next_token().span.end, 2);
+        let plus = l.next_token();
+        assert_eq!((plus.span.start, plus.span.column), (3, 3));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input =
+            "// a comment\nx // trailing\n/// Adds\n///two\n//// not a doc\nlet y = 1; ///\n";
+        let mut l = new(input);
+
+        let x = l.next_token();
+        assert_eq!(x, Token::new(TokenType::Ident, "x"));
+        let let_tok = l.next_token();
+        assert_eq!(let_tok, Token::new(TokenType::Let, "let"));
+        assert!(let_tok.newline_before);
+        for t_type in [
+            TokenType::Ident,
+            TokenType::Assign,
+            TokenType::Int,
+            TokenType::Semicolon,
+        ] {
+            assert_eq!(l.next_token().t_type, t_type);
+        }
+        let eof = l.next_token();
+        assert_eq!(eof.t_type, TokenType::Eof);
+
+        assert_eq!(l.take_doc(x.span.start), None);
+        assert_eq!(
+            l.take_doc(let_tok.span.start),
+            Some("Adds\ntwo".to_string())
+        );
+        assert_eq!(l.take_doc(let_tok.span.start), None);
+        assert_eq!(l.take_doc(eof.span.start), Some(String::new()));
+
+        // A lone slash is still division.
+        let mut l = new("a / b /= c");
+        for t_type in [
+            TokenType::Ident,
+            TokenType::Slash,
+            TokenType::Ident,
+            TokenType::SlashAssign,
+            TokenType::Ident,
+        ] {
+            assert_eq!(l.next_token().t_type, t_type);
+        }
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let mut l = new("while for in break continue");
+
+        for t_type in [
+            TokenType::While,
+            TokenType::For,
+            TokenType::In,
+            TokenType::Break,
+            TokenType::Continue,
+        ] {
+            assert_eq!(l.next_token().t_type, t_type);
+        }
+    }
 }
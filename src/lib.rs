@@ -0,0 +1,15 @@
+pub mod arena;
+pub mod ast;
+pub mod core;
+pub mod docgen;
+pub mod dump;
+pub mod evaluator;
+pub mod lexer;
+pub mod macro_expansion;
+pub mod optimizer;
+pub mod parser;
+pub mod repl;
+pub mod resolver;
+pub mod tailcall;
+pub mod token;
+pub mod typecheck;
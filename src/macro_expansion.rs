@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    fold_expression, CallExpression, Expression, Fold, MacroLiteral, Pattern, Program, Statement,
+};
+use crate::evaluator::{self, Object};
+
+// Macros defined by a program, by name.
+pub type Macros = HashMap<String, MacroLiteral>;
+
+// Remove the top-level `let name = macro(...) { ... };` statements from
+// `program` and return the macros they define. A later definition of a name
+// replaces an earlier one.
+pub fn define_macros(program: &mut Program) -> Macros {
+    let mut macros = Macros::new();
+
+    program.statements.retain(|stmt| match stmt {
+        Statement::Let(s) => match (&s.pattern, &s.value) {
+            (Pattern::Identifier(name), Expression::Macro(m)) => {
+                macros.insert(name.value.clone(), m.clone());
+                false
+            }
+            _ => true,
+        },
+        _ => true,
+    });
+
+    macros
+}
+
+// Replace every call of one of `macros` in `program` with the code it
+// expands to. Arguments are expanded before the call that receives them, and
+// the code a macro expands to is not expanded again.
+pub fn expand_macros(program: Program, macros: &Macros) -> Result<Program, Vec<String>> {
+    // Without macros there is nothing to expand, so the program is not
+    // rebuilt.
+    if macros.is_empty() {
+        return Ok(program);
+    }
+    let mut expander = Expander {
+        macros,
+        errors: Vec::new(),
+    };
+    let program = expander.fold_program(program);
+
+    if expander.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(expander.errors)
+    }
+}
+
+struct Expander<'a> {
+    macros: &'a Macros,
+    errors: Vec<String>,
+}
+
+impl Fold for Expander<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match fold_expression(self, expr) {
+            Expression::Call(call) => match self.macro_called(&call) {
+                Some((name, m)) => self.expand(&name, m, call),
+                None => Expression::Call(call),
+            },
+            expr => expr,
+        }
+    }
+}
+
+impl<'a> Expander<'a> {
+    fn macro_called(&self, call: &CallExpression) -> Option<(String, &'a MacroLiteral)> {
+        match call.function.as_ref() {
+            Expression::Identifier(name) => self
+                .macros
+                .get(&name.value)
+                .map(|m| (name.value.clone(), m)),
+            _ => None,
+        }
+    }
+
+    // Evaluate the body of the macro with the arguments quoted; the quote it
+    // evaluates to is the expansion. On error the call is left as it is.
+    fn expand(&mut self, name: &str, m: &MacroLiteral, call: CallExpression) -> Expression {
+        if !call.named_arguments.is_empty() {
+            self.errors.push(format!(
+                "macro {name} does not take named arguments at {}",
+                call.token.span
+            ));
+            return Expression::Call(call);
+        }
+        if call.arguments.len() != m.parameters.len() {
+            self.errors.push(format!(
+                "wrong number of arguments to macro {name}: want={}, got={} at {}",
+                m.parameters.len(),
+                call.arguments.len(),
+                call.token.span
+            ));
+            return Expression::Call(call);
+        }
+
+        match evaluator::eval_macro_body(m, &call.arguments) {
+            Object::Quote(code) => *code,
+            Object::Error(message) => {
+                self.errors
+                    .push(format!("{message} in macro {name} at {}", call.token.span));
+                Expression::Call(call)
+            }
+            value => {
+                self.errors.push(format!(
+                    "macro {name} must return quote(...), got {} at {}",
+                    value.type_name(),
+                    call.token.span
+                ));
+                Expression::Call(call)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    #[test]
+    fn test_define_macros() {
+        let mut program = parse(
+            "let number = 1;
+             let function = fn(x, y) { x + y };
+             let mymacro = macro(x, y) { x + y; };
+             if (true) { let inner = macro() { 1 } }",
+        );
+
+        let macros = define_macros(&mut program);
+
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(program.statements[0].to_string(), "let number = 1;");
+        assert_eq!(
+            program.statements[1].to_string(),
+            "let function = fn(x, y) { x + y; };"
+        );
+        assert_eq!(macros.len(), 1);
+
+        let m = &macros["mymacro"];
+        let params: Vec<&str> = m.parameters.iter().map(|p| p.value.as_str()).collect();
+        assert_eq!(params, ["x", "y"]);
+        assert_eq!(m.body.to_string(), "{ x + y; }");
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let tests = vec![
+            (
+                "let infixExpression = macro() { quote(1 + 2); };
+                 infixExpression();",
+                "(1 + 2)",
+            ),
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); };
+                 reverse(2 + 2, 10 - 5);",
+                "(10 - 5) - (2 + 2)",
+            ),
+            (
+                r#"let unless = macro(condition, consequence, alternative) {
+                     quote(if (!(unquote(condition))) {
+                       unquote(consequence);
+                     } else {
+                       unquote(alternative);
+                     });
+                   };
+                   unless(10 > 5, puts("not greater"), puts("greater"));"#,
+                r#"if (!(10 > 5)) { puts("not greater") } else { puts("greater") }"#,
+            ),
+            // Arguments are expanded first; quoted names stay as they are.
+            (
+                "let twice = macro(x) { return quote(unquote(x) + unquote(x) + x); };
+                 twice(twice(y));",
+                "y + y + x + (y + y + x) + x",
+            ),
+            (
+                "let m = macro() { quote(1) }; f(m(), fn() { m() })",
+                "f(1, fn() { 1 })",
+            ),
+            // The body is evaluated, so it can compute the code it returns.
+            (
+                "let m = macro(a) { let b = a; quote(unquote(b) * 2) }; m(x + 1)",
+                "(x + 1) * 2",
+            ),
+            (
+                "let m = macro(n) { quote(unquote(1 + 2) + unquote(n)) }; m(4)",
+                "3 + 4",
+            ),
+            (
+                "let m = macro(a) { let code = quote(unquote(a) + 1); quote(unquote(code) * 2) };
+                 m(x)",
+                "(x + 1) * 2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut program = parse(input);
+            let macros = define_macros(&mut program);
+            let expanded = expand_macros(program, &macros).unwrap();
+
+            assert_eq!(expanded, parse(expected), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_expand_macros_errors() {
+        let tests = vec![
+            (
+                "let m = macro(a) { quote(a) }; m(1, 2)",
+                "wrong number of arguments to macro m: want=1, got=2 at 1:33",
+            ),
+            (
+                "let m = macro(a) { quote(a) }; m(a: 1)",
+                "macro m does not take named arguments at 1:33",
+            ),
+            (
+                "let m = macro() { 1 + 2 }; m()",
+                "macro m must return quote(...), got INTEGER at 1:29",
+            ),
+            (
+                "let m = macro(a) { quote(unquote(a + 1)) }; m(1)",
+                "type mismatch: QUOTE + INTEGER in macro m at 1:46",
+            ),
+            (
+                "let m = macro() { quote(unquote(fn() { 1 })) }; m()",
+                "cannot unquote FUNCTION in macro m at 1:50",
+            ),
+            (
+                "let x = 1; let m = macro() { quote(unquote(x)) }; m()",
+                "identifier not found: x in macro m at 1:52",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut program = parse(input);
+            let macros = define_macros(&mut program);
+
+            assert_eq!(
+                expand_macros(program, &macros),
+                Err(vec![expected.to_string()]),
+                "input: {input}"
+            );
+        }
+    }
+}
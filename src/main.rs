@@ -1,9 +1,114 @@
-mod ast;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use std::rc::Rc;
+
+use rusty_monkey::arena::Ast;
+use rusty_monkey::ast::Program;
+use rusty_monkey::macro_expansion::{define_macros, expand_macros};
+use rusty_monkey::{docgen, dump, lexer, parser, repl, resolver, typecheck};
+
+const USAGE: &str = "usage: rusty-monkey [--ast=json|sexpr|source | --doc | --types] [FILE]";
 
 fn main() {
-    repl::start()
+    let mut format = None;
+    let mut path = None;
+
+    for arg in env::args().skip(1) {
+        if let Some(f) = arg.strip_prefix("--ast=") {
+            format = Some(f.to_string());
+        } else if arg == "--doc" {
+            // Markdown documentation instead of an AST dump.
+            format = Some("doc".to_string());
+        } else if arg == "--types" {
+            // The inferred types of the top-level bindings.
+            format = Some("types".to_string());
+        } else if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            return;
+        } else if path.is_none() {
+            path = Some(arg);
+        } else {
+            fail(USAGE);
+        }
+    }
+
+    let Some(format) = format else {
+        if path.is_some() {
+            fail(USAGE);
+        }
+        return repl::start();
+    };
+
+    // Read the program from FILE, or from stdin when no file (or `-`) is given.
+    let input = match path.as_deref() {
+        None | Some("-") => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .unwrap_or_else(|e| fail(&format!("could not read stdin: {e}")));
+            input
+        }
+        Some(path) => fs::read_to_string(path)
+            .unwrap_or_else(|e| fail(&format!("could not read {path}: {e}"))),
+    };
+
+    let lex = lexer::new(&input);
+    let mut parser = parser::new(Rc::new(RefCell::new(lex)));
+    let program = parser.parse_program();
+    for warning in parser.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    if !parser.errors().is_empty() {
+        for err in parser.errors() {
+            eprintln!("{err}");
+        }
+        process::exit(1);
+    }
+
+    match format.as_str() {
+        "json" => println!("{}", dump::to_json(&program, true)),
+        "sexpr" => println!("{}", dump::to_sexpr(&program)),
+        "source" => println!("{program}"),
+        "doc" => print!("{}", docgen::to_markdown(&program)),
+        "types" => print_types(program),
+        other => fail(&format!("unknown AST format {other:?}\n{USAGE}")),
+    }
+}
+
+// Print `name: type` for each top-level binding, or the errors that stop the
+// program from being typed.
+fn print_types(mut program: Program) {
+    let macros = define_macros(&mut program);
+    let program = expand_macros(program, &macros).unwrap_or_else(|errors| {
+        for err in errors {
+            eprintln!("{err}");
+        }
+        process::exit(1)
+    });
+
+    let ast = Ast::from(&program);
+    let resolution = resolver::resolve(&ast);
+    for warning in &resolution.warnings {
+        eprintln!("warning: {warning}");
+    }
+    let typing = typecheck::check(&ast, &resolution);
+    let errors: Vec<&String> = resolution.errors.iter().chain(&typing.errors).collect();
+    if !errors.is_empty() {
+        for err in errors {
+            eprintln!("{err}");
+        }
+        process::exit(1);
+    }
+
+    for (name, scheme) in &typing.lets {
+        println!("{name}: {scheme}");
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{msg}");
+    process::exit(2)
 }
@@ -0,0 +1,351 @@
+use crate::ast::{
+    fold_expression, BlockStatement, Boolean, Expression, ExpressionStatement, Fold, IfExpression,
+    InfixExpression, IntegerLiteral, PrefixExpression, Program, Statement, StringLiteral,
+};
+use crate::token::{Token, TokenType};
+
+// Fold operators applied to literals, as in `2 * 3 + 1` to `7`, drop
+// identities such as `x * 1` and `x + 0` when `x` is known to be an integer,
+// and remove the branch of an `if` that a constant condition rules out. An
+// operation that fails at runtime, like `1 / 0` or `5 + true`, is left for
+// the evaluator to report.
+pub fn optimize(program: Program) -> Program {
+    Optimizer.fold_program(program)
+}
+
+struct Optimizer;
+
+impl Fold for Optimizer {
+    fn fold_program(&mut self, program: Program) -> Program {
+        Program {
+            statements: self.fold_statements(program.statements),
+        }
+    }
+
+    fn fold_block_statement(&mut self, block: BlockStatement) -> BlockStatement {
+        BlockStatement {
+            token: block.token,
+            statements: self.fold_statements(block.statements),
+        }
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match fold_expression(self, expr) {
+            Expression::Prefix(e) => fold_prefix(e),
+            Expression::Infix(e) => fold_infix(e),
+            Expression::If(e) => fold_if(e),
+            expr => expr,
+        }
+    }
+}
+
+impl Optimizer {
+    // An `if` statement whose branch is known runs that branch's statements
+    // in place: blocks do not open a scope, so they can join the enclosing
+    // one. The last statement of a block gives the block its value, so there
+    // the branch must end in an expression or a jump to replace it.
+    fn fold_statements(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+        let count = statements.len();
+        let mut folded = Vec::with_capacity(count);
+
+        for (i, stmt) in statements.into_iter().enumerate() {
+            let stmt = self.fold_statement(stmt);
+            let Some(branch) = taken_branch(&stmt) else {
+                folded.push(stmt);
+                continue;
+            };
+            let last = i + 1 == count;
+            if !last || matches!(branch.last(), Some(s) if !matches!(s, Statement::Let(_))) {
+                folded.extend(branch.iter().cloned());
+            } else {
+                folded.push(stmt);
+            }
+        }
+
+        folded
+    }
+}
+
+// The statements an `if` statement runs, when its condition is a constant.
+// `fold_if` has already dropped the other branch.
+fn taken_branch(stmt: &Statement) -> Option<&[Statement]> {
+    let Statement::Expression(ExpressionStatement {
+        expression: Expression::If(e),
+    }) = stmt
+    else {
+        return None;
+    };
+    match (constant_truth(&e.condition), &e.alternative) {
+        (Some(true), None) => Some(&e.consequence.statements),
+        (Some(false), None) => Some(&[]),
+        _ => None,
+    }
+}
+
+// Whether a literal is truthy. Only `false` (and `null`, which has no
+// literal) is falsy.
+fn constant_truth(expr: &Expression) -> Option<bool> {
+    match expr {
+        Expression::Boolean(b) => Some(b.value),
+        Expression::Integer(_) | Expression::String(_) => Some(true),
+        _ => None,
+    }
+}
+
+fn fold_prefix(e: PrefixExpression) -> Expression {
+    match (e.operator.as_str(), e.right.as_ref()) {
+        ("-", Expression::Integer(n)) => integer(&e.token, n.value.wrapping_neg()),
+        ("!", right) => match constant_truth(right) {
+            Some(truth) => boolean(&e.token, !truth),
+            None => Expression::Prefix(e),
+        },
+        _ => Expression::Prefix(e),
+    }
+}
+
+fn fold_infix(e: InfixExpression) -> Expression {
+    use Expression::{Boolean as Bool, Integer as Int, String as Str};
+
+    let token = &e.token;
+    let folded = match (e.left.as_ref(), e.operator.as_str(), e.right.as_ref()) {
+        (Int(a), op, Int(b)) => match op {
+            "+" => Some(integer(token, a.value.wrapping_add(b.value))),
+            "-" => Some(integer(token, a.value.wrapping_sub(b.value))),
+            "*" => Some(integer(token, a.value.wrapping_mul(b.value))),
+            "/" if b.value != 0 => Some(integer(token, a.value.wrapping_div(b.value))),
+            "<" => Some(boolean(token, a.value < b.value)),
+            ">" => Some(boolean(token, a.value > b.value)),
+            "==" => Some(boolean(token, a.value == b.value)),
+            "!=" => Some(boolean(token, a.value != b.value)),
+            _ => None,
+        },
+        (Str(a), "+", Str(b)) => Some(string(token, format!("{}{}", a.value, b.value))),
+        (Str(a), "==", Str(b)) => Some(boolean(token, a.value == b.value)),
+        (Str(a), "!=", Str(b)) => Some(boolean(token, a.value != b.value)),
+        (Bool(a), "==", Bool(b)) => Some(boolean(token, a.value == b.value)),
+        (Bool(a), "!=", Bool(b)) => Some(boolean(token, a.value != b.value)),
+        // Literals of different types are never equal.
+        (left, op @ ("==" | "!="), right)
+            if constant_truth(left).is_some() && constant_truth(right).is_some() =>
+        {
+            Some(boolean(token, op == "!="))
+        }
+        _ => None,
+    };
+    if let Some(folded) = folded {
+        return folded;
+    }
+
+    // The operand that is left when the other one is an identity element.
+    let is_int = |expr: &Expression, value: i64| matches!(expr, Int(n) if n.value == value);
+    let operand = match e.operator.as_str() {
+        "+" | "-" if is_int(&e.right, 0) => Some(&e.left),
+        "+" if is_int(&e.left, 0) => Some(&e.right),
+        "*" | "/" if is_int(&e.right, 1) => Some(&e.left),
+        "*" if is_int(&e.left, 1) => Some(&e.right),
+        _ => None,
+    };
+    match operand {
+        Some(operand) if known_integer(operand) => (**operand).clone(),
+        _ => Expression::Infix(e),
+    }
+}
+
+// Whether `expr` evaluates to an integer whenever it does not fail. `-`, `*`
+// and `/` only apply to integers, while `+` also joins strings.
+fn known_integer(expr: &Expression) -> bool {
+    match expr {
+        Expression::Integer(_) => true,
+        Expression::Prefix(e) => e.operator == "-",
+        Expression::Infix(e) => match e.operator.as_str() {
+            "-" | "*" | "/" => true,
+            "+" => known_integer(&e.left) && known_integer(&e.right),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// With a constant condition only one branch can run. When it is a single
+// expression the `if` becomes that expression; otherwise the other branch is
+// dropped, leaving `if (true) { ... }`, or `if (false) {}` for a missing
+// branch, which evaluates to `null`.
+fn fold_if(e: IfExpression) -> Expression {
+    let Some(truth) = constant_truth(&e.condition) else {
+        return Expression::If(e);
+    };
+    let IfExpression {
+        token,
+        consequence,
+        alternative,
+        ..
+    } = e;
+    let taken = if truth {
+        Some(consequence)
+    } else {
+        alternative
+    };
+
+    match taken {
+        Some(block) => match block.statements.as_slice() {
+            [Statement::Expression(s)] => s.expression.clone(),
+            _ => Expression::If(IfExpression {
+                condition: Box::new(boolean(&token, true)),
+                token,
+                consequence: block,
+                alternative: None,
+            }),
+        },
+        None => Expression::If(IfExpression {
+            condition: Box::new(boolean(&token, false)),
+            consequence: BlockStatement {
+                token: token.clone(),
+                statements: Vec::new(),
+            },
+            token,
+            alternative: None,
+        }),
+    }
+}
+
+// A literal standing in for the expression whose token is `token`.
+fn literal_token(token: &Token, t_type: TokenType, literal: String) -> Token {
+    Token {
+        t_type,
+        literal,
+        ..token.clone()
+    }
+}
+
+fn integer(token: &Token, value: i64) -> Expression {
+    Expression::Integer(IntegerLiteral {
+        token: literal_token(token, TokenType::Int, value.to_string()),
+        value,
+    })
+}
+
+fn boolean(token: &Token, value: bool) -> Expression {
+    let t_type = if value {
+        TokenType::True
+    } else {
+        TokenType::False
+    };
+    Expression::Boolean(Boolean {
+        token: literal_token(token, t_type, value.to_string()),
+        value,
+    })
+}
+
+fn string(token: &Token, value: String) -> Expression {
+    Expression::String(StringLiteral {
+        token: literal_token(token, TokenType::String, value.clone()),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    fn assert_optimized(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            assert_eq!(
+                optimize(parse(input)).to_string(),
+                parse(expected).to_string(),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_optimized(vec![
+            ("2 * 3 + 1", "7"),
+            ("!true", "false"),
+            (r#""a" + "b""#, r#""ab""#),
+            ("-(2 - 5)", "3"),
+            ("10 / 3 - 7", "-4"),
+            ("1 < 2 == true", "true"),
+            ("!!5", "true"),
+            (r#"!"""#, "false"),
+            (r#""a" != "b""#, "true"),
+            (r#"1 == true"#, "false"),
+            (r#"1 != "1""#, "true"),
+            (
+                "let f = fn(x = 2 * 2) { x * (1 + 1) }",
+                "let f = fn(x = 4) { x * 2 };",
+            ),
+            ("[1 + 1, {2 * 2: 3 - 3}][0 + 0]", "[2, {4: 0}][0]"),
+            ("match x { 1 => 2 + 2 }", "match x { 1 => 4 }"),
+        ]);
+    }
+
+    #[test]
+    fn test_preserve_runtime_errors() {
+        assert_optimized(vec![
+            ("1 / 0", "1 / 0"),
+            ("(1 + 1) / (2 - 2)", "2 / 0"),
+            ("5 + true", "5 + true"),
+            ("-true", "-true"),
+            (r#""a" - "b""#, r#""a" - "b""#),
+            (r#""a" < "b""#, r#""a" < "b""#),
+            ("true + false", "true + false"),
+        ]);
+    }
+
+    #[test]
+    fn test_simplify_identities() {
+        assert_optimized(vec![
+            // Only operands known to be integers lose the identity: `x + 0`
+            // fails for a boolean `x` and `x * 1` for a string.
+            ("x * 1", "x * 1"),
+            ("x + 0", "x + 0"),
+            ("(x - 1) * 1", "x - 1"),
+            ("1 * (x * y)", "x * y"),
+            ("0 + -x", "-x"),
+            ("(a / b) - 0", "a / b"),
+            ("(x - 1) / (3 - 2)", "x - 1"),
+            ("(a - 1) + (b - 1) + 0", "a - 1 + (b - 1)"),
+            ("(a + b) + 0", "a + b + 0"),
+            ("0 - x", "0 - x"),
+            ("f() * 1", "f() * 1"),
+        ]);
+    }
+
+    #[test]
+    fn test_remove_dead_branches() {
+        assert_optimized(vec![
+            ("if (false) { 1 } else { 2 }", "2"),
+            ("let y = if (1 > 2) { a } else { b };", "let y = b;"),
+            ("let y = if (1) { a } else { b };", "let y = a;"),
+            ("let y = if (false) { a };", "let y = if (false) {};"),
+            (
+                "let y = if (true) { f(); a };",
+                "let y = if (true) { f(); a };",
+            ),
+            (
+                "let y = if (!true) { a } else { f(); b };",
+                "let y = if (true) { f(); b };",
+            ),
+            ("if (x) { 1 } else { 2 }", "if (x) { 1 } else { 2 }"),
+            // Statements of the branch taken join the enclosing block.
+            ("if (true) { let a = 1; a } else { 2 }", "let a = 1; a"),
+            (
+                "let f = fn() { if (false) { 1 }; 2 };",
+                "let f = fn() { 2 };",
+            ),
+            (
+                "while (x) { if (1 < 2) { let a = f(); g(a); } h() }",
+                "while (x) { let a = f(); g(a); h() }",
+            ),
+            ("fn() { if (true) { return 1; } }", "fn() { return 1; }"),
+            // As the last statement the branch decides the block's value.
+            ("fn() { 1; if (false) { 2 } }", "fn() { 1; if (false) {} }"),
+            (
+                "fn() { 1; if (true) { let a = 2; } }",
+                "fn() { 1; if (true) { let a = 2; } }",
+            ),
+        ]);
+    }
+}
@@ -1,44 +1,1446 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+use lazy_static::lazy_static;
+
+use crate::ast::{
+    ArrayLiteral, ArrayPattern, AssignExpression, BlockStatement, Boolean, BreakStatement,
+    CallExpression, ContinueStatement, Expression, ExpressionStatement, ForExpression,
+    FunctionLiteral, HashLiteral, HashPattern, Identifier, IfExpression, IndexExpression,
+    InfixExpression, IntegerLiteral, LetStatement, MacroLiteral, MatchArm, MatchExpression,
+    Parameter, Pattern, PrefixExpression, Program, RangeExpression, ReturnStatement, Statement,
+    StringLiteral, TypeAnnotation, WhileExpression, WildcardPattern,
+};
 use crate::lexer::Lexer;
-use crate::token::Token;
+use crate::token::{Span, Token, TokenType};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Precedence {
+    Lowest,
+    // x = y or x += y
+    Assign,
+    // x |> f
+    Pipe,
+    // a..b or a..=b
+    Range,
+    // ==
+    Equals,
+    // > or <
+    LessGreater,
+    // +
+    Sum,
+    // *
+    Product,
+    // -x or !x
+    Prefix,
+    // myFunction(x)
+    Call,
+    // array[index]
+    Index,
+}
+
+lazy_static! {
+    static ref PRECEDENCES: HashMap<TokenType, Precedence> = {
+        let mut map = HashMap::new();
+        map.insert(TokenType::Assign, Precedence::Assign);
+        map.insert(TokenType::PlusAssign, Precedence::Assign);
+        map.insert(TokenType::MinusAssign, Precedence::Assign);
+        map.insert(TokenType::AsteriskAssign, Precedence::Assign);
+        map.insert(TokenType::SlashAssign, Precedence::Assign);
+        map.insert(TokenType::Pipe, Precedence::Pipe);
+        map.insert(TokenType::DotDot, Precedence::Range);
+        map.insert(TokenType::DotDotEq, Precedence::Range);
+        map.insert(TokenType::Eq, Precedence::Equals);
+        map.insert(TokenType::NotEq, Precedence::Equals);
+        map.insert(TokenType::Lt, Precedence::LessGreater);
+        map.insert(TokenType::Gt, Precedence::LessGreater);
+        map.insert(TokenType::Plus, Precedence::Sum);
+        map.insert(TokenType::Minus, Precedence::Sum);
+        map.insert(TokenType::Slash, Precedence::Product);
+        map.insert(TokenType::Asterisk, Precedence::Product);
+        map.insert(TokenType::Lparen, Precedence::Call);
+        map.insert(TokenType::Lbracket, Precedence::Index);
+
+        map
+    };
+}
+
+// How deeply expressions, patterns and blocks may nest, each operator of a
+// chain counting as a level. Parsing and the passes over the tree are
+// recursive, so without a limit input such as ten thousand `(` or `1 +`
+// would overflow the stack.
+const MAX_NESTING_DEPTH: usize = 100;
 
-struct Parser<'a> {
+// Positional and named arguments of a call, in source order.
+type CallArguments = (Vec<Expression>, Vec<(Identifier, Expression)>);
+
+// Look up the binding power of a token used in infix position.
+pub fn token_precedence(t_type: &TokenType) -> Precedence {
+    PRECEDENCES
+        .get(t_type)
+        .copied()
+        .unwrap_or(Precedence::Lowest)
+}
+
+pub struct Parser<'a> {
     lex: Rc<RefCell<Lexer<'a>>>,
 
-    cur_token: Rc<RefCell<Token>>,
-    peek_token: Rc<RefCell<Token>>,
+    cur_token: Token,
+    peek_token: Token,
+
+    errors: Vec<String>,
+    warnings: Vec<String>,
+
+    // Number of loop bodies enclosing the current token within the current
+    // function, used to reject `break` and `continue` outside of a loop.
+    loop_depth: usize,
+
+    // Whether a line break ends the expression being parsed. Statements
+    // inside a block or at the top level may end at the end of a line;
+    // inside brackets a line break is plain whitespace.
+    newline_ends_expression: bool,
+
+    // Number of expressions, patterns and blocks being parsed around the
+    // current token, plus the operators folded into the expressions so far,
+    // and the index of the error reported on exceeding the limit.
+    depth: usize,
+    nesting_error: Option<usize>,
+
+    // The `..` tokens of the open ranges in the current statement that are
+    // not the whole index of an index expression, the only place an open
+    // range is allowed.
+    open_ranges: Vec<Span>,
+
+    // The `)` of the last parenthesized expression, which tells whether the
+    // right-hand side of a pipe is parenthesized as a whole.
+    group_end: Option<Span>,
 }
-fn new<'a>(lex: Rc<RefCell<Lexer<'a>>>) -> Parser<'a> {
+
+// Create new parser instance.
+pub fn new(lex: Rc<RefCell<Lexer<'_>>>) -> Parser<'_> {
     let curr = lex.borrow_mut().next_token();
 
     let peek = lex.borrow_mut().next_token();
 
-    let parser = Parser {
+    Parser {
         lex: lex.clone(),
-        cur_token: Rc::new(RefCell::new(curr)),
-        peek_token: Rc::new(RefCell::new(peek)),
-    };
-
-    parser
+        cur_token: curr,
+        peek_token: peek,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+        loop_depth: 0,
+        newline_ends_expression: true,
+        depth: 0,
+        nesting_error: None,
+        open_ranges: Vec::new(),
+        group_end: None,
+    }
 }
 
 impl<'a> Parser<'a> {
-    fn next_token(&'a mut self) {
-        self.cur_token = self.peek_token.clone();
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    // Problems that do not stop the program from parsing.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // Advance both the current and the peek token.
+    fn next_token(&mut self) {
         let next = self.lex.borrow_mut().next_token();
-        self.peek_token = Rc::new(RefCell::new(next));
+        self.cur_token = std::mem::replace(&mut self.peek_token, next);
+    }
+
+    fn cur_token_is(&self, t_type: TokenType) -> bool {
+        self.cur_token.t_type == t_type
+    }
+
+    fn peek_token_is(&self, t_type: TokenType) -> bool {
+        self.peek_token.t_type == t_type
+    }
+
+    // Only advance when the next token has the expected type, otherwise
+    // record an error.
+    fn expect_peek(&mut self, t_type: TokenType) -> bool {
+        if self.peek_token_is(t_type.clone()) {
+            self.next_token();
+            true
+        } else {
+            self.peek_error(t_type);
+            false
+        }
+    }
+
+    fn peek_error(&mut self, t_type: TokenType) {
+        self.errors.push(format!(
+            "expected next token to be {:?}, got {:?} instead",
+            t_type, self.peek_token.t_type
+        ));
+    }
+
+    // The `///` comment written right above `token`, if any.
+    fn take_doc(&self, token: &Token) -> Option<String> {
+        self.lex.borrow_mut().take_doc(token.span.start)
+    }
+
+    fn peek_precedence(&self) -> Precedence {
+        token_precedence(&self.peek_token.t_type)
+    }
+
+    fn cur_precedence(&self) -> Precedence {
+        token_precedence(&self.cur_token.t_type)
+    }
+
+    pub fn parse_program(&mut self) -> Program {
+        let mut program = Program::default();
+
+        while !self.cur_token_is(TokenType::Eof) {
+            if let Some(stmt) = self.parse_statement() {
+                program.statements.push(stmt);
+            }
+            for span in std::mem::take(&mut self.open_ranges) {
+                self.errors
+                    .push(format!("open ranges can only be used to slice at {span}"));
+            }
+            self.next_token();
+        }
+
+        // Whatever went wrong after giving up on deeply nested input only
+        // follows from skipping the rest of it.
+        if let Some(index) = self.nesting_error {
+            self.errors.truncate(index + 1);
+        }
+
+        program
+    }
+
+    fn parse_statement(&mut self) -> Option<Statement> {
+        match self.cur_token.t_type {
+            TokenType::Let => self
+                .parse_let_statement()
+                .map(|s| Statement::Let(Box::new(s))),
+            TokenType::Return => self.parse_return_statement().map(Statement::Return),
+            TokenType::Break => self.parse_break_statement().map(Statement::Break),
+            TokenType::Continue => self.parse_continue_statement().map(Statement::Continue),
+            _ => self.parse_expression_statement().map(Statement::Expression),
+        }
+    }
+
+    fn parse_let_statement(&mut self) -> Option<LetStatement> {
+        let token = self.cur_token.clone();
+        let doc = self.take_doc(&token);
+
+        let pattern = match self.peek_token.t_type {
+            TokenType::Lbracket | TokenType::Lbrace | TokenType::Underscore => {
+                self.next_token();
+                self.parse_pattern()?
+            }
+            _ => {
+                if !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                Pattern::Identifier(self.parse_identifier())
+            }
+        };
+        self.check_duplicate_bindings(&pattern);
+        let annotation = self.parse_optional_annotation(TokenType::Colon)?;
+
+        if !self.expect_peek(TokenType::Assign) {
+            return None;
+        }
+        self.next_token();
+
+        let mut value = self.parse_expression(Precedence::Lowest)?;
+        if let Expression::Function(func) = &mut value {
+            if func.doc.is_none() {
+                func.doc.clone_from(&doc);
+            }
+        }
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(LetStatement {
+            token,
+            pattern,
+            annotation,
+            value,
+            doc,
+        })
+    }
+
+    // The type after `marker` when the next token is `marker`. The outer
+    // `None` means the type could not be parsed.
+    fn parse_optional_annotation(&mut self, marker: TokenType) -> Option<Option<TypeAnnotation>> {
+        if !self.peek_token_is(marker) {
+            return Some(None);
+        }
+        self.next_token();
+        self.next_token();
+        self.parse_type_annotation().map(Some)
+    }
+
+    fn parse_type_annotation(&mut self) -> Option<TypeAnnotation> {
+        if !self.enter_nested() {
+            return None;
+        }
+        let annotation = self.parse_type_annotation_kind();
+        self.depth -= 1;
+        annotation
+    }
+
+    fn parse_type_annotation_kind(&mut self) -> Option<TypeAnnotation> {
+        match self.cur_token.t_type {
+            TokenType::Ident => {
+                let name = self.parse_identifier();
+                if !matches!(
+                    name.value.as_str(),
+                    "int" | "bool" | "string" | "null" | "range"
+                ) {
+                    // The name is read, so parsing goes on as if it were a
+                    // type.
+                    self.errors.push(format!(
+                        "unknown type {} at {}",
+                        name.value, name.token.span
+                    ));
+                }
+                Some(TypeAnnotation::Named(name))
+            }
+            TokenType::Lbracket => {
+                self.next_token();
+                let element = self.parse_type_annotation()?;
+                if !self.expect_peek(TokenType::Rbracket) {
+                    return None;
+                }
+                Some(TypeAnnotation::Array(Box::new(element)))
+            }
+            TokenType::Lbrace => {
+                self.next_token();
+                let key = self.parse_type_annotation()?;
+                if !self.expect_peek(TokenType::Colon) {
+                    return None;
+                }
+                self.next_token();
+                let value = self.parse_type_annotation()?;
+                if !self.expect_peek(TokenType::Rbrace) {
+                    return None;
+                }
+                Some(TypeAnnotation::Hash(Box::new(key), Box::new(value)))
+            }
+            TokenType::Function => {
+                if !self.expect_peek(TokenType::Lparen) {
+                    return None;
+                }
+                let mut params = Vec::new();
+                while !self.peek_token_is(TokenType::Rparen) {
+                    self.next_token();
+                    params.push(self.parse_type_annotation()?);
+                    if !self.peek_token_is(TokenType::Rparen) && !self.expect_peek(TokenType::Comma)
+                    {
+                        return None;
+                    }
+                }
+                self.next_token();
+                if !self.expect_peek(TokenType::Arrow) {
+                    return None;
+                }
+                self.next_token();
+                let result = self.parse_type_annotation()?;
+                Some(TypeAnnotation::Function(params, Box::new(result)))
+            }
+            _ => {
+                self.errors.push(format!(
+                    "expected a type, got {:?} instead",
+                    self.cur_token.t_type
+                ));
+                None
+            }
+        }
+    }
+
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        if !self.enter_nested() {
+            return None;
+        }
+        let pattern = self.parse_pattern_kind();
+        self.depth -= 1;
+        pattern
+    }
+
+    fn parse_pattern_kind(&mut self) -> Option<Pattern> {
+        match self.cur_token.t_type {
+            TokenType::Ident => Some(Pattern::Identifier(self.parse_identifier())),
+            TokenType::Underscore => Some(Pattern::Wildcard(WildcardPattern {
+                token: self.cur_token.clone(),
+            })),
+            TokenType::Int
+            | TokenType::Minus
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False => self
+                .parse_literal_pattern()
+                .map(|e| Pattern::Literal(Box::new(e))),
+            TokenType::Lbracket => self.parse_array_pattern().map(Pattern::Array),
+            TokenType::Lbrace => self.parse_hash_pattern().map(Pattern::Hash),
+            _ => {
+                self.errors.push(format!(
+                    "expected a pattern, got {:?} instead",
+                    self.cur_token.t_type
+                ));
+                None
+            }
+        }
+    }
+
+    // Only plain literals can be compared against, with `-` allowed in front
+    // of an integer.
+    fn parse_literal_pattern(&mut self) -> Option<Expression> {
+        match self.cur_token.t_type {
+            TokenType::Int => self.parse_integer_literal().map(Expression::Integer),
+            TokenType::String => Some(Expression::String(self.parse_string_literal())),
+            TokenType::True | TokenType::False => Some(Expression::Boolean(self.parse_boolean())),
+            _ => {
+                let token = self.cur_token.clone();
+                if !self.expect_peek(TokenType::Int) {
+                    return None;
+                }
+                let right = self.parse_integer_literal()?;
+                Some(Expression::Prefix(PrefixExpression {
+                    operator: token.literal.clone(),
+                    token,
+                    right: Box::new(Expression::Integer(right)),
+                }))
+            }
+        }
+    }
+
+    fn parse_array_pattern(&mut self) -> Option<ArrayPattern> {
+        let token = self.cur_token.clone();
+        let mut elements = Vec::new();
+        let mut rest = None;
+
+        while !self.peek_token_is(TokenType::Rbracket) {
+            self.next_token();
+
+            // The rest binding collects everything left over, so nothing
+            // may follow it.
+            if self.cur_token_is(TokenType::Ellipsis) {
+                if !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                rest = Some(self.parse_identifier());
+                break;
+            }
+
+            elements.push(self.parse_pattern()?);
+
+            if !self.peek_token_is(TokenType::Rbracket) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+
+        if !self.expect_peek(TokenType::Rbracket) {
+            return None;
+        }
+
+        Some(ArrayPattern {
+            token,
+            elements,
+            rest,
+        })
+    }
+
+    fn parse_hash_pattern(&mut self) -> Option<HashPattern> {
+        let token = self.cur_token.clone();
+        let mut fields = Vec::new();
+
+        while !self.peek_token_is(TokenType::Rbrace) {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let key = self.parse_identifier();
+
+            let value = if self.peek_token_is(TokenType::Colon) {
+                self.next_token();
+                self.next_token();
+                self.parse_pattern()?
+            } else {
+                Pattern::Identifier(key.clone())
+            };
+
+            fields.push((key, value));
+
+            if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(HashPattern { token, fields })
+    }
+
+    fn check_duplicate_bindings(&mut self, pattern: &Pattern) {
+        let mut seen = Vec::new();
+
+        for ident in pattern.bindings() {
+            if seen.contains(&ident.value) {
+                self.errors.push(format!(
+                    "duplicate binding {} in pattern at {}",
+                    ident.value, ident.token.span
+                ));
+            } else {
+                seen.push(ident.value.clone());
+            }
+        }
+    }
+
+    fn parse_return_statement(&mut self) -> Option<ReturnStatement> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let return_value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ReturnStatement {
+            token,
+            return_value,
+        })
+    }
+
+    fn parse_break_statement(&mut self) -> Option<BreakStatement> {
+        let token = self.cur_token.clone();
+        self.check_inside_loop();
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(BreakStatement { token })
+    }
+
+    fn parse_continue_statement(&mut self) -> Option<ContinueStatement> {
+        let token = self.cur_token.clone();
+        self.check_inside_loop();
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ContinueStatement { token })
+    }
+
+    fn check_inside_loop(&mut self) {
+        if self.loop_depth == 0 {
+            self.errors.push(format!(
+                "{} outside of a loop at {}",
+                self.cur_token.literal, self.cur_token.span
+            ));
+        }
+    }
+
+    fn parse_expression_statement(&mut self) -> Option<ExpressionStatement> {
+        let expression = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ExpressionStatement { expression })
+    }
+
+    // Pratt parsing: parse a prefix expression for the current token, then
+    // keep folding infix operators that bind tighter than `precedence`.
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        let depth = self.depth;
+        if !self.enter_nested() {
+            return None;
+        }
+        let expression = self.parse_operators(precedence);
+        self.depth = depth;
+        expression
+    }
+
+    fn parse_operators(&mut self, precedence: Precedence) -> Option<Expression> {
+        let mut left = self.parse_prefix()?;
+
+        while !self.peek_token_is(TokenType::Semicolon)
+            && !self.peek_ends_expression()
+            && precedence < self.peek_precedence()
+        {
+            left = match self.peek_token.t_type {
+                TokenType::Plus
+                | TokenType::Minus
+                | TokenType::Slash
+                | TokenType::Asterisk
+                | TokenType::Eq
+                | TokenType::NotEq
+                | TokenType::Lt
+                | TokenType::Gt => {
+                    self.next_token();
+                    self.parse_infix_expression(left)?
+                }
+                TokenType::Assign
+                | TokenType::PlusAssign
+                | TokenType::MinusAssign
+                | TokenType::AsteriskAssign
+                | TokenType::SlashAssign => {
+                    self.next_token();
+                    self.parse_assign_expression(left)?
+                }
+                TokenType::Pipe => {
+                    self.next_token();
+                    self.parse_pipe_expression(left)?
+                }
+                TokenType::DotDot | TokenType::DotDotEq => {
+                    self.next_token();
+                    self.parse_range_expression(Some(left))?
+                }
+                TokenType::Lparen => {
+                    self.next_token();
+                    self.parse_call_expression(left)?
+                }
+                TokenType::Lbracket => {
+                    self.next_token();
+                    self.parse_index_expression(left)?
+                }
+                _ => return Some(left),
+            };
+            // Each operator folded in makes the tree one level deeper, so a
+            // long chain such as `1 + 1 + ...` counts as deep nesting.
+            if !self.enter_nested() {
+                return None;
+            }
+        }
+
+        Some(left)
+    }
+
+    // Count one more level of nesting, unless that exceeds the limit: then
+    // report it, skip the rest of the input and return false.
+    fn enter_nested(&mut self) -> bool {
+        if self.depth < MAX_NESTING_DEPTH {
+            self.depth += 1;
+            return true;
+        }
+        if self.nesting_error.is_none() {
+            self.errors.push(format!(
+                "input nested too deeply at {}",
+                self.cur_token.span
+            ));
+            self.nesting_error = Some(self.errors.len() - 1);
+        }
+        while !self.cur_token_is(TokenType::Eof) {
+            self.next_token();
+        }
+        false
+    }
+
+    fn parse_prefix(&mut self) -> Option<Expression> {
+        match self.cur_token.t_type {
+            TokenType::Ident => Some(Expression::Identifier(self.parse_identifier())),
+            TokenType::Int => self.parse_integer_literal().map(Expression::Integer),
+            TokenType::True | TokenType::False => Some(Expression::Boolean(self.parse_boolean())),
+            TokenType::Bang | TokenType::Minus => {
+                self.parse_prefix_expression().map(Expression::Prefix)
+            }
+            TokenType::Lparen => self.parse_grouped_expression(),
+            TokenType::If => self.parse_if_expression().map(Expression::If),
+            TokenType::Function => self.parse_function_literal().map(Expression::Function),
+            TokenType::Macro => self.parse_macro_literal().map(Expression::Macro),
+            TokenType::String => Some(Expression::String(self.parse_string_literal())),
+            TokenType::Lbracket => self.parse_array_literal().map(Expression::Array),
+            TokenType::Lbrace => self.parse_hash_literal().map(Expression::Hash),
+            TokenType::While => self.parse_while_expression().map(Expression::While),
+            TokenType::For => self.parse_for_expression().map(Expression::For),
+            TokenType::Match => self.parse_match_expression().map(Expression::Match),
+            TokenType::DotDot | TokenType::DotDotEq => self.parse_range_expression(None),
+            _ => {
+                self.errors.push(format!(
+                    "no prefix parse function for {:?} found",
+                    self.cur_token.t_type
+                ));
+                None
+            }
+        }
+    }
+
+    fn parse_identifier(&self) -> Identifier {
+        Identifier {
+            token: self.cur_token.clone(),
+            value: self.cur_token.literal.clone(),
+        }
+    }
+
+    fn parse_integer_literal(&mut self) -> Option<IntegerLiteral> {
+        match self.cur_token.literal.parse::<i64>() {
+            Ok(value) => Some(IntegerLiteral {
+                token: self.cur_token.clone(),
+                value,
+            }),
+            Err(_) => {
+                self.errors.push(format!(
+                    "could not parse {:?} as integer",
+                    self.cur_token.literal
+                ));
+                None
+            }
+        }
+    }
+
+    fn parse_boolean(&self) -> Boolean {
+        Boolean {
+            token: self.cur_token.clone(),
+            value: self.cur_token_is(TokenType::True),
+        }
+    }
+
+    fn parse_string_literal(&self) -> StringLiteral {
+        StringLiteral {
+            token: self.cur_token.clone(),
+            value: self.cur_token.literal.clone(),
+        }
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<PrefixExpression> {
+        let token = self.cur_token.clone();
+        let operator = token.literal.clone();
+
+        self.next_token();
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Some(PrefixExpression {
+            token,
+            operator,
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let operator = token.literal.clone();
+        let precedence = self.cur_precedence();
+
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+
+        Some(Expression::Infix(InfixExpression {
+            token,
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }))
+    }
+
+    // Assignment is right associative, so the value is parsed at the lowest
+    // precedence: `a = b = c` assigns `c` to `b` and then to `a`.
+    fn parse_assign_expression(&mut self, target: Expression) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let operator = token.literal.clone();
+
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if !matches!(target, Expression::Identifier(_) | Expression::Index(_)) {
+            self.errors.push(format!(
+                "invalid assignment target {target} at {}",
+                token.span
+            ));
+            return None;
+        }
+
+        Some(Expression::Assign(AssignExpression {
+            token,
+            target: Box::new(target),
+            operator,
+            value: Box::new(value),
+        }))
+    }
+
+    // A line break before the next token ends the expression, unless the
+    // line ended with an operator still waiting for its operand: the loop
+    // in `parse_expression` only gets here between complete operands, so
+    // `1 +` on one line and `2` on the next still form a single sum. A line
+    // starting with `|>` continues the previous one, since no statement can
+    // start with it.
+    fn peek_ends_expression(&self) -> bool {
+        self.newline_ends_expression
+            && self.peek_token.newline_before
+            && !self.peek_token_is(TokenType::Pipe)
+    }
+
+    // Parse an expression enclosed in brackets, where it may span lines.
+    fn parse_nested_expression(&mut self) -> Option<Expression> {
+        let outer = std::mem::replace(&mut self.newline_ends_expression, false);
+        let expr = self.parse_expression(Precedence::Lowest);
+        self.newline_ends_expression = outer;
+        expr
+    }
+
+    // `value |> f(args)` is sugar for `f(value, args)` and `value |> f` for
+    // `f(value)`, so the tree only ever holds the call. When the right-hand
+    // side is a call the value becomes its first argument, otherwise the
+    // right-hand side is called with the value alone. A parenthesized
+    // right-hand side is a value too: `x |> (f(y))` is `f(y)(x)`. The pipe is
+    // left associative: `x |> f |> g` is `g(f(x))`.
+    fn parse_pipe_expression(&mut self, value: Expression) -> Option<Expression> {
+        let span = self.cur_token.span;
+
+        self.next_token();
+        let opens_group = self.cur_token_is(TokenType::Lparen);
+        let target = self.parse_expression(Precedence::Pipe)?;
+        let grouped = opens_group && self.group_end == Some(self.cur_token.span);
+
+        let call = match target {
+            Expression::Call(mut call) if !grouped => {
+                call.arguments.insert(0, value);
+                call
+            }
+            function => CallExpression {
+                // Stands in for the `(` the call would have been written with.
+                token: Token {
+                    span,
+                    ..Token::new(TokenType::Lparen, "(")
+                },
+                function: Box::new(function),
+                arguments: vec![value],
+                named_arguments: Vec::new(),
+            },
+        };
+
+        Some(Expression::Call(call))
+    }
+
+    // Both kinds of range, with `start` missing for `..end`. A range missing
+    // either bound is checked once its statement is parsed: only a slice
+    // index may be open.
+    fn parse_range_expression(&mut self, start: Option<Expression>) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let inclusive = self.cur_token_is(TokenType::DotDotEq);
+
+        // Only an exclusive range right before `]` may leave out its end.
+        let end = if !inclusive && self.peek_token_is(TokenType::Rbracket) {
+            None
+        } else {
+            self.next_token();
+            Some(Box::new(self.parse_expression(Precedence::Range)?))
+        };
+
+        if self.peek_token_is(TokenType::DotDot) || self.peek_token_is(TokenType::DotDotEq) {
+            self.errors.push(format!(
+                "ranges cannot be chained at {}",
+                self.peek_token.span
+            ));
+            return None;
+        }
+
+        if start.is_none() || end.is_none() {
+            self.open_ranges.push(token.span);
+        }
+        Some(Expression::Range(RangeExpression {
+            token,
+            start: start.map(Box::new),
+            end,
+            inclusive,
+        }))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<Expression> {
+        self.next_token();
+
+        let exp = self.parse_nested_expression();
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        self.group_end = Some(self.cur_token.span);
+
+        exp
+    }
+
+    fn parse_if_expression(&mut self) -> Option<IfExpression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+        self.next_token();
+
+        let condition = self.parse_nested_expression()?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let consequence = self.parse_block_statement();
+
+        let alternative = if self.peek_token_is(TokenType::Else) {
+            self.next_token();
+
+            if !self.expect_peek(TokenType::Lbrace) {
+                return None;
+            }
+
+            Some(self.parse_block_statement())
+        } else {
+            None
+        };
+
+        Some(IfExpression {
+            token,
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        })
+    }
+
+    fn parse_block_statement(&mut self) -> BlockStatement {
+        let token = self.cur_token.clone();
+        let mut statements = Vec::new();
+
+        if !self.enter_nested() {
+            return BlockStatement { token, statements };
+        }
+
+        // Statements in a block end at line breaks again, even when the
+        // block sits inside brackets.
+        let outer = std::mem::replace(&mut self.newline_ends_expression, true);
+        self.next_token();
+
+        while !self.cur_token_is(TokenType::Rbrace) && !self.cur_token_is(TokenType::Eof) {
+            if let Some(stmt) = self.parse_statement() {
+                statements.push(stmt);
+            }
+            self.next_token();
+        }
+        self.newline_ends_expression = outer;
+        self.depth -= 1;
+
+        if self.cur_token_is(TokenType::Eof) {
+            self.errors
+                .push(format!("unterminated block starting at {}", token.span));
+        }
+
+        BlockStatement { token, statements }
+    }
+
+    fn parse_while_expression(&mut self) -> Option<WhileExpression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+        self.next_token();
+
+        let condition = self.parse_nested_expression()?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let body = self.parse_loop_body();
+
+        Some(WhileExpression {
+            token,
+            condition: Box::new(condition),
+            body,
+        })
+    }
+
+    fn parse_for_expression(&mut self) -> Option<ForExpression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let variable = self.parse_identifier();
+
+        if !self.expect_peek(TokenType::In) {
+            return None;
+        }
+        self.next_token();
+
+        let iterable = self.parse_nested_expression()?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let body = self.parse_loop_body();
+
+        Some(ForExpression {
+            token,
+            variable,
+            iterable: Box::new(iterable),
+            body,
+        })
+    }
+
+    fn parse_loop_body(&mut self) -> BlockStatement {
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_match_expression(&mut self) -> Option<MatchExpression> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let subject = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let mut arms = Vec::new();
+        while !self.peek_token_is(TokenType::Rbrace) {
+            self.next_token();
+            let pattern = self.parse_pattern()?;
+            self.check_duplicate_bindings(&pattern);
+
+            let guard = if self.peek_token_is(TokenType::If) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_nested_expression()?)
+            } else {
+                None
+            };
+
+            if !self.expect_peek(TokenType::FatArrow) {
+                return None;
+            }
+            self.next_token();
+            let body = self.parse_nested_expression()?;
+
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+
+            // Arms are separated by commas, and a trailing comma is allowed.
+            if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        self.check_unreachable_arms(&arms);
+
+        Some(MatchExpression {
+            token,
+            subject: Box::new(subject),
+            arms,
+        })
+    }
+
+    // Warn about arms that can never be chosen: every arm after an unguarded
+    // one that matches any value, and an unguarded literal arm repeating an
+    // earlier one.
+    fn check_unreachable_arms(&mut self, arms: &[MatchArm]) {
+        let mut literals = Vec::new();
+        let mut exhausted = false;
+
+        for arm in arms {
+            let printed = arm.pattern.to_string();
+            let repeated =
+                matches!(arm.pattern, Pattern::Literal(_)) && literals.contains(&printed);
+            if exhausted || repeated {
+                self.warnings.push(format!(
+                    "unreachable match arm {printed} at {}",
+                    arm.pattern.span()
+                ));
+            }
+
+            if arm.guard.is_none() {
+                match arm.pattern {
+                    Pattern::Identifier(_) | Pattern::Wildcard(_) => exhausted = true,
+                    Pattern::Literal(_) => literals.push(printed),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn parse_function_literal(&mut self) -> Option<FunctionLiteral> {
+        let token = self.cur_token.clone();
+        let doc = self.take_doc(&token);
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+
+        let (parameters, rest) = self.parse_function_parameters()?;
+        let result = self.parse_optional_annotation(TokenType::Arrow)?;
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        // A function body starts a new context: loops around the literal do
+        // not make `break` valid inside it.
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+
+        Some(FunctionLiteral {
+            token,
+            parameters,
+            rest,
+            result,
+            body,
+            doc,
+        })
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<(Vec<Parameter>, Option<Identifier>)> {
+        let mut parameters = Vec::new();
+        let mut rest = None;
+
+        if self.peek_token_is(TokenType::Rparen) {
+            self.next_token();
+            return Some((parameters, rest));
+        }
+
+        loop {
+            // The rest parameter collects the remaining arguments, so it has
+            // to be the last one.
+            if self.peek_token_is(TokenType::Ellipsis) {
+                self.next_token();
+                if !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                rest = Some(self.parse_identifier());
+                break;
+            }
+
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let name = self.parse_identifier();
+            let annotation = self.parse_optional_annotation(TokenType::Colon)?;
+
+            let default = if self.peek_token_is(TokenType::Assign) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_nested_expression()?)
+            } else {
+                None
+            };
+
+            parameters.push(Parameter {
+                name,
+                annotation,
+                default,
+            });
+
+            if !self.peek_token_is(TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        self.check_parameters(&parameters, rest.as_ref());
+
+        Some((parameters, rest))
+    }
+
+    // Macro parameters are plain names: arguments are code, so there is
+    // nothing to default or to collect.
+    fn parse_macro_literal(&mut self) -> Option<MacroLiteral> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+
+        let mut parameters: Vec<Identifier> = Vec::new();
+        if self.peek_token_is(TokenType::Rparen) {
+            self.next_token();
+        } else {
+            loop {
+                if !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                parameters.push(self.parse_identifier());
+
+                if !self.peek_token_is(TokenType::Comma) {
+                    break;
+                }
+                self.next_token();
+            }
+            if !self.expect_peek(TokenType::Rparen) {
+                return None;
+            }
+        }
+        let checked: Vec<Parameter> = parameters
+            .iter()
+            .map(|name| Parameter {
+                name: name.clone(),
+                annotation: None,
+                default: None,
+            })
+            .collect();
+        self.check_parameters(&checked, None);
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+
+        Some(MacroLiteral {
+            token,
+            parameters,
+            body,
+        })
+    }
+
+    // Parameter names must be unique, and once a parameter has a default
+    // every later one needs one too.
+    fn check_parameters(&mut self, parameters: &[Parameter], rest: Option<&Identifier>) {
+        let mut seen = Vec::new();
+        let mut optional = false;
+
+        for param in parameters {
+            if param.default.is_some() {
+                optional = true;
+            } else if optional {
+                self.errors.push(format!(
+                    "required parameter {} after a parameter with a default at {}",
+                    param.name, param.name.token.span
+                ));
+            }
+        }
+
+        for name in parameters.iter().map(|p| &p.name).chain(rest) {
+            if seen.contains(&name.value) {
+                self.errors.push(format!(
+                    "duplicate parameter {} at {}",
+                    name.value, name.token.span
+                ));
+            } else {
+                seen.push(name.value.clone());
+            }
+        }
+    }
+
+    fn parse_call_expression(&mut self, function: Expression) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let (arguments, named_arguments) = self.parse_call_arguments()?;
+
+        Some(Expression::Call(CallExpression {
+            token,
+            function: Box::new(function),
+            arguments,
+            named_arguments,
+        }))
+    }
+
+    // Positional arguments, then `name: value` pairs. Which names are valid
+    // depends on the function being called, so that is checked at runtime.
+    fn parse_call_arguments(&mut self) -> Option<CallArguments> {
+        let mut arguments = Vec::new();
+        let mut named_arguments: Vec<(Identifier, Expression)> = Vec::new();
+
+        if self.peek_token_is(TokenType::Rparen) {
+            self.next_token();
+            return Some((arguments, named_arguments));
+        }
+
+        loop {
+            self.next_token();
+
+            if self.cur_token_is(TokenType::Ident) && self.peek_token_is(TokenType::Colon) {
+                let name = self.parse_identifier();
+                self.next_token();
+                self.next_token();
+                let value = self.parse_nested_expression()?;
+
+                if named_arguments.iter().any(|(n, _)| n.value == name.value) {
+                    self.errors.push(format!(
+                        "duplicate argument {} at {}",
+                        name.value, name.token.span
+                    ));
+                }
+                named_arguments.push((name, value));
+            } else {
+                let span = self.cur_token.span;
+                let value = self.parse_nested_expression()?;
+
+                if !named_arguments.is_empty() {
+                    self.errors.push(format!(
+                        "positional argument after named arguments at {span}"
+                    ));
+                }
+                arguments.push(value);
+            }
+
+            if !self.peek_token_is(TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        Some((arguments, named_arguments))
+    }
+
+    fn parse_array_literal(&mut self) -> Option<ArrayLiteral> {
+        let token = self.cur_token.clone();
+        let elements = self.parse_expression_list(TokenType::Rbracket)?;
+
+        Some(ArrayLiteral { token, elements })
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let index = self.parse_nested_expression()?;
+
+        if !self.expect_peek(TokenType::Rbracket) {
+            return None;
+        }
+        if let Expression::Range(range) = &index {
+            self.open_ranges.retain(|&span| span != range.token.span);
+        }
+
+        Some(Expression::Index(IndexExpression {
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+        }))
+    }
+
+    // A `{` in expression position always starts a hash literal: blocks are
+    // only parsed where the grammar expects one, after `if`, `else` and `fn`.
+    fn parse_hash_literal(&mut self) -> Option<HashLiteral> {
+        let token = self.cur_token.clone();
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(TokenType::Rbrace) {
+            self.next_token();
+            let key = self.parse_nested_expression()?;
+
+            if !self.expect_peek(TokenType::Colon) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_nested_expression()?;
+
+            pairs.push((key, value));
+
+            // Pairs are separated by commas, and a trailing comma is allowed.
+            if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        self.check_duplicate_keys(&pairs);
+
+        Some(HashLiteral { token, pairs })
+    }
+
+    // Warn about literal keys that appear more than once, since only one of
+    // their values can end up in the hash.
+    fn check_duplicate_keys(&mut self, pairs: &[(Expression, Expression)]) {
+        let mut seen = Vec::new();
+
+        for (key, _) in pairs {
+            let span = match key {
+                Expression::Integer(e) => e.token.span,
+                Expression::Boolean(e) => e.token.span,
+                Expression::String(e) => e.token.span,
+                _ => continue,
+            };
+
+            let printed = key.to_string();
+            if seen.contains(&printed) {
+                self.warnings
+                    .push(format!("duplicate key {printed} in hash literal at {span}"));
+            } else {
+                seen.push(printed);
+            }
+        }
     }
 
-    fn parse_program(&self) {}
+    // Parse comma separated expressions up to the closing `end` token, as
+    // used by array literals.
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Expression>> {
+        let mut args = Vec::new();
+
+        if self.peek_token_is(end.clone()) {
+            self.next_token();
+            return Some(args);
+        }
+
+        self.next_token();
+        args.push(self.parse_nested_expression()?);
+
+        while self.peek_token_is(TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            args.push(self.parse_nested_expression()?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+
+        Some(args)
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::ast::Node;
     use crate::lexer;
 
+    pub(crate) fn parse(input: &str) -> Program {
+        let lex = lexer::new(input);
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+        program
+    }
+
+    fn check_parser_errors(parser: &Parser) {
+        let errors = parser.errors();
+        assert!(errors.is_empty(), "parser has errors: {errors:?}");
+    }
+
     #[test]
     fn test_let_statement() {
         let input = r#"
@@ -48,8 +1450,1272 @@ mod tests {
         "#;
 
         let lex = lexer::new(input);
-        let parser = new(Rc::new(RefCell::new(lex)));
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+
+        assert_eq!(program.statements.len(), 3);
+        for (stmt, name) in program.statements.iter().zip(["x", "y", "foobar"]) {
+            assert_eq!(stmt.token_literal(), "let");
+            match stmt {
+                Statement::Let(s) => match &s.pattern {
+                    Pattern::Identifier(ident) => {
+                        assert_eq!(ident.value, name);
+                        assert_eq!(ident.token_literal(), name);
+                    }
+                    other => panic!("expected identifier pattern, got {other:?}"),
+                },
+                other => panic!("expected let statement, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_let_statement_errors() {
+        let input = "let = 5; let x 5;";
+
+        let lex = lexer::new(input);
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+        parser.parse_program();
+
+        assert_eq!(
+            parser.errors()[0],
+            "expected next token to be Ident, got Assign instead"
+        );
+        assert_eq!(
+            parser.errors()[2],
+            "expected next token to be Assign, got Int instead"
+        );
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let program = parse("let [a, b, ...rest] = list; let {name, age: years} = person;");
+
+        let patterns: Vec<&Pattern> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Let(s) => &s.pattern,
+                other => panic!("expected let statement, got {other:?}"),
+            })
+            .collect();
+
+        match patterns[0] {
+            Pattern::Array(p) => {
+                assert_eq!(p.elements.len(), 2);
+                assert_eq!(p.elements[1].to_string(), "b");
+                assert_eq!(p.rest.as_ref().map(|r| r.value.as_str()), Some("rest"));
+            }
+            other => panic!("expected array pattern, got {other:?}"),
+        }
+
+        match patterns[1] {
+            Pattern::Hash(p) => {
+                let fields: Vec<(String, String)> = p
+                    .fields
+                    .iter()
+                    .map(|(k, v)| (k.value.clone(), v.to_string()))
+                    .collect();
+                assert_eq!(
+                    fields,
+                    [
+                        ("name".to_string(), "name".to_string()),
+                        ("age".to_string(), "years".to_string())
+                    ]
+                );
+            }
+            other => panic!("expected hash pattern, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_pattern_errors() {
+        let tests = vec![
+            ("let [a, +] = x;", "expected a pattern, got Plus instead"),
+            (
+                "let [...a, b] = x;",
+                "expected next token to be Rbracket, got Comma instead",
+            ),
+            (
+                "let [a, ...] = x;",
+                "expected next token to be Ident, got Rbracket instead",
+            ),
+            (
+                "let {\"a\": b} = x;",
+                "expected next token to be Ident, got String instead",
+            ),
+            (
+                "let [a, {b: a}] = x;",
+                "duplicate binding a in pattern at 1:13",
+            ),
+            ("let {a, a} = x;", "duplicate binding a in pattern at 1:9"),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let program = parse(
+            r#"match x { 0 => "zero", -1 => "minus one", [h, ...t] if h > 0 => h, {kind: "dot"} => 1, _ => y, }"#,
+        );
+
+        let stmt = match &program.statements[0] {
+            Statement::Expression(s) => s,
+            other => panic!("expected expression statement, got {other:?}"),
+        };
+        let expr = match &stmt.expression {
+            Expression::Match(e) => e,
+            other => panic!("expected match expression, got {other:?}"),
+        };
+
+        assert_eq!(expr.subject.to_string(), "x");
+        let arms: Vec<(String, Option<String>, String)> = expr
+            .arms
+            .iter()
+            .map(|a| {
+                (
+                    a.pattern.to_string(),
+                    a.guard.as_ref().map(|g| g.to_string()),
+                    a.body.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            arms,
+            [
+                ("0".to_string(), None, "\"zero\"".to_string()),
+                ("-1".to_string(), None, "\"minus one\"".to_string()),
+                (
+                    "[h, ...t]".to_string(),
+                    Some("h > 0".to_string()),
+                    "h".to_string()
+                ),
+                ("{kind: \"dot\"}".to_string(), None, "1".to_string()),
+                ("_".to_string(), None, "y".to_string()),
+            ]
+        );
+        assert!(matches!(expr.arms[4].pattern, Pattern::Wildcard(_)));
+        assert!(matches!(
+            &expr.arms[1].pattern,
+            Pattern::Literal(literal) if matches!(literal.as_ref(), Expression::Prefix(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_expression_errors() {
+        let tests = vec![
+            (
+                "match x { 1 2 }",
+                "expected next token to be FatArrow, got Int instead",
+            ),
+            (
+                "match x { 1 => 2 3 }",
+                "expected next token to be Comma, got Int instead",
+            ),
+            (
+                "match x 1",
+                "expected next token to be Lbrace, got Int instead",
+            ),
+            (
+                "match x { a + 1 => 2 }",
+                "expected next token to be FatArrow, got Plus instead",
+            ),
+            (
+                "match x { [a, a] => a }",
+                "duplicate binding a in pattern at 1:15",
+            ),
+            (
+                "match x { fn => a }",
+                "expected a pattern, got Function instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unreachable_match_arms() {
+        let tests = vec![
+            ("match x { 1 => a, 2 => b, _ => c }", vec![]),
+            (
+                "match x { _ => a, 1 => b, y => c }",
+                vec![
+                    "unreachable match arm 1 at 1:19",
+                    "unreachable match arm y at 1:27",
+                ],
+            ),
+            (
+                "match x { y if y > 1 => a, -1 => b, -1 => c, true => d }",
+                vec!["unreachable match arm -1 at 1:37"],
+            ),
+            ("match x { 1 if a => a, 1 => b }", vec![]),
+            ("match x { [y] => a, [z] => b }", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(parser.warnings(), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_newline_terminated_statements() {
+        let tests = vec![
+            (
+                "let a = 1\nlet b = a +\n  2\nb",
+                "let a = 1;\nlet b = a + 2;\nb;",
+            ),
+            ("a\n(b)", "a;\nb;"),
+            ("a\n[1]", "a;\n[1];"),
+            ("x =\n  -1\ny", "x = -1;\ny;"),
+            ("f(a,\n  b)\n", "f(a, b);"),
+            ("let x = (a\n  + b)", "let x = a + b;"),
+            ("[1,\n 2\n]", "[1, 2];"),
+            ("{\"a\": 1\n  - 2}", r#"{"a": 1 - 2};"#),
+            ("m[i\n  + 1]", "m[i + 1];"),
+            (
+                "if (a\n  == b) { c }\nelse { d }",
+                "if (a == b) { c; } else { d; };",
+            ),
+            (
+                "let f = fn(x) {\n  let y = x\n  -y\n}",
+                "let f = fn(x) { let y = x; -y; };",
+            ),
+            ("g(fn() {\n  a\n  (b)\n})", "g(fn() { a; b; });"),
+            (
+                "while (x) {\n  x -= 1\n  break\n}",
+                "while (x) { x -= 1; break; };",
+            ),
+            ("a; b\nc", "a;\nb;\nc;"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input:?}");
+        }
+    }
 
+    #[test]
+    fn test_newline_before_operator() {
+        let lex = lexer::new("let x = a\n  + b");
+        let mut parser = new(Rc::new(RefCell::new(lex)));
         let program = parser.parse_program();
+
+        assert_eq!(program.to_string(), "let x = a;\nb;");
+        assert_eq!(parser.errors(), ["no prefix parse function for Plus found"]);
+    }
+
+    #[test]
+    fn test_range_expression() {
+        let tests = vec![
+            ("1..10", "1..10;"),
+            ("0..=n - 1", "0..=n - 1;"),
+            ("a < b..c", "a < b..c;"),
+            ("x = 1..2", "x = 1..2;"),
+            ("arr[1..3]", "arr[1..3];"),
+            ("str[..5]", "str[..5];"),
+            ("str[-2..]", "str[-2..];"),
+            ("str[..=-1]", "str[..=-1];"),
+            ("arr[..]", "arr[..];"),
+            ("xs[ys[..2][0]..]", "xs[ys[..2][0]..];"),
+            (
+                "for (i in 0..len(xs)) { i }",
+                "for (i in 0..len(xs)) { i; };",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input:?}");
+        }
+
+        let program = parse("xs[1..=3]");
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Index(index),
+        }) = &program.statements[0]
+        else {
+            panic!("expected index expression, got {:?}", program.statements[0]);
+        };
+        let Expression::Range(range) = index.index.as_ref() else {
+            panic!("expected range, got {:?}", index.index);
+        };
+        assert!(range.inclusive);
+        assert_eq!(
+            range.start.as_ref().map(|s| s.to_string()),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            range.end.as_ref().map(|e| e.to_string()),
+            Some("3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_range_expression_errors() {
+        let tests = vec![
+            ("1..2..3", "ranges cannot be chained at 1:5"),
+            ("xs[1..=]", "no prefix parse function for Rbracket found"),
+            ("f(1..)", "no prefix parse function for Rparen found"),
+            (
+                "for (i in 0..) {}",
+                "no prefix parse function for Rparen found",
+            ),
+            // Open ranges only slice.
+            ("[1..]", "open ranges can only be used to slice at 1:3"),
+            (
+                "let r = ..5;",
+                "open ranges can only be used to slice at 1:9",
+            ),
+            (
+                "for (i in ..3) {}",
+                "open ranges can only be used to slice at 1:11",
+            ),
+            ("xs[[..1]]", "open ranges can only be used to slice at 1:5"),
+            (
+                "xs[i = 1..]",
+                "open ranges can only be used to slice at 1:9",
+            ),
+            (
+                "fn() { xs[1..] + [..2] }",
+                "open ranges can only be used to slice at 1:19",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipe_expression() {
+        let tests = vec![
+            ("x |> f", "f(x);"),
+            ("x |> f(y)", "f(x, y);"),
+            (
+                "xs |> map(double) |> filter(even)",
+                "filter(map(xs, double), even);",
+            ),
+            ("a + b |> f", "f(a + b);"),
+            ("1..10 |> sum", "sum(1..10);"),
+            ("x |> f(b: 2)", "f(x, b: 2);"),
+            ("x |> f(y)(z)", "f(y)(x, z);"),
+            ("x |> fn(v) { v }", "fn(v) { v; }(x);"),
+            ("x |> (f |> g)", "g(f)(x);"),
+            ("xs |> (makeFilter(even))", "makeFilter(even)(xs);"),
+            ("x |> (f)(y)", "f(x, y);"),
+            ("x |> (f)", "f(x);"),
+            ("let y = x |> f", "let y = f(x);"),
+            ("y = x |> f", "y = f(x);"),
+            ("xs\n  |> f\n  |> g\nh", "g(f(xs));\nh;"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input:?}");
+        }
+
+        // The desugared call is the same tree as the call written out.
+        assert_eq!(parse("x |> f |> g(1)"), parse("g(f(x), 1)"));
+    }
+
+    #[test]
+    fn test_return_statement() {
+        let program = parse("return 5; return 10; return add(15);");
+
+        assert_eq!(program.statements.len(), 3);
+        for stmt in &program.statements {
+            assert_eq!(stmt.token_literal(), "return");
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        // The printer only keeps the parentheses that are needed to rebuild
+        // the same tree, so a wrong grouping shows up as a different string.
+        let tests = vec![
+            ("-a * b", "-a * b;"),
+            ("-(a * b)", "-(a * b);"),
+            ("!-a", "!-a;"),
+            ("a + b + c", "a + b + c;"),
+            ("a + b - c", "a + b - c;"),
+            ("a * b * c", "a * b * c;"),
+            ("a * b / c", "a * b / c;"),
+            ("a + b / c", "a + b / c;"),
+            ("(a + b) / c", "(a + b) / c;"),
+            ("a + b * c + d / e - f", "a + b * c + d / e - f;"),
+            ("5 > 4 == 3 < 4", "5 > 4 == 3 < 4;"),
+            ("5 < (4 != 3) > 4", "5 < (4 != 3) > 4;"),
+            ("3 + 4 * 5 == 3 * 1 + 4 * 5", "3 + 4 * 5 == 3 * 1 + 4 * 5;"),
+            ("true == !false", "true == !false;"),
+            ("1 + (2 + 3) + 4", "1 + (2 + 3) + 4;"),
+            ("(5 + 5) * 2", "(5 + 5) * 2;"),
+            ("2 / (5 + 5)", "2 / (5 + 5);"),
+            ("-(5 + 5)", "-(5 + 5);"),
+            ("!(true == true)", "!(true == true);"),
+            ("a + add(b * c) + d", "a + add(b * c) + d;"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8));",
+            ),
+            ("add(a + b + c * d / f + g)", "add(a + b + c * d / f + g);"),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "a * [1, 2, 3, 4][b * c] * d;",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add(a * b[2], b[1], 2 * [1, 2][1]);",
+            ),
+            ("-a[0]", "-a[0];"),
+            ("(-a)[0]", "(-a)[0];"),
+            ("(a + b)[c]", "(a + b)[c];"),
+            ("f(x)[0](y)", "f(x)[0](y);"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.to_string(), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let program = parse("if (x < y) { x } else { y }");
+
+        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.statements[0].token_literal(), "if");
+        assert_eq!(program.to_string(), "if (x < y) { x; } else { y; };");
+    }
+
+    #[test]
+    fn test_function_literal() {
+        let tests = vec![
+            ("fn() {};", "fn() {};"),
+            ("fn(x) {};", "fn(x) {};"),
+            ("fn(x, y, z) { x + y; };", "fn(x, y, z) { x + y; };"),
+            ("fn(a, b = 10) {}", "fn(a, b = 10) {};"),
+            ("fn(a = 1, b = a * 2) {}", "fn(a = 1, b = a * 2) {};"),
+            ("fn(...rest) {}", "fn(...rest) {};"),
+            ("fn(a, b = [], ...rest) {}", "fn(a, b = [], ...rest) {};"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected);
+        }
+
+        let program = parse("fn(a, b = 10, ...rest) {}");
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Function(function),
+        }) = &program.statements[0]
+        else {
+            panic!("expected function literal, got {:?}", program.statements[0]);
+        };
+        assert_eq!(function.parameters.len(), 2);
+        assert_eq!(function.parameters[0].name.value, "a");
+        assert!(function.parameters[0].default.is_none());
+        assert_eq!(
+            function.parameters[1]
+                .default
+                .as_ref()
+                .map(|d| d.to_string()),
+            Some("10".to_string())
+        );
+        assert_eq!(
+            function.rest.as_ref().map(|r| r.value.as_str()),
+            Some("rest")
+        );
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let tests = vec![
+            ("let x: int = 5", "let x: int = 5;"),
+            ("let [a, b]: [string] = xs", "let [a, b]: [string] = xs;"),
+            (
+                "let h: {string: [bool]} = {}",
+                "let h: {string: [bool]} = {};",
+            ),
+            (
+                "fn(a: int, b: string) -> bool { true }",
+                "fn(a: int, b: string) -> bool { true; };",
+            ),
+            ("fn(a: int = 1, ...rest) {}", "fn(a: int = 1, ...rest) {};"),
+            (
+                "let apply = fn(f: fn(int) -> fn() -> null, x: range) -> fn() -> null { f(x) }",
+                "let apply = fn(f: fn(int) -> fn() -> null, x: range) -> fn() -> null { f(x); };",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input}");
+        }
+
+        let program = parse("let f = fn(a: [int], b) -> int { 1 };");
+        let Statement::Let(s) = &program.statements[0] else {
+            panic!("expected let statement, got {:?}", program.statements[0]);
+        };
+        let Expression::Function(function) = &s.value else {
+            panic!("expected a function, got {:?}", s.value);
+        };
+        assert_eq!(s.annotation, None);
+        assert!(matches!(
+            &function.parameters[0].annotation,
+            Some(TypeAnnotation::Array(element))
+                if matches!(element.as_ref(), TypeAnnotation::Named(name) if name.value == "int")
+        ));
+        assert_eq!(function.parameters[1].annotation, None);
+        assert_eq!(
+            function.result.as_ref().map(|r| r.to_string()),
+            Some("int".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_annotation_errors() {
+        let tests = vec![
+            ("let x: integer = 5", "unknown type integer at 1:8"),
+            ("let x: = 5", "expected a type, got Assign instead"),
+            (
+                "let x: [int = 5",
+                "expected next token to be Rbracket, got Assign instead",
+            ),
+            (
+                "let x: {int} = 5",
+                "expected next token to be Colon, got Rbrace instead",
+            ),
+            (
+                "let f: fn(int) = 5",
+                "expected next token to be Arrow, got Assign instead",
+            ),
+            ("fn(a) -> {}", "expected a type, got Rbrace instead"),
+            ("fn(a: 1) {}", "expected a type, got Int instead"),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input}"
+            );
+        }
+
+        // An unknown type is the only error it causes.
+        let tests = vec![
+            ("let x: foo = 1;", vec!["unknown type foo at 1:8"]),
+            ("fn(a: foo) {}", vec!["unknown type foo at 1:7"]),
+            (
+                "let f: fn(foo) -> [bar] = 1;",
+                vec!["unknown type foo at 1:11", "unknown type bar at 1:20"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_macro_literal() {
+        let program = parse("macro(x, y) { x + y; }");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Macro(m),
+        }) = &program.statements[0]
+        else {
+            panic!("expected macro literal, got {:?}", program.statements[0]);
+        };
+        let params: Vec<&str> = m.parameters.iter().map(|p| p.value.as_str()).collect();
+        assert_eq!(params, ["x", "y"]);
+        assert_eq!(m.body.to_string(), "{ x + y; }");
+
+        assert_eq!(
+            parse("let m = macro() {}").to_string(),
+            "let m = macro() {};"
+        );
+    }
+
+    #[test]
+    fn test_macro_literal_errors() {
+        let tests = vec![
+            ("macro(a, a) {}", "duplicate parameter a at 1:10"),
+            (
+                "macro(a = 1) {}",
+                "expected next token to be Rparen, got Assign instead",
+            ),
+            (
+                "macro(...a) {}",
+                "expected next token to be Ident, got Ellipsis instead",
+            ),
+            (
+                "while (x) { macro() { break } }",
+                "break outside of a loop at 1:23",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let program = parse(
+            "/// Adds two numbers.
+             ///
+             /// Works on integers.
+             let add = fn(a, b) { a + b };
+             /// A constant
+             let answer = 42;
+             // Not a doc comment
+             let plain = fn() {};
+             /// On the let
+             let wrapped = /// On the literal
+             fn() {};
+             /// Dropped: nothing to attach to
+             map(xs, /// Doubles
+             fn(x) { x * 2 });",
+        );
+
+        let docs: Vec<(Option<&str>, Option<&str>)> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Let(s) => {
+                    let func = match &s.value {
+                        Expression::Function(func) => func.doc.as_deref(),
+                        _ => None,
+                    };
+                    (s.doc.as_deref(), func)
+                }
+                Statement::Expression(ExpressionStatement {
+                    expression: Expression::Call(call),
+                }) => match &call.arguments[1] {
+                    Expression::Function(func) => (None, func.doc.as_deref()),
+                    other => panic!("expected function literal, got {other:?}"),
+                },
+                other => panic!("unexpected statement {other:?}"),
+            })
+            .collect();
+
+        let adds = "Adds two numbers.\n\nWorks on integers.";
+        assert_eq!(
+            docs,
+            [
+                (Some(adds), Some(adds)),
+                (Some("A constant"), None),
+                (None, None),
+                (Some("On the let"), Some("On the literal")),
+                (None, Some("Doubles")),
+            ]
+        );
+
+        assert_eq!(
+            program.statements[0].to_string(),
+            "/// Adds two numbers.\n///\n/// Works on integers.\nlet add = fn(a, b) { a + b; };"
+        );
+        assert_eq!(
+            program.statements[3].to_string(),
+            "/// On the let\nlet wrapped = /// On the literal\nfn() {};"
+        );
+        assert_eq!(parse(&program.to_string()), program);
+    }
+
+    #[test]
+    fn test_function_parameter_errors() {
+        let tests = vec![
+            (
+                "fn(a = 1, b) {}",
+                "required parameter b after a parameter with a default at 1:11",
+            ),
+            ("fn(a, b, a) {}", "duplicate parameter a at 1:10"),
+            ("fn(a, ...a) {}", "duplicate parameter a at 1:10"),
+            (
+                "fn(...a, b) {}",
+                "expected next token to be Rparen, got Comma instead",
+            ),
+            (
+                "fn(a,) {}",
+                "expected next token to be Ident, got Rparen instead",
+            ),
+            (
+                "fn(1) {}",
+                "expected next token to be Ident, got Int instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_call_expression() {
+        let program = parse("add(1, 2 * 3, 4 + 5);");
+
+        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.statements[0].token_literal(), "(");
+        assert_eq!(program.to_string(), "add(1, 2 * 3, 4 + 5);");
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let program = parse("f(1, b: 2 + 3, c: g(d: 4))");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Call(call),
+        }) = &program.statements[0]
+        else {
+            panic!("expected call expression, got {:?}", program.statements[0]);
+        };
+        assert_eq!(call.arguments.len(), 1);
+        let named: Vec<(String, String)> = call
+            .named_arguments
+            .iter()
+            .map(|(name, value)| (name.value.clone(), value.to_string()))
+            .collect();
+        assert_eq!(
+            named,
+            [
+                ("b".to_string(), "2 + 3".to_string()),
+                ("c".to_string(), "g(d: 4)".to_string())
+            ]
+        );
+
+        let tests = vec![
+            (
+                "f(a: 1, 2)",
+                "positional argument after named arguments at 1:9",
+            ),
+            ("f(a: 1, a: 2)", "duplicate argument a at 1:9"),
+            ("f(a: )", "no prefix parse function for Rparen found"),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_array_literal() {
+        let program = parse("[1, 2 * 2, 3 + 3]");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Array(array),
+        }) = &program.statements[0]
+        else {
+            panic!("expected array literal, got {:?}", program.statements[0]);
+        };
+
+        assert_eq!(array.elements.len(), 3);
+        assert_eq!(array.elements[0].to_string(), "1");
+        assert_eq!(array.elements[1].to_string(), "2 * 2");
+        assert_eq!(array.elements[2].to_string(), "3 + 3");
+
+        assert_eq!(parse("[]").to_string(), "[];");
+    }
+
+    #[test]
+    fn test_index_expression() {
+        let program = parse("myArray[1 + 1]");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Index(index),
+        }) = &program.statements[0]
+        else {
+            panic!("expected index expression, got {:?}", program.statements[0]);
+        };
+
+        assert_eq!(index.left.to_string(), "myArray");
+        assert_eq!(index.index.to_string(), "1 + 1");
+    }
+
+    #[test]
+    fn test_nested_index_expression() {
+        let program = parse("m[i][j + 1]");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::Index(outer),
+        }) = &program.statements[0]
+        else {
+            panic!("expected index expression, got {:?}", program.statements[0]);
+        };
+
+        assert_eq!(outer.index.to_string(), "j + 1");
+        match outer.left.as_ref() {
+            Expression::Index(inner) => {
+                assert_eq!(inner.left.to_string(), "m");
+                assert_eq!(inner.index.to_string(), "i");
+            }
+            other => panic!("expected nested index expression, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unterminated_array_literal() {
+        let lex = lexer::new("[1, 2");
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+        parser.parse_program();
+
+        assert_eq!(
+            parser.errors(),
+            ["expected next token to be Rbracket, got Eof instead"]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let tests = vec![
+            ("if (x) { 1", vec!["unterminated block starting at 1:8"]),
+            (
+                "fn() {\n  while (x) { y",
+                vec![
+                    "unterminated block starting at 2:13",
+                    "unterminated block starting at 1:6",
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let ok = |depth: usize, open: &str, close: &str| {
+            format!("{}x{}", open.repeat(depth), close.repeat(depth))
+        };
+        let tests = vec![
+            ("(".repeat(10_000), "1:101"),
+            (ok(MAX_NESTING_DEPTH + 1, "(", ")"), "1:101"),
+            ("-".repeat(10_000), "1:101"),
+            // An `if` and its block each count as a level.
+            ("if (x) {".repeat(10_000), "1:401"),
+            ("let ".to_string() + &"[".repeat(10_000), "1:105"),
+            ("x = ".repeat(10_000) + "1", "1:401"),
+            // So does every operator of a chain.
+            ("1 + ".repeat(10_000) + "1", "1:401"),
+            ("f".to_string() + &"()".repeat(10_000), "1:201"),
+        ];
+
+        for (input, span) in tests {
+            let lex = lexer::new(&input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(
+                parser.errors(),
+                [format!("input nested too deeply at {span}")],
+                "input: {input:.20}"
+            );
+        }
+
+        // Anything within the limit still parses.
+        let input = ok(MAX_NESTING_DEPTH - 1, "[", "]");
+        assert_eq!(parse(&input).to_string(), input + ";");
+    }
+
+    // Feed random bytes and random sequences of tokens to the parser: it may
+    // report errors but must always return.
+    #[test]
+    fn test_fuzz() {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+        let fragments = [
+            "let ", "fn", "(", ")", "{", "}", "[", "]", ",", ";", ":", "\n", "=", "+=", "==", "+",
+            "-", "!", "*", "/", "<", ">", "|>", "..", "..=", "...", "=>", "->", "_", "x", "1",
+            "\"s\"", "true", "if", "else", "while", "for", "in", "break", "continue", "return",
+            "match", "int", "é", ".", "|", "\"",
+        ];
+
+        for _ in 0..2_000 {
+            let input = if next(2) == 0 {
+                let bytes: Vec<u8> = (0..next(64)).map(|_| next(256) as u8).collect();
+                String::from_utf8_lossy(&bytes).into_owned()
+            } else {
+                (0..next(64))
+                    .map(|_| fragments[next(fragments.len())])
+                    .collect()
+            };
+
+            let lex = lexer::new(&input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            let program = parser.parse_program();
+            if parser.errors().is_empty() {
+                let printed = program.to_string();
+                assert_eq!(parse(&printed).to_string(), printed, "input: {input:?}");
+            }
+        }
+    }
+
+    fn parse_hash(input: &str) -> HashLiteral {
+        let program = parse(input);
+
+        match &program.statements[0] {
+            Statement::Expression(ExpressionStatement {
+                expression: Expression::Hash(hash),
+            }) => hash.clone(),
+            other => panic!("expected hash literal, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let program = parse(r#""hello world";"#);
+
+        match &program.statements[0] {
+            Statement::Expression(ExpressionStatement {
+                expression: Expression::String(s),
+            }) => assert_eq!(s.value, "hello world"),
+            other => panic!("expected string literal, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_hash_literal_string_keys() {
+        let hash = parse_hash(r#"{"one": 1, "two": 2, "three": 3}"#);
+
+        let pairs: Vec<(String, String)> = hash
+            .pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                (r#""one""#.to_string(), "1".to_string()),
+                (r#""two""#.to_string(), "2".to_string()),
+                (r#""three""#.to_string(), "3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_literal_arbitrary_keys() {
+        let hash = parse_hash(r#"{"name": "monkey", 1: true, key: value, 2 * 3: [1]}"#);
+
+        assert_eq!(hash.pairs.len(), 4);
+        assert!(matches!(hash.pairs[0].0, Expression::String(_)));
+        assert!(matches!(hash.pairs[1].0, Expression::Integer(_)));
+        assert!(matches!(hash.pairs[2].0, Expression::Identifier(_)));
+        assert!(matches!(hash.pairs[3].0, Expression::Infix(_)));
+        assert_eq!(
+            hash.to_string(),
+            r#"{"name": "monkey", 1: true, key: value, 2 * 3: [1]}"#
+        );
+    }
+
+    #[test]
+    fn test_hash_literal_empty_and_trailing_comma() {
+        assert!(parse_hash("{}").pairs.is_empty());
+        assert_eq!(parse_hash("{a: 1,}").pairs.len(), 1);
+        assert_eq!(parse_hash("{a: 1, b: 2,}").to_string(), "{a: 1, b: 2}");
+    }
+
+    #[test]
+    fn test_hash_literal_errors() {
+        let tests = vec![
+            (
+                "{a: 1 b: 2}",
+                "expected next token to be Comma, got Ident instead",
+            ),
+            ("{a 1}", "expected next token to be Colon, got Int instead"),
+            ("{,}", "no prefix parse function for Comma found"),
+            ("{a: 1", "expected next token to be Comma, got Eof instead"),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors()[0], expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_brace_disambiguation() {
+        // Braces after `if`, `else` and `fn` open blocks; anywhere an
+        // expression is expected they open a hash literal.
+        let program = parse("if (x) { {} } else { {a: 1}; }; fn() { {} }");
+        assert_eq!(
+            program.to_string(),
+            "if (x) { {}; } else { {a: 1}; };\nfn() { {}; };"
+        );
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::If(if_expr),
+        }) = &program.statements[0]
+        else {
+            panic!("expected if expression, got {:?}", program.statements[0]);
+        };
+        assert!(matches!(
+            &if_expr.consequence.statements[0],
+            Statement::Expression(ExpressionStatement {
+                expression: Expression::Hash(_),
+            })
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_hash_keys() {
+        let lex = lexer::new(r#"{"a": 1, b: 2, "a": 3, 1: x, b: 4, 01: y, true: 1, true: 2}"#);
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+        parser.parse_program();
+
+        assert!(parser.errors().is_empty());
+        assert_eq!(
+            parser.warnings(),
+            [
+                r#"duplicate key "a" in hash literal at 1:16"#,
+                "duplicate key 1 in hash literal at 1:36",
+                "duplicate key true in hash literal at 1:52",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_while_expression() {
+        let program = parse("while (x < 10) { let x = x + 1; if (x == 5) { break; } continue; }");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::While(w),
+        }) = &program.statements[0]
+        else {
+            panic!("expected while expression, got {:?}", program.statements[0]);
+        };
+
+        assert_eq!(w.condition.to_string(), "x < 10");
+        assert_eq!(w.body.statements.len(), 3);
+        assert!(matches!(w.body.statements[2], Statement::Continue(_)));
+        assert_eq!(
+            program.to_string(),
+            "while (x < 10) { let x = x + 1; if (x == 5) { break; }; continue; };"
+        );
+    }
+
+    #[test]
+    fn test_for_expression() {
+        let program = parse("for (item in [1, 2, 3]) { puts(item); }");
+
+        let Statement::Expression(ExpressionStatement {
+            expression: Expression::For(f),
+        }) = &program.statements[0]
+        else {
+            panic!("expected for expression, got {:?}", program.statements[0]);
+        };
+
+        assert_eq!(f.variable.value, "item");
+        assert_eq!(f.iterable.to_string(), "[1, 2, 3]");
+        assert_eq!(f.body.to_string(), "{ puts(item); }");
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let tests = vec![
+            ("break;", vec!["break outside of a loop at 1:1"]),
+            (
+                "if (x) { continue; }",
+                vec!["continue outside of a loop at 1:10"],
+            ),
+            (
+                "while (x) { fn() { break; } }",
+                vec!["break outside of a loop at 1:20"],
+            ),
+            (
+                "while (x) { break; }; continue;",
+                vec!["continue outside of a loop at 1:23"],
+            ),
+            ("for (x in xs) { while (y) { break; } continue; }", vec![]),
+            ("fn() { while (y) { if (z) { break; } } }", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors(), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_for_expression_errors() {
+        let tests = vec![
+            (
+                "for x in xs { }",
+                "expected next token to be Lparen, got Ident instead",
+            ),
+            (
+                "for (1 in xs) { }",
+                "expected next token to be Ident, got Int instead",
+            ),
+            (
+                "for (x of xs) { }",
+                "expected next token to be In, got Ident instead",
+            ),
+            (
+                "while (x) y",
+                "expected next token to be Lbrace, got Ident instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors()[0], expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_assign_expression() {
+        let tests = vec![
+            ("x = x + 1", "x", "=", "x + 1"),
+            ("arr[i] = v", "arr[i]", "=", "v"),
+            (r#"h["k"] = v"#, r#"h["k"]"#, "=", "v"),
+            ("x += 2 * y", "x", "+=", "2 * y"),
+            ("x -= 1", "x", "-=", "1"),
+            ("m[i][j] *= 2", "m[i][j]", "*=", "2"),
+            ("x /= f(y)", "x", "/=", "f(y)"),
+            ("a = b = c", "a", "=", "b = c"),
+        ];
+
+        for (input, target, operator, value) in tests {
+            let program = parse(input);
+
+            let Statement::Expression(ExpressionStatement {
+                expression: Expression::Assign(assign),
+            }) = &program.statements[0]
+            else {
+                panic!("expected assignment, got {:?}", program.statements[0]);
+            };
+
+            assert_eq!(assign.target.to_string(), target, "input: {input:?}");
+            assert_eq!(assign.operator, operator, "input: {input:?}");
+            assert_eq!(assign.value.to_string(), value, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_assign_precedence() {
+        let tests = vec![
+            ("x = 1 == 2", "x = 1 == 2;"),
+            ("(x = 1) == 2", "(x = 1) == 2;"),
+            ("f(x = 1)", "f(x = 1);"),
+            ("let y = x = 2;", "let y = x = 2;"),
+            ("while (i < 10) { i += 1; }", "while (i < 10) { i += 1; };"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_assign_target() {
+        let tests = vec![
+            ("1 = 2", "invalid assignment target 1 at 1:3"),
+            ("a + b = c", "invalid assignment target a + b at 1:7"),
+            ("f() += 1", "invalid assignment target f() at 1:5"),
+            ("-x = 1", "invalid assignment target -x at 1:4"),
+            ("(a = b) = c", "invalid assignment target a = b at 1:9"),
+            ("let x = [1] = 2", "invalid assignment target [1] at 1:13"),
+        ];
+
+        for (input, expected) in tests {
+            let lex = lexer::new(input);
+            let mut parser = new(Rc::new(RefCell::new(lex)));
+            parser.parse_program();
+
+            assert_eq!(parser.errors(), [expected], "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_no_prefix_parse_function() {
+        let lex = lexer::new("let x = ;");
+        let mut parser = new(Rc::new(RefCell::new(lex)));
+        parser.parse_program();
+
+        assert_eq!(
+            parser.errors(),
+            ["no prefix parse function for Semicolon found"]
+        );
     }
 }
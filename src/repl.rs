@@ -1,26 +1,91 @@
+use std::cell::RefCell;
 use std::io::{self, Write};
+use std::rc::Rc;
+use std::thread;
 
+use crate::arena::Ast;
+use crate::evaluator::{self, Environment};
 use crate::lexer;
-use crate::token::TokenType;
+use crate::macro_expansion::{self, Macros};
+use crate::parser;
+use crate::resolver;
+use crate::typecheck;
 
 const PROMPT: &str = ">>";
 
+// Read, evaluate and print lines until the end of the input. Evaluation
+// runs on a thread with a stack large enough for deeply recursive code.
 pub fn start() {
+    thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(run)
+        .expect("could not start the REPL thread")
+        .join()
+        .expect("the REPL thread panicked");
+}
+
+fn run() {
     let mut input = String::new();
+    // Bindings and macros stay defined for the rest of the session.
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut macros = Macros::new();
 
     loop {
         print!("{PROMPT} ");
         io::stdout().flush().unwrap();
         input.clear();
 
-        io::stdin().read_line(&mut input).unwrap();
-        let mut lex = lexer::new(&input);
+        if io::stdin().read_line(&mut input).unwrap() == 0 {
+            return;
+        }
+        let lex = lexer::new(&input);
+        let mut parser = parser::new(Rc::new(RefCell::new(lex)));
 
-        let mut tok = lex.next_token();
-        while tok.t_type != TokenType::Eof {
-            println!("{tok:?}");
+        let mut program = parser.parse_program();
+        for warning in parser.warnings() {
+            println!("\twarning: {warning}");
+        }
+        if !parser.errors().is_empty() {
+            for err in parser.errors() {
+                println!("\t{err}");
+            }
+            continue;
+        }
 
-            tok = lex.next_token();
+        macros.extend(macro_expansion::define_macros(&mut program));
+        let program = match macro_expansion::expand_macros(program, &macros) {
+            Ok(expanded) => expanded,
+            Err(errors) => {
+                for err in errors {
+                    println!("\t{err}");
+                }
+                continue;
+            }
+        };
+
+        // Names bound by earlier input are defined for this one, with types
+        // that are not known.
+        let ast = Ast::from(&program);
+        let resolution = resolver::resolve_after(&ast, &env.borrow().names());
+        for warning in &resolution.warnings {
+            println!("\twarning: {warning}");
+        }
+        if !resolution.errors.is_empty() {
+            for err in &resolution.errors {
+                println!("\t{err}");
+            }
+            continue;
         }
+        // Only the annotation errors running would fail on stop the input:
+        // the other type errors may be about untyped code that works.
+        let typing = typecheck::check(&ast, &resolution);
+        if !typing.annotation_errors.is_empty() {
+            for err in &typing.annotation_errors {
+                println!("\t{err}");
+            }
+            continue;
+        }
+
+        println!("{}", evaluator::eval(&program, &env));
     }
 }
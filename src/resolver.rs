@@ -0,0 +1,764 @@
+use std::collections::HashSet;
+
+use crate::arena::{
+    Ast, BlockId, Expr, ExprId, Ident, IdentId, Parameter, Pattern, PatternId, SideTable, Stmt,
+    StmtId,
+};
+use crate::evaluator::BUILTINS;
+use crate::token::Span;
+
+// What name resolution found out about a program. Scopes are the ones the
+// evaluator creates at runtime: the program, every function call, every
+// iteration of a `for` loop and every `match` arm. The blocks of `if` and
+// `while` share the scope around them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Resolution {
+    // The binding each used name refers to. Builtins have no entry.
+    pub uses: SideTable<Expr, Resolved>,
+    // The slot of every binding in its scope. A name declared twice in one
+    // scope keeps its slot.
+    pub slots: SideTable<Ident, usize>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resolved {
+    pub binding: IdentId,
+    // How many scopes out from the use the binding lives, 0 being the
+    // innermost.
+    pub depth: usize,
+    pub slot: usize,
+}
+
+// Bind every name used in `ast` to its `let`, parameter, `for` variable or
+// pattern, and report names that are undefined, shadow another binding or
+// are never used.
+//
+// Code runs top to bottom, so it sees the bindings of its scope declared
+// above it. A function body runs later, when it is called, so it sees every
+// binding of the scopes around it; this is what lets functions call each
+// other whatever their order. Unused bindings are not reported at the top
+// level, nor for names starting with `_`.
+pub fn resolve(ast: &Ast) -> Resolution {
+    resolve_input(ast, None)
+}
+
+// Resolve `ast` as code that runs after code which bound the names in
+// `earlier`, as each input of the REPL does. Those names have no binding in
+// `ast`, so their uses have no entry either. A later input may still define
+// the names a function body uses, so those that are undefined are only
+// warned about.
+pub fn resolve_after(ast: &Ast, earlier: &[String]) -> Resolution {
+    resolve_input(ast, Some(earlier))
+}
+
+fn resolve_input(ast: &Ast, earlier: Option<&[String]>) -> Resolution {
+    let mut resolver = Resolver {
+        ast,
+        earlier,
+        scopes: Vec::new(),
+        function_depth: 0,
+        used: HashSet::new(),
+        resolution: Resolution::default(),
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    let mut bindings = Vec::new();
+    for &stmt in &ast.statements {
+        resolver.hoist_stmt(stmt, &mut bindings);
+    }
+    resolver.enter_scope(bindings);
+    for &stmt in &ast.statements {
+        resolver.resolve_stmt(stmt);
+    }
+    resolver.exit_scope();
+
+    // Diagnostics come out in source order.
+    let mut resolution = resolver.resolution;
+    resolver.errors.sort_by_key(|(span, _)| span.start);
+    resolver.warnings.sort_by_key(|(span, _)| span.start);
+    resolution.errors = resolver.errors.into_iter().map(|(_, e)| e).collect();
+    resolution.warnings = resolver.warnings.into_iter().map(|(_, w)| w).collect();
+    resolution
+}
+
+struct Scope {
+    names: Vec<Name>,
+    // How many function bodies enclose the scope.
+    function_depth: usize,
+}
+
+// A name bound in a scope, possibly more than once.
+struct Name {
+    name: String,
+    bindings: Vec<IdentId>,
+    // The binding reached most recently, if any yet.
+    current: Option<IdentId>,
+}
+
+enum Lookup {
+    Found(Resolved),
+    // Only bound further down in a scope that runs the use first.
+    NotYet,
+    Undefined,
+}
+
+struct Resolver<'a> {
+    ast: &'a Ast,
+    // The names bound by earlier input, when more input may follow.
+    earlier: Option<&'a [String]>,
+    scopes: Vec<Scope>,
+    function_depth: usize,
+    used: HashSet<IdentId>,
+    resolution: Resolution,
+    errors: Vec<(Span, String)>,
+    warnings: Vec<(Span, String)>,
+}
+
+impl Resolver<'_> {
+    fn span(&self, ident: IdentId) -> Span {
+        self.ast
+            .spans
+            .idents
+            .get(ident)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn name(&self, ident: IdentId) -> &str {
+        &self.ast.idents[ident].name
+    }
+
+    // Open a scope holding `bindings`, which are given slots in order.
+    fn enter_scope(&mut self, bindings: Vec<IdentId>) {
+        let mut names: Vec<Name> = Vec::new();
+        for ident in bindings {
+            let name = self.name(ident);
+            let slot = match names.iter().position(|n| n.name == name) {
+                Some(slot) => slot,
+                None => {
+                    names.push(Name {
+                        name: name.to_string(),
+                        bindings: Vec::new(),
+                        current: None,
+                    });
+                    names.len() - 1
+                }
+            };
+            names[slot].bindings.push(ident);
+            self.resolution.slots.insert(ident, slot);
+        }
+        self.scopes.push(Scope {
+            names,
+            function_depth: self.function_depth,
+        });
+    }
+
+    fn exit_scope(&mut self) {
+        let scope = self.scopes.pop().expect("no scope to exit");
+        if self.scopes.is_empty() {
+            return;
+        }
+        for name in &scope.names {
+            if name.name.starts_with('_') {
+                continue;
+            }
+            for &ident in &name.bindings {
+                if !self.used.contains(&ident) {
+                    let span = self.span(ident);
+                    self.warnings
+                        .push((span, format!("unused binding {} at {span}", name.name)));
+                }
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Lookup {
+        let mut not_yet = false;
+        for (i, scope) in self.scopes.iter().enumerate().rev() {
+            let Some(slot) = scope.names.iter().position(|n| n.name == name) else {
+                continue;
+            };
+            let entry = &scope.names[slot];
+            let binding = match entry.current {
+                Some(binding) => binding,
+                None if self.function_depth > scope.function_depth => entry.bindings[0],
+                None => {
+                    not_yet = true;
+                    continue;
+                }
+            };
+            return Lookup::Found(Resolved {
+                binding,
+                depth: self.scopes.len() - 1 - i,
+                slot,
+            });
+        }
+        if not_yet {
+            Lookup::NotYet
+        } else {
+            Lookup::Undefined
+        }
+    }
+
+    // Make `ident` the current binding of its name in the innermost scope.
+    fn declare(&mut self, ident: IdentId) {
+        let name = self.name(ident).to_string();
+        let span = self.span(ident);
+        match self.lookup(&name) {
+            Lookup::Found(shadowed) => {
+                let shadowed = self.span(shadowed.binding);
+                self.warnings.push((
+                    span,
+                    format!("{name} shadows the binding from {shadowed} at {span}"),
+                ));
+            }
+            _ if self.bound_earlier(&name) => self
+                .warnings
+                .push((span, format!("{name} shadows an earlier binding at {span}"))),
+            _ => {}
+        }
+        let scope = self.scopes.last_mut().expect("no scope to declare in");
+        let entry = scope
+            .names
+            .iter_mut()
+            .find(|n| n.name == name)
+            .expect("binding was not hoisted into its scope");
+        entry.current = Some(ident);
+    }
+
+    fn declare_pattern(&mut self, pattern: PatternId) {
+        match &self.ast.patterns[pattern] {
+            Pattern::Identifier(ident) => self.declare(*ident),
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+            Pattern::Array { elements, rest } => {
+                for &element in elements {
+                    self.declare_pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.declare(*rest);
+                }
+            }
+            Pattern::Hash(fields) => {
+                for (_, value) in fields {
+                    self.declare_pattern(*value);
+                }
+            }
+        }
+    }
+
+    fn resolve_use(&mut self, expr: ExprId, name: &str) {
+        let span = self.ast.spans.exprs.get(expr).copied().unwrap_or_default();
+        match self.lookup(name) {
+            Lookup::Found(resolved) => {
+                self.used.insert(resolved.binding);
+                self.resolution.uses.insert(expr, resolved);
+            }
+            _ if BUILTINS.contains_key(name) || self.bound_earlier(name) => {}
+            Lookup::NotYet => self.errors.push((
+                span,
+                format!("{name} used before its declaration at {span}"),
+            )),
+            Lookup::Undefined if self.earlier.is_some() && self.function_depth > 0 => self
+                .warnings
+                .push((span, format!("undefined name {name} at {span}"))),
+            Lookup::Undefined => self
+                .errors
+                .push((span, format!("undefined name {name} at {span}"))),
+        }
+    }
+
+    fn bound_earlier(&self, name: &str) -> bool {
+        self.earlier
+            .is_some_and(|names| names.iter().any(|n| n == name))
+    }
+
+    fn resolve_block(&mut self, block: BlockId) {
+        for &stmt in &self.ast.blocks[block].statements {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: StmtId) {
+        match &self.ast.stmts[stmt] {
+            // The value cannot see the names the `let` binds.
+            Stmt::Let { pattern, value, .. } => {
+                self.resolve_expr(*value);
+                self.declare_pattern(*pattern);
+            }
+            Stmt::Return(value) | Stmt::Expression(value) => self.resolve_expr(*value),
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn resolve_exprs(&mut self, exprs: &[ExprId]) {
+        for &expr in exprs {
+            self.resolve_expr(expr);
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: ExprId) {
+        match &self.ast.exprs[expr] {
+            Expr::Identifier(name) => self.resolve_use(expr, name),
+            Expr::Integer(_) | Expr::Boolean(_) | Expr::String(_) => {}
+            Expr::Prefix { right, .. } => self.resolve_expr(*right),
+            Expr::Infix { left, right, .. } => self.resolve_exprs(&[*left, *right]),
+            Expr::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.resolve_expr(*condition);
+                self.resolve_block(*consequence);
+                if let Some(alt) = alternative {
+                    self.resolve_block(*alt);
+                }
+            }
+            Expr::Function {
+                parameters,
+                rest,
+                body,
+                ..
+            } => self.resolve_function(parameters, *rest, *body),
+            // A macro's body is code for the places it is expanded at.
+            Expr::Macro { .. } => {}
+            Expr::Call {
+                function,
+                arguments,
+                named_arguments,
+            } => {
+                // Unless a binding shadows it, `quote` is not a function: its
+                // argument is code, not a value.
+                if let Expr::Identifier(name) = &self.ast.exprs[*function] {
+                    if name == "quote" && !matches!(self.lookup(name), Lookup::Found(_)) {
+                        return;
+                    }
+                }
+                self.resolve_expr(*function);
+                self.resolve_exprs(arguments);
+                for (_, value) in named_arguments {
+                    self.resolve_expr(*value);
+                }
+            }
+            Expr::Array(elements) => self.resolve_exprs(elements),
+            Expr::Index { left, index } => self.resolve_exprs(&[*left, *index]),
+            Expr::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.resolve_exprs(&[*key, *value]);
+                }
+            }
+            Expr::While { condition, body } => {
+                self.resolve_expr(*condition);
+                self.resolve_block(*body);
+            }
+            Expr::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.resolve_expr(*iterable);
+                let mut bindings = vec![*variable];
+                self.hoist_block(*body, &mut bindings);
+                self.enter_scope(bindings);
+                self.declare(*variable);
+                self.resolve_block(*body);
+                self.exit_scope();
+            }
+            Expr::Assign { target, value, .. } => self.resolve_exprs(&[*target, *value]),
+            Expr::Match { subject, arms } => {
+                self.resolve_expr(*subject);
+                for arm in arms {
+                    let mut bindings = self.ast.pattern_bindings(arm.pattern);
+                    if let Some(guard) = arm.guard {
+                        self.hoist_expr(guard, &mut bindings);
+                    }
+                    self.hoist_expr(arm.body, &mut bindings);
+                    self.enter_scope(bindings);
+                    self.declare_pattern(arm.pattern);
+                    if let Some(guard) = arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_expr(arm.body);
+                    self.exit_scope();
+                }
+            }
+            Expr::Range { start, end, .. } => {
+                for bound in [start, end].into_iter().flatten() {
+                    self.resolve_expr(*bound);
+                }
+            }
+        }
+    }
+
+    // Parameters are bound in order, so a default sees the parameters
+    // before it.
+    fn resolve_function(&mut self, parameters: &[Parameter], rest: Option<IdentId>, body: BlockId) {
+        let mut bindings: Vec<IdentId> = parameters.iter().map(|p| p.name).collect();
+        bindings.extend(rest);
+        for default in parameters.iter().filter_map(|p| p.default) {
+            self.hoist_expr(default, &mut bindings);
+        }
+        self.hoist_block(body, &mut bindings);
+
+        self.function_depth += 1;
+        self.enter_scope(bindings);
+        for param in parameters {
+            if let Some(default) = param.default {
+                self.resolve_expr(default);
+            }
+            self.declare(param.name);
+        }
+        if let Some(rest) = rest {
+            self.declare(rest);
+        }
+        self.resolve_block(body);
+        self.exit_scope();
+        self.function_depth -= 1;
+    }
+
+    // Collect the bindings `let` statements make in the scope being entered,
+    // in source order. Function bodies, `for` bodies and `match` arms open
+    // scopes of their own.
+    fn hoist_block(&self, block: BlockId, bindings: &mut Vec<IdentId>) {
+        for &stmt in &self.ast.blocks[block].statements {
+            self.hoist_stmt(stmt, bindings);
+        }
+    }
+
+    fn hoist_stmt(&self, stmt: StmtId, bindings: &mut Vec<IdentId>) {
+        match &self.ast.stmts[stmt] {
+            Stmt::Let { pattern, value, .. } => {
+                self.hoist_expr(*value, bindings);
+                bindings.extend(self.ast.pattern_bindings(*pattern));
+            }
+            Stmt::Return(value) | Stmt::Expression(value) => self.hoist_expr(*value, bindings),
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn hoist_expr(&self, expr: ExprId, bindings: &mut Vec<IdentId>) {
+        let mut hoist = |expr: &ExprId| self.hoist_expr(*expr, bindings);
+        match &self.ast.exprs[expr] {
+            Expr::Identifier(_)
+            | Expr::Integer(_)
+            | Expr::Boolean(_)
+            | Expr::String(_)
+            | Expr::Function { .. }
+            | Expr::Macro { .. } => {}
+            Expr::Prefix { right, .. } => hoist(right),
+            Expr::Infix { left, right, .. } => {
+                hoist(left);
+                hoist(right);
+            }
+            Expr::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                hoist(condition);
+                self.hoist_block(*consequence, bindings);
+                if let Some(alt) = alternative {
+                    self.hoist_block(*alt, bindings);
+                }
+            }
+            Expr::Call {
+                function,
+                arguments,
+                named_arguments,
+            } => {
+                hoist(function);
+                arguments.iter().for_each(&mut hoist);
+                named_arguments.iter().for_each(|(_, value)| hoist(value));
+            }
+            Expr::Array(elements) => elements.iter().for_each(hoist),
+            Expr::Index { left, index } => {
+                hoist(left);
+                hoist(index);
+            }
+            Expr::Hash(pairs) => pairs.iter().for_each(|(key, value)| {
+                hoist(key);
+                hoist(value);
+            }),
+            Expr::While { condition, body } => {
+                hoist(condition);
+                self.hoist_block(*body, bindings);
+            }
+            Expr::For { iterable, .. } => hoist(iterable),
+            Expr::Assign { target, value, .. } => {
+                hoist(target);
+                hoist(value);
+            }
+            Expr::Match { subject, .. } => hoist(subject),
+            Expr::Range { start, end, .. } => [start, end].into_iter().flatten().for_each(hoist),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    // Every use of a name in `input`, with where its binding is and the
+    // depth and slot it resolves to.
+    fn uses(input: &str) -> Vec<String> {
+        let ast = Ast::from(&parse(input));
+        let resolution = resolve(&ast);
+        assert!(
+            resolution.errors.is_empty(),
+            "errors: {:?}",
+            resolution.errors
+        );
+
+        ast.exprs
+            .iter()
+            .filter_map(|(id, expr)| match expr {
+                Expr::Identifier(name) => Some((id, name)),
+                _ => None,
+            })
+            .map(|(id, name)| {
+                let at = ast.spans.exprs.get(id).unwrap();
+                match resolution.uses.get(id) {
+                    Some(r) => format!(
+                        "{name} {at} -> {} {}/{}",
+                        ast.spans.idents.get(r.binding).unwrap(),
+                        r.depth,
+                        r.slot
+                    ),
+                    None if BUILTINS.contains_key(name.as_str()) => {
+                        format!("{name} {at} -> builtin")
+                    }
+                    None => format!("{name} {at} -> none"),
+                }
+            })
+            .collect()
+    }
+
+    fn diagnostics(input: &str) -> (Vec<String>, Vec<String>) {
+        let resolution = resolve(&Ast::from(&parse(input)));
+        (resolution.errors, resolution.warnings)
+    }
+
+    #[test]
+    fn test_resolve_uses() {
+        let tests: Vec<(&str, Vec<&str>)> = vec![
+            (
+                "let x = 1; let y = x; y",
+                vec!["x 1:20 -> 1:5 0/0", "y 1:23 -> 1:16 0/1"],
+            ),
+            (
+                "let x = 1; let f = fn(a, b) { let c = a + b; c + x }; f(1, 2)",
+                vec![
+                    "a 1:39 -> 1:23 0/0",
+                    "b 1:43 -> 1:26 0/1",
+                    "c 1:46 -> 1:35 0/2",
+                    "x 1:50 -> 1:5 1/0",
+                    "f 1:55 -> 1:16 0/1",
+                ],
+            ),
+            // Closures reach as many scopes out as they are nested.
+            (
+                "let newAdder = fn(a) { fn(b) { a + b } }; newAdder(2)(3)",
+                vec![
+                    "a 1:32 -> 1:19 1/0",
+                    "b 1:36 -> 1:27 0/0",
+                    "newAdder 1:43 -> 1:5 0/0",
+                ],
+            ),
+            // Redeclaring a name keeps its slot; the value sees the old one.
+            (
+                "let x = 1; let x = x + 1; x",
+                vec!["x 1:20 -> 1:5 0/0", "x 1:27 -> 1:16 0/0"],
+            ),
+            // `if` and `while` blocks share the scope around them.
+            (
+                "let a = 1; if (a) { let b = a; b } while (a) { a = 2 }",
+                vec![
+                    "a 1:16 -> 1:5 0/0",
+                    "a 1:29 -> 1:5 0/0",
+                    "b 1:32 -> 1:25 0/1",
+                    "a 1:43 -> 1:5 0/0",
+                    "a 1:48 -> 1:5 0/0",
+                ],
+            ),
+            (
+                "let xs = [1]; for (x in xs) { let y = x; len(y) }",
+                vec![
+                    "xs 1:25 -> 1:5 0/0",
+                    "x 1:39 -> 1:20 0/0",
+                    "len 1:42 -> builtin",
+                    "y 1:46 -> 1:35 0/1",
+                ],
+            ),
+            (
+                "let v = 1; match (v) { [h, ...t] if h => t, n => n + v }",
+                vec![
+                    "v 1:19 -> 1:5 0/0",
+                    "h 1:37 -> 1:25 0/0",
+                    "t 1:42 -> 1:31 0/1",
+                    "n 1:50 -> 1:45 0/0",
+                    "v 1:54 -> 1:5 1/0",
+                ],
+            ),
+            // Parameters and their defaults see the parameters before them.
+            (
+                "let f = fn(a, b = a + 1, ...c) { c }; f(1)",
+                vec![
+                    "a 1:19 -> 1:12 0/0",
+                    "c 1:34 -> 1:29 0/2",
+                    "f 1:39 -> 1:5 0/0",
+                ],
+            ),
+            // Quoted code and macro bodies are not resolved.
+            (
+                "let m = macro(a) { quote(a + b) }; quote(c)",
+                vec![
+                    "quote 1:20 -> none",
+                    "a 1:26 -> none",
+                    "b 1:30 -> none",
+                    "quote 1:36 -> none",
+                    "c 1:42 -> none",
+                ],
+            ),
+            (
+                "let quote = fn(x) { x }; quote(len)",
+                vec![
+                    "x 1:21 -> 1:16 0/0",
+                    "quote 1:26 -> 1:5 0/0",
+                    "len 1:32 -> builtin",
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(uses(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_forward_references() {
+        let tests: Vec<(&str, Vec<&str>)> = vec![
+            // Function bodies run after the lets below them.
+            (
+                "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+                 let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };",
+                vec![
+                    "n 1:26 -> 1:17 0/0",
+                    "isOdd 1:50 -> 2:22 1/1",
+                    "n 1:56 -> 1:17 0/0",
+                    "n 2:42 -> 2:33 0/0",
+                    "isEven 2:67 -> 1:5 1/0",
+                    "n 2:74 -> 2:33 0/0",
+                ],
+            ),
+            (
+                "let f = fn() { fn() { x } }; let x = 1;",
+                vec!["x 1:23 -> 1:34 2/1"],
+            ),
+            // Before any of them runs, a redeclared name is its first binding.
+            (
+                "let f = fn() { x }; let x = 1; let x = 2;",
+                vec!["x 1:16 -> 1:25 1/1"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(uses(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("x + 1", vec!["undefined name x at 1:1"]),
+            (
+                "let f = fn(a) { a + b }; f(c)",
+                vec!["undefined name b at 1:21", "undefined name c at 1:28"],
+            ),
+            ("let x = x;", vec!["x used before its declaration at 1:9"]),
+            (
+                "let f = fn() { let y = z; let z = 1; y + z }; f()",
+                vec!["z used before its declaration at 1:24"],
+            ),
+            ("for (x in [1]) { x }; x", vec!["undefined name x at 1:23"]),
+            ("match (1) { n => n }; n", vec!["undefined name n at 1:23"]),
+            ("y = 1", vec!["undefined name y at 1:1"]),
+            ("unquote(1)", vec!["undefined name unquote at 1:1"]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(diagnostics(input).0, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_warnings() {
+        let tests = vec![
+            (
+                "let x = 1; let f = fn(x) { x }; f(x)",
+                vec!["x shadows the binding from 1:5 at 1:23"],
+            ),
+            (
+                "let f = fn() { let a = 1; let a = a + 1; a }; f()",
+                vec!["a shadows the binding from 1:20 at 1:31"],
+            ),
+            (
+                "let f = fn(a, b, _c) { let d = 1; a }; f(1, 2, 3)",
+                vec!["unused binding b at 1:15", "unused binding d at 1:28"],
+            ),
+            (
+                "for (x in [1]) { let [y, ...z] = x; y }",
+                vec!["unused binding z at 1:29"],
+            ),
+            // Unused globals are not reported.
+            ("let x = 1; let y = 2;", vec![]),
+            // An assignment counts as a use.
+            ("let f = fn() { let a = 1; a = 2 }; f()", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let (errors, warnings) = diagnostics(input);
+            assert!(errors.is_empty(), "errors: {errors:?}");
+            assert_eq!(warnings, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_after() {
+        let earlier = ["x".to_string(), "f".to_string()];
+        let tests = vec![
+            ("f(x)", vec![], vec![]),
+            (
+                "let x = x + 1;",
+                vec![],
+                vec!["x shadows an earlier binding at 1:5"],
+            ),
+            // A later input may define what a function body uses.
+            (
+                "let g = fn(f) { f(y) };",
+                vec![],
+                vec![
+                    "f shadows an earlier binding at 1:12",
+                    "undefined name y at 1:19",
+                ],
+            ),
+            ("f(y)", vec!["undefined name y at 1:3"], vec![]),
+        ];
+
+        for (input, errors, warnings) in tests {
+            let resolution = resolve_after(&Ast::from(&parse(input)), &earlier);
+            assert_eq!(resolution.errors, errors, "input: {input}");
+            assert_eq!(resolution.warnings, warnings, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_builtins_are_defined() {
+        for name in ["doc", "first", "last", "len", "push", "puts", "rest"] {
+            let (errors, _) = diagnostics(name);
+            assert!(errors.is_empty(), "{name}: {errors:?}");
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::arena::{Ast, BlockId, Expr, ExprId, SideTable, Stmt, StmtId};
+
+// The calls a function makes as the last thing it does, so that a backend
+// can reuse the function's frame for the callee instead of growing the
+// stack. Any callee qualifies, not only the function itself, which is what
+// keeps mutually recursive functions such as `isEven` and `isOdd` in
+// constant space.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TailCalls {
+    // The function literal whose call each tail call ends.
+    pub calls: SideTable<Expr, ExprId>,
+}
+
+impl TailCalls {
+    pub fn is_tail_call(&self, call: ExprId) -> bool {
+        self.calls.contains(call)
+    }
+}
+
+// Mark the calls in tail position in `ast`: the final expression of a
+// function body, the branches of an `if` or the arms of a `match` in tail
+// position, and the value of a `return`. The statements before the last one
+// in a block, loop bodies, conditions and operands are never in tail
+// position, and neither is anything at the top level of the program.
+//
+// A function with an annotated result checks the result after its body, so
+// it has no tail calls. Macro bodies are only code to expand and are
+// skipped.
+pub fn find_tail_calls(ast: &Ast) -> TailCalls {
+    let mut finder = Finder {
+        ast,
+        tail_calls: TailCalls::default(),
+    };
+    for &stmt in &ast.statements {
+        finder.visit_stmt(stmt, None, false);
+    }
+    finder.tail_calls
+}
+
+struct Finder<'a> {
+    ast: &'a Ast,
+    tail_calls: TailCalls,
+}
+
+// Each method takes the function whose frame a tail call may reuse, if any,
+// and whether the node's value is that function's result.
+impl Finder<'_> {
+    fn visit_block(&mut self, block: BlockId, function: Option<ExprId>, tail: bool) {
+        let statements = &self.ast.blocks[block].statements;
+        for (i, &stmt) in statements.iter().enumerate() {
+            self.visit_stmt(stmt, function, tail && i == statements.len() - 1);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: StmtId, function: Option<ExprId>, tail: bool) {
+        match &self.ast.stmts[stmt] {
+            Stmt::Let { value, .. } => self.visit(*value, function, false),
+            Stmt::Return(value) => self.visit(*value, function, true),
+            Stmt::Expression(value) => self.visit(*value, function, tail),
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn visit(&mut self, expr: ExprId, function: Option<ExprId>, tail: bool) {
+        let ast = self.ast;
+        match &ast.exprs[expr] {
+            Expr::Identifier(_) | Expr::Integer(_) | Expr::Boolean(_) | Expr::String(_) => {}
+            Expr::Prefix { right, .. } => self.visit(*right, function, false),
+            Expr::Infix { left, right, .. } => {
+                self.visit(*left, function, false);
+                self.visit(*right, function, false);
+            }
+            Expr::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.visit(*condition, function, false);
+                self.visit_block(*consequence, function, tail);
+                if let Some(alternative) = alternative {
+                    self.visit_block(*alternative, function, tail);
+                }
+            }
+            // Defaults are evaluated in the new call's frame, before the body.
+            Expr::Function {
+                parameters,
+                result,
+                body,
+                ..
+            } => {
+                let inner = match result {
+                    Some(_) => None,
+                    None => Some(expr),
+                };
+                for default in parameters.iter().filter_map(|p| p.default) {
+                    self.visit(default, inner, false);
+                }
+                self.visit_block(*body, inner, true);
+            }
+            Expr::Macro { .. } => {}
+            Expr::Call {
+                function: callee,
+                arguments,
+                named_arguments,
+            } => {
+                if let (true, Some(function)) = (tail, function) {
+                    self.tail_calls.calls.insert(expr, function);
+                }
+                self.visit(*callee, function, false);
+                for &arg in arguments {
+                    self.visit(arg, function, false);
+                }
+                for &(_, arg) in named_arguments {
+                    self.visit(arg, function, false);
+                }
+            }
+            Expr::Array(elements) => {
+                for &element in elements {
+                    self.visit(element, function, false);
+                }
+            }
+            Expr::Index { left, index } => {
+                self.visit(*left, function, false);
+                self.visit(*index, function, false);
+            }
+            Expr::Hash(pairs) => {
+                for &(key, value) in pairs {
+                    self.visit(key, function, false);
+                    self.visit(value, function, false);
+                }
+            }
+            // A loop evaluates to null, but a `return` in its body still
+            // ends the function.
+            Expr::While { condition, body } => {
+                self.visit(*condition, function, false);
+                self.visit_block(*body, function, false);
+            }
+            Expr::For { iterable, body, .. } => {
+                self.visit(*iterable, function, false);
+                self.visit_block(*body, function, false);
+            }
+            Expr::Assign { target, value, .. } => {
+                self.visit(*target, function, false);
+                self.visit(*value, function, false);
+            }
+            Expr::Match { subject, arms } => {
+                self.visit(*subject, function, false);
+                for arm in arms {
+                    if let Some(guard) = arm.guard {
+                        self.visit(guard, function, false);
+                    }
+                    self.visit(arm.body, function, tail);
+                }
+            }
+            Expr::Range { start, end, .. } => {
+                for &bound in [start, end].into_iter().flatten() {
+                    self.visit(bound, function, false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse;
+
+    // Every call to a name in `input`, as `name at L:C` followed by `tail`
+    // for the tail calls, in source order.
+    fn calls(input: &str) -> Vec<String> {
+        let ast = Ast::from(&parse(input));
+        let tail_calls = find_tail_calls(&ast);
+        let mut calls: Vec<_> = ast
+            .exprs
+            .iter()
+            .filter_map(|(id, expr)| match expr {
+                Expr::Call { function, .. } => match &ast.exprs[*function] {
+                    Expr::Identifier(name) => Some((id, name)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(|(id, name)| {
+                let span = ast.spans.exprs.get(id).copied().unwrap_or_default();
+                let tail = match tail_calls.is_tail_call(id) {
+                    true => " tail",
+                    false => "",
+                };
+                (span.start, format!("{name} at {span}{tail}"))
+            })
+            .collect();
+        calls.sort_by_key(|(start, _)| *start);
+        calls.into_iter().map(|(_, call)| call).collect()
+    }
+
+    #[test]
+    fn test_tail_positions() {
+        let tests = vec![
+            ("let f = fn() { g() };", vec!["g at 1:17 tail"]),
+            (
+                "let f = fn() { g(); h() };",
+                vec!["g at 1:17", "h at 1:22 tail"],
+            ),
+            ("let f = fn() { let x = g(); };", vec!["g at 1:25"]),
+            (
+                "let f = fn(n) { if (p(n)) { g(n) } else { h(n) } };",
+                vec!["p at 1:22", "g at 1:30 tail", "h at 1:44 tail"],
+            ),
+            (
+                "let f = fn(n) { if (n) { return g(n); } 1 + h(n) };",
+                vec!["g at 1:34 tail", "h at 1:46"],
+            ),
+            (
+                "let f = fn(n) { match n { 0 if p(n) => g(n), _ => h(h(n)) } };",
+                vec!["p at 1:33", "g at 1:41 tail", "h at 1:52 tail", "h at 1:54"],
+            ),
+            (
+                "let f = fn(xs) { for (x in xs) { if (x) { return g(x); } h(x) } };",
+                vec!["g at 1:51 tail", "h at 1:59"],
+            ),
+            // Only calls a function ends with are tail calls.
+            (
+                "g(); let x = fn() { 1 }; h()",
+                vec!["g at 1:2", "h at 1:27"],
+            ),
+            (
+                "let f = fn(x = g()) { fn() { h() } };",
+                vec!["g at 1:17", "h at 1:31 tail"],
+            ),
+            // The result of an annotated function is checked after the call.
+            ("let f = fn() -> int { g() };", vec!["g at 1:24"]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(calls(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let input = "
+let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };
+let loop = fn(n, acc) { if (n < 2) { return acc; } loop(n - 1, acc * n) };
+isEven(10)";
+        assert_eq!(
+            calls(input),
+            vec![
+                "isOdd at 2:55 tail",
+                "isEven at 3:56 tail",
+                "fact at 4:52",
+                "loop at 5:56 tail",
+                "isEven at 6:7",
+            ]
+        );
+
+        // Each tail call reuses the frame of the function it is in.
+        let ast = Ast::from(&parse(input));
+        let tail_calls = find_tail_calls(&ast);
+        let functions: Vec<ExprId> = ast
+            .exprs
+            .iter()
+            .filter(|(_, expr)| matches!(expr, Expr::Function { .. }))
+            .map(|(id, _)| id)
+            .collect();
+        let callers: Vec<ExprId> = ast
+            .exprs
+            .iter()
+            .filter_map(|(id, _)| tail_calls.calls.get(id).copied())
+            .collect();
+        assert_eq!(callers, vec![functions[0], functions[1], functions[3]]);
+    }
+}
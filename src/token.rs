@@ -1,5 +1,6 @@
 #![allow(unused)]
 use std::collections::HashMap;
+use std::fmt;
 
 use lazy_static::lazy_static;
 
@@ -13,6 +14,14 @@ lazy_static! {
         map.insert("if", TokenType::If);
         map.insert("else", TokenType::Else);
         map.insert("return", TokenType::Return);
+        map.insert("while", TokenType::While);
+        map.insert("for", TokenType::For);
+        map.insert("in", TokenType::In);
+        map.insert("break", TokenType::Break);
+        map.insert("continue", TokenType::Continue);
+        map.insert("match", TokenType::Match);
+        map.insert("_", TokenType::Underscore);
+        map.insert("macro", TokenType::Macro);
 
         map
     };
@@ -25,12 +34,13 @@ pub fn lookup_ident(ident: &str) -> TokenType {
     TokenType::Ident
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum TokenType {
     Illegal,
     Eof,
     Ident,
     Int,
+    String,
     Assign,
     // Operators
     Plus,
@@ -39,11 +49,20 @@ pub enum TokenType {
     Asterisk,
     Comma,
     Semicolon,
+    Colon,
+    Ellipsis,
+    DotDot,
+    DotDotEq,
+    FatArrow,
+    Arrow,
+    Pipe,
 
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
     Lt,
     Gt,
     Slash,
@@ -55,10 +74,23 @@ pub enum TokenType {
     If,
     Else,
     Return,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Match,
+    Underscore,
+    Macro,
 
     Eq,
     NotEq,
 
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+
     Default,
 }
 
@@ -69,15 +101,25 @@ impl TokenType {
             TokenType::Eof => "EOF",
             TokenType::Ident => "IDENT",
             TokenType::Int => "INT",
+            TokenType::String => "STRING",
             TokenType::Assign => "=",
             TokenType::Plus => "+",
             TokenType::Minus => "-",
             TokenType::Comma => ",",
             TokenType::Semicolon => ";",
+            TokenType::Colon => ":",
+            TokenType::Ellipsis => "...",
+            TokenType::DotDot => "..",
+            TokenType::DotDotEq => "..=",
+            TokenType::FatArrow => "=>",
+            TokenType::Arrow => "->",
+            TokenType::Pipe => "|>",
             TokenType::Lparen => "(",
             TokenType::Rparen => ")",
             TokenType::Lbrace => "{",
             TokenType::Rbrace => "}",
+            TokenType::Lbracket => "[",
+            TokenType::Rbracket => "]",
             TokenType::Lt => "<",
             TokenType::Gt => ">",
             TokenType::Bang => "!",
@@ -90,15 +132,67 @@ impl TokenType {
             TokenType::If => "IF",
             TokenType::Else => "ELSE",
             TokenType::Return => "RETURN",
+            TokenType::While => "WHILE",
+            TokenType::For => "FOR",
+            TokenType::In => "IN",
+            TokenType::Break => "BREAK",
+            TokenType::Continue => "CONTINUE",
+            TokenType::Match => "MATCH",
+            TokenType::Underscore => "_",
+            TokenType::Macro => "macro",
             TokenType::Eq => "==",
             TokenType::NotEq => "!=",
+            TokenType::PlusAssign => "+=",
+            TokenType::MinusAssign => "-=",
+            TokenType::AsteriskAssign => "*=",
+            TokenType::SlashAssign => "/=",
             TokenType::Default => "default",
         }
     }
 }
 
+// Where a token was found in the source. `start` and `end` are character
+// offsets with `end` exclusive; `line` and `column` are 1-based and point
+// at `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub t_type: TokenType,
     pub literal: String,
+    pub span: Span,
+    // Set when a line break separates the token from the one before it,
+    // which lets the parser end statements without a semicolon.
+    pub newline_before: bool,
+}
+
+impl Token {
+    pub fn new(t_type: TokenType, literal: &str) -> Token {
+        Token {
+            t_type,
+            literal: literal.to_string(),
+            span: Span::default(),
+            newline_before: false,
+        }
+    }
+}
+
+// Tokens compare by kind and text only, so trees parsed from differently
+// formatted sources are still equal.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.t_type == other.t_type && self.literal == other.literal
+    }
 }
@@ -0,0 +1,1512 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::arena::{
+    Ast, BlockId, Expr, ExprId, Ident, IdentId, Parameter, Pattern, PatternId, SideTable, Stmt,
+    StmtId,
+};
+use crate::ast::TypeAnnotation;
+use crate::resolver::Resolution;
+use crate::token::Span;
+
+// The static type of a value. Arrays and hashes are homogeneous: every
+// element of an array, and every key and every value of a hash, has the same
+// type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Integer,
+    Boolean,
+    String,
+    Null,
+    Range,
+    Array(Box<Type>),
+    // The type of the keys, then of the values.
+    Hash(Box<Type>, Box<Type>),
+    Function(Box<FunctionType>),
+    Var(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub params: Vec<ParamType>,
+    // The type of each argument the rest parameter collects.
+    pub rest: Option<Type>,
+    pub result: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamType {
+    // `None` for a function only known from the way it is called.
+    pub name: Option<String>,
+    pub ty: Type,
+    // Whether the parameter has a default, so that it may be left out.
+    pub optional: bool,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Integer => write!(f, "int"),
+            Type::Boolean => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Null => write!(f, "null"),
+            Type::Range => write!(f, "range"),
+            Type::Array(element) => write!(f, "[{element}]"),
+            Type::Hash(key, value) => write!(f, "{{{key}: {value}}}"),
+            Type::Function(func) => {
+                let mut params: Vec<String> = func
+                    .params
+                    .iter()
+                    .map(|p| match p.optional {
+                        true => format!("{}?", p.ty),
+                        false => p.ty.to_string(),
+                    })
+                    .collect();
+                if let Some(rest) = &func.rest {
+                    params.push(format!("...{rest}"));
+                }
+                write!(f, "fn({}) -> {}", params.join(", "), func.result)
+            }
+            Type::Var(var) => write!(f, "{}", var_name(*var)),
+        }
+    }
+}
+
+// `a` to `z`, then `a1` to `z1` and so on.
+fn var_name(var: u32) -> String {
+    let letter = (b'a' + (var % 26) as u8) as char;
+    match var / 26 {
+        0 => letter.to_string(),
+        n => format!("{letter}{n}"),
+    }
+}
+
+// The type of a binding, polymorphic in `vars`: each use of the binding may
+// pick its own types for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<u32>,
+    // The variables in `ty` that can only be `int` or `string`, because
+    // values of their type are added with `+`.
+    pub addable: Vec<u32>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Scheme {
+        Scheme {
+            vars: Vec::new(),
+            addable: Vec::new(),
+            ty,
+        }
+    }
+}
+
+// Variables are renamed `a`, `b`, ... in the order they appear.
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = Vec::new();
+        let ty = rename(&self.ty, &mut names);
+        write!(f, "{ty}")?;
+
+        let addable: Vec<String> = names
+            .iter()
+            .enumerate()
+            .filter(|(_, var)| self.addable.contains(var))
+            .map(|(i, _)| var_name(i as u32))
+            .collect();
+        if !addable.is_empty() {
+            write!(f, " where {}: int | string", addable.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Replace each variable of `ty` with its index in `names`, adding the ones
+// not seen yet.
+fn rename(ty: &Type, names: &mut Vec<u32>) -> Type {
+    map_vars(ty, &mut |var| {
+        let index = match names.iter().position(|&v| v == var) {
+            Some(index) => index,
+            None => {
+                names.push(var);
+                names.len() - 1
+            }
+        };
+        Type::Var(index as u32)
+    })
+}
+
+fn map_vars(ty: &Type, f: &mut impl FnMut(u32) -> Type) -> Type {
+    match ty {
+        Type::Integer | Type::Boolean | Type::String | Type::Null | Type::Range => ty.clone(),
+        Type::Array(element) => Type::Array(Box::new(map_vars(element, f))),
+        Type::Hash(key, value) => {
+            Type::Hash(Box::new(map_vars(key, f)), Box::new(map_vars(value, f)))
+        }
+        Type::Function(func) => Type::Function(Box::new(FunctionType {
+            params: func
+                .params
+                .iter()
+                .map(|p| ParamType {
+                    ty: map_vars(&p.ty, f),
+                    ..p.clone()
+                })
+                .collect(),
+            rest: func.rest.as_ref().map(|rest| map_vars(rest, f)),
+            result: map_vars(&func.result, f),
+        })),
+        Type::Var(var) => f(*var),
+    }
+}
+
+// The variables of `ty` in the order they appear, each once.
+fn free_vars(ty: &Type) -> Vec<u32> {
+    let mut vars = Vec::new();
+    map_vars(ty, &mut |var| {
+        if !vars.contains(&var) {
+            vars.push(var);
+        }
+        Type::Var(var)
+    });
+    vars
+}
+
+fn function_type(params: Vec<Type>, result: Type) -> Type {
+    Type::Function(Box::new(FunctionType {
+        params: params
+            .into_iter()
+            .map(|ty| ParamType {
+                name: None,
+                ty,
+                optional: false,
+            })
+            .collect(),
+        rest: None,
+        result,
+    }))
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Typing {
+    // The type of every binding.
+    pub bindings: SideTable<Ident, Scheme>,
+    // The names bound by top-level `let` statements with their types, in
+    // order.
+    pub lets: Vec<(String, Scheme)>,
+    // The `let` and `fn` tokens of the annotations on values and results
+    // whose type was known before the annotation was, so that their runtime
+    // checks cannot fail. Parameters are always checked at runtime.
+    pub proven: HashSet<Span>,
+    pub errors: Vec<String>,
+    // The errors about annotations that running the code is certain to fail
+    // on: values that cannot fit their annotation and names that are not
+    // types. The other errors may be about code that works, only untyped.
+    pub annotation_errors: Vec<String>,
+}
+
+// Infer the type of every binding in `ast` with Hindley-Milner inference,
+// reporting the expressions whose types do not fit together. Names are
+// looked up through `resolution`; the names it could not resolve have an
+// unknown type.
+//
+// As in ML, a `let` is polymorphic only when its value is a function
+// literal, a literal or a name, and the binding is never assigned to, since
+// a mutable binding or a collection created once cannot safely take a
+// different type at each use. Functions that refer to each other before
+// both are declared are monomorphic until they are.
+pub fn check(ast: &Ast, resolution: &Resolution) -> Typing {
+    let mut checker = Checker::new(ast, resolution, false);
+    checker.infer_statements(&ast.statements);
+
+    let mut typing = Typing::default();
+    for (ident, _) in ast.idents.iter() {
+        if let Some(scheme) = checker.bindings.get(ident) {
+            typing.bindings.insert(ident, checker.finish(scheme));
+        }
+    }
+    for &stmt in &ast.statements {
+        if let Stmt::Let { pattern, .. } = &ast.stmts[stmt] {
+            for ident in ast.pattern_bindings(*pattern) {
+                let scheme = typing.bindings.get(ident).expect("binding was not typed");
+                typing
+                    .lets
+                    .push((ast.idents[ident].name.clone(), scheme.clone()));
+            }
+        }
+    }
+    // Code expanded from a macro shares its spans with every other
+    // expansion, and each of those has to be proven.
+    typing.proven = &checker.proven - &checker.unproven;
+    checker.errors.sort_by_key(|(span, _)| span.start);
+    typing.errors = checker.errors.into_iter().map(|(_, e)| e).collect();
+    typing.annotation_errors = certain_annotation_errors(ast, resolution);
+    typing
+}
+
+// The annotation errors running `ast` is certain to fail on. Inference is
+// more precise than a run in two ways: a one-armed `if` is null even when
+// its block runs, and a value used at two types is given one of them. So
+// the errors come from a second pass where a one-armed `if` can have any
+// type, and only count when that pass finds no other error.
+fn certain_annotation_errors(ast: &Ast, resolution: &Resolution) -> Vec<String> {
+    let mut checker = Checker::new(ast, resolution, true);
+    checker.infer_statements(&ast.statements);
+    if checker.errors.len() != checker.annotation_errors.len() {
+        return Vec::new();
+    }
+    checker
+        .annotation_errors
+        .sort_by_key(|(span, _)| span.start);
+    checker
+        .annotation_errors
+        .into_iter()
+        .map(|(_, e)| e)
+        .collect()
+}
+
+// Why two types could not be unified.
+enum Clash {
+    Mismatch,
+    // The variable would have to contain itself.
+    Infinite(u32, Type),
+}
+
+struct Checker<'a> {
+    ast: &'a Ast,
+    resolution: &'a Resolution,
+    // What each type variable stands for, once known. The substitution is
+    // kept in this table rather than composed and applied to the
+    // environment at each step.
+    subst: Vec<Option<Type>>,
+    addable: HashSet<u32>,
+    bindings: SideTable<Ident, Scheme>,
+    // The typed bindings in scope, innermost last.
+    env: Vec<IdentId>,
+    // Bindings used before their `let` was checked.
+    pending: Vec<IdentId>,
+    assigned: HashSet<IdentId>,
+    // The result types of the functions being checked, innermost last.
+    results: Vec<Type>,
+    proven: HashSet<Span>,
+    unproven: HashSet<Span>,
+    errors: Vec<(Span, String)>,
+    annotation_errors: Vec<(Span, String)>,
+    // Whether a one-armed `if` has a type of its own rather than null.
+    lenient: bool,
+}
+
+impl<'a> Checker<'a> {
+    fn new(ast: &'a Ast, resolution: &'a Resolution, lenient: bool) -> Checker<'a> {
+        let mut assigned = HashSet::new();
+        for (_, expr) in ast.exprs.iter() {
+            if let Expr::Assign { target, .. } = expr {
+                if let Some(resolved) = resolution.uses.get(*target) {
+                    assigned.insert(resolved.binding);
+                }
+            }
+        }
+
+        Checker {
+            ast,
+            resolution,
+            subst: Vec::new(),
+            addable: HashSet::new(),
+            bindings: SideTable::new(),
+            env: Vec::new(),
+            pending: Vec::new(),
+            assigned,
+            results: Vec::new(),
+            proven: HashSet::new(),
+            unproven: HashSet::new(),
+            errors: Vec::new(),
+            annotation_errors: Vec::new(),
+            lenient,
+        }
+    }
+
+    fn expr_span(&self, expr: ExprId) -> Span {
+        self.ast.spans.exprs.get(expr).copied().unwrap_or_default()
+    }
+
+    fn fresh_var(&mut self) -> u32 {
+        self.subst.push(None);
+        self.subst.len() as u32 - 1
+    }
+
+    fn fresh(&mut self) -> Type {
+        Type::Var(self.fresh_var())
+    }
+
+    // `ty`, or what it stands for when it is a known variable.
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.subst[var as usize] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    // `ty` with every known variable replaced by what it stands for.
+    fn apply(&self, ty: &Type) -> Type {
+        map_vars(ty, &mut |var| match &self.subst[var as usize] {
+            Some(bound) => self.apply(bound),
+            None => Type::Var(var),
+        })
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), Clash> {
+        let (a, b) = (self.shallow(a), self.shallow(b));
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(*var, ty),
+            (Type::Array(x), Type::Array(y)) => self.unify(x, y),
+            (Type::Hash(k1, v1), Type::Hash(k2, v2)) => {
+                self.unify(k1, k2)?;
+                self.unify(v1, v2)
+            }
+            (Type::Function(f), Type::Function(g)) => {
+                if f.params.len() != g.params.len() || f.rest.is_some() != g.rest.is_some() {
+                    return Err(Clash::Mismatch);
+                }
+                for (p, q) in f.params.iter().zip(&g.params) {
+                    self.unify(&p.ty, &q.ty)?;
+                }
+                if let (Some(r), Some(s)) = (&f.rest, &g.rest) {
+                    self.unify(r, s)?;
+                }
+                self.unify(&f.result, &g.result)
+            }
+            _ if a == b => Ok(()),
+            _ => Err(Clash::Mismatch),
+        }
+    }
+
+    fn bind(&mut self, var: u32, ty: &Type) -> Result<(), Clash> {
+        if free_vars(&self.apply(ty)).contains(&var) {
+            return Err(Clash::Infinite(var, ty.clone()));
+        }
+        if self.addable.contains(&var) {
+            match ty {
+                Type::Var(other) => {
+                    self.addable.insert(*other);
+                }
+                Type::Integer | Type::String => {}
+                _ => return Err(Clash::Mismatch),
+            }
+        }
+        self.subst[var as usize] = Some(ty.clone());
+        Ok(())
+    }
+
+    // Unify `expected` with `actual`, reporting `message(expected, actual)`
+    // at `span` when they differ.
+    fn expect(
+        &mut self,
+        span: Span,
+        expected: &Type,
+        actual: &Type,
+        message: impl FnOnce(&str, &str) -> String,
+    ) -> bool {
+        let message = match self.unify(expected, actual) {
+            Ok(()) => return true,
+            Err(Clash::Mismatch) => {
+                let shown = self.show(&[expected, actual]);
+                message(&shown[0], &shown[1])
+            }
+            Err(Clash::Infinite(var, ty)) => {
+                let shown = self.show(&[&Type::Var(var), &ty]);
+                format!("infinite type {} = {}", shown[0], shown[1])
+            }
+        };
+        self.error(span, message);
+        false
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push((span, format!("{message} at {span}")));
+    }
+
+    // Like `expect`, for a value and its annotation.
+    fn expect_annotated(
+        &mut self,
+        span: Span,
+        expected: &Type,
+        actual: &Type,
+        message: impl FnOnce(&str, &str) -> String,
+    ) -> bool {
+        let fits = self.expect(span, expected, actual, message);
+        if !fits {
+            let error = self.errors.last().cloned().expect("a mismatch is an error");
+            self.annotation_errors.push(error);
+        }
+        fits
+    }
+
+    // The type an annotation stands for. The parameters of a function type
+    // are positional and required.
+    fn annotation_type(&mut self, annotation: &TypeAnnotation) -> Type {
+        match annotation {
+            TypeAnnotation::Named(name) => match name.value.as_str() {
+                "int" => Type::Integer,
+                "bool" => Type::Boolean,
+                "string" => Type::String,
+                "null" => Type::Null,
+                "range" => Type::Range,
+                unknown => {
+                    let span = name.token.span;
+                    self.error(span, format!("unknown type {unknown}"));
+                    let error = self.errors.last().cloned().expect("just reported");
+                    self.annotation_errors.push(error);
+                    self.fresh()
+                }
+            },
+            TypeAnnotation::Array(element) => Type::Array(Box::new(self.annotation_type(element))),
+            TypeAnnotation::Hash(key, value) => Type::Hash(
+                Box::new(self.annotation_type(key)),
+                Box::new(self.annotation_type(value)),
+            ),
+            TypeAnnotation::Function(params, result) => {
+                let params = params.iter().map(|p| self.annotation_type(p)).collect();
+                function_type(params, self.annotation_type(result))
+            }
+        }
+    }
+
+    // `types` as they are known so far, with their variables named alike.
+    fn show(&self, types: &[&Type]) -> Vec<String> {
+        let mut names = Vec::new();
+        types
+            .iter()
+            .map(|ty| rename(&self.apply(ty), &mut names).to_string())
+            .collect()
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let ty = self.apply(&scheme.ty);
+        let mut fresh = Vec::new();
+        for &var in &scheme.vars {
+            let new = self.fresh_var();
+            if self.addable.contains(&var) {
+                self.addable.insert(new);
+            }
+            fresh.push((var, new));
+        }
+        map_vars(
+            &ty,
+            &mut |var| match fresh.iter().find(|(old, _)| *old == var) {
+                Some((_, new)) => Type::Var(*new),
+                None => Type::Var(var),
+            },
+        )
+    }
+
+    // Quantify over the variables of `ty` that no binding in scope mentions.
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.apply(ty);
+        let mut in_scope = HashSet::new();
+        for &binding in self.env.iter().chain(&self.pending) {
+            if let Some(scheme) = self.bindings.get(binding) {
+                let vars = free_vars(&self.apply(&scheme.ty));
+                in_scope.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+            }
+        }
+        let mut vars = free_vars(&ty);
+        vars.retain(|var| !in_scope.contains(var));
+        Scheme {
+            vars,
+            addable: Vec::new(),
+            ty,
+        }
+    }
+
+    fn finish(&self, scheme: &Scheme) -> Scheme {
+        let ty = self.apply(&scheme.ty);
+        let mut addable = free_vars(&ty);
+        addable.retain(|var| self.addable.contains(var));
+        Scheme {
+            vars: scheme.vars.clone(),
+            addable,
+            ty,
+        }
+    }
+
+    // Bring `ident` into scope with type `ty`.
+    fn declare(&mut self, ident: IdentId, ty: Type, polymorphic: bool) {
+        if let Some(i) = self.pending.iter().position(|&b| b == ident) {
+            self.pending.remove(i);
+            let used = self
+                .bindings
+                .get(ident)
+                .expect("pending binding")
+                .ty
+                .clone();
+            let span = self
+                .ast
+                .spans
+                .idents
+                .get(ident)
+                .copied()
+                .unwrap_or_default();
+            let name = &self.ast.idents[ident].name;
+            self.expect(span, &used, &ty, |used, ty| {
+                format!("{name} is used as {used} but is {ty}")
+            });
+        }
+        let scheme = if polymorphic && !self.assigned.contains(&ident) {
+            self.generalize(&ty)
+        } else {
+            Scheme::mono(ty)
+        };
+        self.bindings.insert(ident, scheme);
+        self.env.push(ident);
+    }
+
+    fn lookup(&mut self, binding: IdentId) -> Type {
+        match self.bindings.get(binding).cloned() {
+            Some(scheme) => self.instantiate(&scheme),
+            // Used before its `let`: the `let` unifies its value with this.
+            None => {
+                let ty = self.fresh();
+                self.bindings.insert(binding, Scheme::mono(ty.clone()));
+                self.pending.push(binding);
+                ty
+            }
+        }
+    }
+
+    // Check `actual` against `annotation`, reporting a mismatch as the
+    // runtime check would, and give the annotated type.
+    fn annotate(
+        &mut self,
+        span: Span,
+        annotation: &TypeAnnotation,
+        actual: &Type,
+        what: &str,
+    ) -> Type {
+        let known = self.checked_known(annotation, actual);
+        let expected = self.annotation_type(annotation);
+        let fits = self.expect_annotated(span, &expected, actual, |expected, actual| {
+            format!("expected {expected} for {what}, got {actual}")
+        });
+        if known && fits {
+            self.proven.insert(span);
+        } else {
+            self.unproven.insert(span);
+        }
+        expected
+    }
+
+    // Whether the parts of `ty` the runtime check of `annotation` looks at
+    // are known: the elements of arrays and hashes, but only the outside of
+    // a function.
+    fn checked_known(&self, annotation: &TypeAnnotation, ty: &Type) -> bool {
+        match (annotation, self.shallow(ty)) {
+            (_, Type::Var(_)) => false,
+            (TypeAnnotation::Array(element), Type::Array(ty)) => self.checked_known(element, &ty),
+            (TypeAnnotation::Hash(key, value), Type::Hash(k, v)) => {
+                self.checked_known(key, &k) && self.checked_known(value, &v)
+            }
+            _ => true,
+        }
+    }
+
+    fn builtin(&mut self, name: &str) -> Type {
+        let a = self.fresh();
+        let array = Type::Array(Box::new(a.clone()));
+        match name {
+            "len" => function_type(vec![a], Type::Integer),
+            "first" | "last" => function_type(vec![array], a),
+            "rest" => function_type(vec![array.clone()], array),
+            "push" => function_type(vec![array.clone(), a], array),
+            "doc" => function_type(vec![a], Type::String),
+            "puts" => Type::Function(Box::new(FunctionType {
+                params: Vec::new(),
+                rest: Some(a),
+                result: Type::Null,
+            })),
+            // Names bound by earlier REPL input and the names the resolver
+            // reported.
+            _ => a,
+        }
+    }
+
+    // A block has the type of its final expression. It is null when it is
+    // empty or ends in a `let`, and can have any type when it ends by
+    // jumping elsewhere.
+    fn infer_block(&mut self, block: BlockId) -> Type {
+        let ast = self.ast;
+        self.infer_statements(&ast.blocks[block].statements)
+    }
+
+    fn infer_statements(&mut self, statements: &[StmtId]) -> Type {
+        let mut ty = Type::Null;
+        for &stmt in statements {
+            ty = self.infer_stmt(stmt);
+        }
+        ty
+    }
+
+    fn infer_stmt(&mut self, stmt: StmtId) -> Type {
+        let ast = self.ast;
+        match &ast.stmts[stmt] {
+            Stmt::Let {
+                pattern,
+                annotation,
+                value,
+            } => {
+                let mut ty = self.infer(*value);
+                if let Some(annotation) = annotation {
+                    let span = ast.spans.stmts.get(stmt).copied().unwrap_or_default();
+                    let what = match &ast.patterns[*pattern] {
+                        Pattern::Identifier(ident) => ast.idents[*ident].name.as_str(),
+                        _ => "the value",
+                    };
+                    ty = self.annotate(span, annotation, &ty, what);
+                }
+                match &ast.patterns[*pattern] {
+                    Pattern::Identifier(ident) => {
+                        let polymorphic = matches!(
+                            ast.exprs[*value],
+                            Expr::Function { .. }
+                                | Expr::Identifier(_)
+                                | Expr::Integer(_)
+                                | Expr::Boolean(_)
+                                | Expr::String(_)
+                        );
+                        self.declare(*ident, ty, polymorphic);
+                    }
+                    _ => self.bind_pattern(*pattern, &ty),
+                }
+                Type::Null
+            }
+            Stmt::Return(value) => {
+                let ty = self.infer(*value);
+                if let Some(result) = self.results.last().cloned() {
+                    let span = self.expr_span(*value);
+                    self.expect(span, &result, &ty, |result, ty| {
+                        format!("expected to return {result}, got {ty}")
+                    });
+                }
+                self.fresh()
+            }
+            Stmt::Expression(value) => self.infer(*value),
+            Stmt::Break | Stmt::Continue => self.fresh(),
+        }
+    }
+
+    // Declare the names `pattern` binds for a value of type `ty`.
+    fn bind_pattern(&mut self, pattern: PatternId, ty: &Type) {
+        let ast = self.ast;
+        let span = ast.spans.patterns.get(pattern).copied().unwrap_or_default();
+        match &ast.patterns[pattern] {
+            Pattern::Identifier(ident) => self.declare(*ident, ty.clone(), false),
+            Pattern::Wildcard => {}
+            Pattern::Literal(literal) => {
+                let literal = self.infer(*literal);
+                self.expect(span, ty, &literal, |ty, literal| {
+                    format!("a {literal} pattern cannot match {ty}")
+                });
+            }
+            Pattern::Array { elements, rest } => {
+                let element = self.fresh();
+                let array = Type::Array(Box::new(element.clone()));
+                self.expect(span, ty, &array, |ty, _| {
+                    format!("an array pattern cannot match {ty}")
+                });
+                for &p in elements {
+                    self.bind_pattern(p, &element);
+                }
+                if let Some(rest) = rest {
+                    self.declare(*rest, array, false);
+                }
+            }
+            Pattern::Hash(fields) => {
+                let value = self.fresh();
+                let hash = Type::Hash(Box::new(Type::String), Box::new(value.clone()));
+                self.expect(span, ty, &hash, |ty, _| {
+                    format!("a hash pattern cannot match {ty}")
+                });
+                for (_, p) in fields {
+                    self.bind_pattern(*p, &value);
+                }
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: ExprId) -> Type {
+        let ast = self.ast;
+        let span = self.expr_span(expr);
+        match &ast.exprs[expr] {
+            Expr::Identifier(name) => match self.resolution.uses.get(expr) {
+                Some(resolved) => self.lookup(resolved.binding),
+                None => self.builtin(name),
+            },
+            Expr::Integer(_) => Type::Integer,
+            Expr::Boolean(_) => Type::Boolean,
+            Expr::String(_) => Type::String,
+            Expr::Prefix { operator, right } => {
+                let right = self.infer(*right);
+                if operator == "!" {
+                    return Type::Boolean;
+                }
+                self.expect(span, &Type::Integer, &right, |_, right| {
+                    format!("unknown operator: {operator}{right}")
+                });
+                Type::Integer
+            }
+            Expr::Infix {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.infer(*left);
+                let right = self.infer(*right);
+                self.infer_operator(span, operator, &left, &right)
+            }
+            Expr::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.infer(*condition);
+                let consequence = self.infer_block(*consequence);
+                let Some(alternative) = alternative else {
+                    return match self.lenient {
+                        true => self.fresh(),
+                        false => Type::Null,
+                    };
+                };
+                let alternative = self.infer_block(*alternative);
+                self.expect(span, &consequence, &alternative, |a, b| {
+                    format!("if branches differ: {a} and {b}")
+                });
+                consequence
+            }
+            Expr::Function {
+                parameters,
+                rest,
+                result,
+                body,
+            } => self.infer_function(span, parameters, *rest, result.as_ref(), *body),
+            Expr::Macro { .. } => self.fresh(),
+            Expr::Call {
+                function,
+                arguments,
+                named_arguments,
+            } => {
+                // Quoted code is not evaluated, and `puts` takes anything.
+                if let Expr::Identifier(name) = &ast.exprs[*function] {
+                    if !self.resolution.uses.contains(*function) {
+                        match name.as_str() {
+                            "quote" => return self.fresh(),
+                            "puts" => {
+                                for &arg in arguments {
+                                    self.infer(arg);
+                                }
+                                return Type::Null;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                self.infer_call(span, *function, arguments, named_arguments)
+            }
+            Expr::Array(elements) => {
+                let element = self.fresh();
+                for &e in elements {
+                    let ty = self.infer(e);
+                    let span = self.expr_span(e);
+                    self.expect(span, &element, &ty, |a, b| {
+                        format!("array elements differ: {a} and {b}")
+                    });
+                }
+                Type::Array(Box::new(element))
+            }
+            Expr::Index { left, index } => {
+                let left = self.infer(*left);
+                let index = self.infer(*index);
+                self.infer_index(span, &left, &index)
+            }
+            Expr::Hash(pairs) => {
+                let (key, value) = (self.fresh(), self.fresh());
+                for &(k, v) in pairs {
+                    let (k_ty, k_span) = (self.infer(k), self.expr_span(k));
+                    self.expect(k_span, &key, &k_ty, |a, b| {
+                        format!("hash keys differ: {a} and {b}")
+                    });
+                    let (v_ty, v_span) = (self.infer(v), self.expr_span(v));
+                    self.expect(v_span, &value, &v_ty, |a, b| {
+                        format!("hash values differ: {a} and {b}")
+                    });
+                }
+                let shown = self.show(&[&key]);
+                match self.shallow(&key) {
+                    Type::Array(_)
+                    | Type::Hash(..)
+                    | Type::Function(_)
+                    | Type::Range
+                    | Type::Null => self.error(span, format!("unusable as hash key: {}", shown[0])),
+                    _ => {}
+                }
+                Type::Hash(Box::new(key), Box::new(value))
+            }
+            Expr::While { condition, body } => {
+                self.infer(*condition);
+                self.infer_block(*body);
+                Type::Null
+            }
+            Expr::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                let iterable = self.infer(*iterable);
+                let element = match self.shallow(&iterable) {
+                    Type::Array(element) => *element,
+                    Type::String => Type::String,
+                    Type::Hash(key, _) => *key,
+                    Type::Range => Type::Integer,
+                    Type::Var(_) => {
+                        let element = self.fresh();
+                        let array = Type::Array(Box::new(element.clone()));
+                        self.expect(span, &array, &iterable, |_, iterable| {
+                            format!("cannot iterate over {iterable}")
+                        });
+                        element
+                    }
+                    _ => {
+                        let shown = self.show(&[&iterable]);
+                        self.error(span, format!("cannot iterate over {}", shown[0]));
+                        self.fresh()
+                    }
+                };
+                let mark = self.env.len();
+                self.declare(*variable, element, false);
+                self.infer_block(*body);
+                self.env.truncate(mark);
+                Type::Null
+            }
+            Expr::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let value = self.infer(*value);
+                let target = self.infer(*target);
+                let value = match operator.trim_end_matches('=') {
+                    "" => value,
+                    op => self.infer_operator(span, op, &target, &value),
+                };
+                self.expect(span, &target, &value, |target, value| {
+                    format!("cannot assign {value} to {target}")
+                });
+                target
+            }
+            Expr::Match { subject, arms } => {
+                let subject = self.infer(*subject);
+                let result = self.fresh();
+                for arm in arms {
+                    let mark = self.env.len();
+                    self.bind_pattern(arm.pattern, &subject);
+                    if let Some(guard) = arm.guard {
+                        self.infer(guard);
+                    }
+                    let body = self.infer(arm.body);
+                    let span = self.expr_span(arm.body);
+                    self.expect(span, &result, &body, |a, b| {
+                        format!("match arms differ: {a} and {b}")
+                    });
+                    self.env.truncate(mark);
+                }
+                result
+            }
+            Expr::Range { start, end, .. } => {
+                for &bound in [start, end].into_iter().flatten() {
+                    let ty = self.infer(bound);
+                    let span = self.expr_span(bound);
+                    self.expect(span, &Type::Integer, &ty, |_, ty| {
+                        format!("range bounds must be integers, got {ty}")
+                    });
+                }
+                Type::Range
+            }
+        }
+    }
+
+    // `+` adds integers or joins strings. `==` and `!=` compare values of
+    // the same type; the other operators take integers.
+    fn infer_operator(&mut self, span: Span, operator: &str, left: &Type, right: &Type) -> Type {
+        let result = match operator {
+            "+" => left.clone(),
+            "<" | ">" | "==" | "!=" => Type::Boolean,
+            _ => Type::Integer,
+        };
+        let mismatch = |l: &str, r: &str| format!("type mismatch: {l} {operator} {r}");
+        if !self.expect(span, left, right, mismatch) {
+            return result;
+        }
+
+        let unknown = |l: &str, _: &str| format!("unknown operator: {l} {operator} {l}");
+        match operator {
+            "==" | "!=" => {}
+            "+" => match self.shallow(left) {
+                Type::Var(var) => {
+                    self.addable.insert(var);
+                }
+                Type::Integer | Type::String => {}
+                other => {
+                    let shown = self.show(&[&other]);
+                    self.error(span, unknown(&shown[0], ""));
+                }
+            },
+            _ => {
+                self.expect(span, left, &Type::Integer, unknown);
+            }
+        }
+        result
+    }
+
+    fn infer_index(&mut self, span: Span, left: &Type, index: &Type) -> Type {
+        let not_supported = |left: &str, _: &str| format!("index operator not supported: {left}");
+        let slice = matches!(self.shallow(index), Type::Range);
+        let collection = match self.shallow(left) {
+            // Strings and booleans index hashes, anything else arrays.
+            Type::Var(_) => {
+                let collection = match self.shallow(index) {
+                    Type::String | Type::Boolean => {
+                        Type::Hash(Box::new(index.clone()), Box::new(self.fresh()))
+                    }
+                    _ => Type::Array(Box::new(self.fresh())),
+                };
+                if !self.expect(span, left, &collection, not_supported) {
+                    return self.fresh();
+                }
+                collection
+            }
+            collection => collection,
+        };
+
+        let integer_index = |_: &str, index: &str| format!("index must be an integer, got {index}");
+        match collection {
+            Type::Array(_) | Type::String if slice => collection,
+            Type::Array(element) => {
+                self.expect(span, &Type::Integer, index, integer_index);
+                *element
+            }
+            Type::String => {
+                self.expect(span, &Type::Integer, index, integer_index);
+                Type::String
+            }
+            Type::Hash(key, value) => {
+                self.expect(span, &key, index, |key, index| {
+                    format!("expected {key} key, got {index}")
+                });
+                *value
+            }
+            other => {
+                let shown = self.show(&[&other]);
+                self.error(span, not_supported(&shown[0], ""));
+                self.fresh()
+            }
+        }
+    }
+
+    fn infer_function(
+        &mut self,
+        span: Span,
+        parameters: &[Parameter],
+        rest: Option<IdentId>,
+        annotation: Option<&TypeAnnotation>,
+        body: BlockId,
+    ) -> Type {
+        let ast = self.ast;
+        let mark = self.env.len();
+
+        // Each default is checked with the parameters before it in scope.
+        let mut params = Vec::new();
+        for param in parameters {
+            let name = &ast.idents[param.name].name;
+            let ty = match (&param.annotation, param.default) {
+                (Some(annotation), Some(default)) => {
+                    let (ty, span) = (self.infer(default), self.expr_span(default));
+                    let expected = self.annotation_type(annotation);
+                    self.expect_annotated(span, &expected, &ty, |expected, ty| {
+                        format!("expected {expected} for parameter {name}, got {ty}")
+                    });
+                    expected
+                }
+                (Some(annotation), None) => self.annotation_type(annotation),
+                (None, Some(default)) => self.infer(default),
+                (None, None) => self.fresh(),
+            };
+            self.declare(param.name, ty.clone(), false);
+            params.push(ParamType {
+                name: Some(name.clone()),
+                ty,
+                optional: param.default.is_some(),
+            });
+        }
+        let rest = rest.map(|rest| {
+            let element = self.fresh();
+            self.declare(rest, Type::Array(Box::new(element.clone())), false);
+            element
+        });
+
+        let result = self.fresh();
+        self.results.push(result.clone());
+        let ty = self.infer_block(body);
+        let end = match ast.blocks[body].statements.last() {
+            Some(&last) => ast.spans.stmts.get(last),
+            None => ast.spans.blocks.get(body),
+        };
+        let end = end.copied().unwrap_or_default();
+        self.expect(end, &result, &ty, |result, ty| {
+            format!("expected to return {result}, got {ty}")
+        });
+        self.results.pop();
+        self.env.truncate(mark);
+        let result = match annotation {
+            Some(annotation) => self.annotate(span, annotation, &result, "the result"),
+            None => result,
+        };
+
+        Type::Function(Box::new(FunctionType {
+            params,
+            rest,
+            result,
+        }))
+    }
+
+    fn infer_call(
+        &mut self,
+        span: Span,
+        function: ExprId,
+        arguments: &[ExprId],
+        named_arguments: &[(String, ExprId)],
+    ) -> Type {
+        let callee = self.infer(function);
+        let args: Vec<(Span, Type)> = arguments
+            .iter()
+            .map(|&arg| (self.expr_span(arg), self.infer(arg)))
+            .collect();
+        let named: Vec<(&str, Span, Type)> = named_arguments
+            .iter()
+            .map(|(name, arg)| (name.as_str(), self.expr_span(*arg), self.infer(*arg)))
+            .collect();
+
+        let func = match self.shallow(&callee) {
+            Type::Function(func) => func,
+            // Named arguments say nothing about the order of the parameters,
+            // so only the positional ones shape a function of unknown type.
+            Type::Var(_) => {
+                let result = self.fresh();
+                let called =
+                    function_type(args.into_iter().map(|(_, ty)| ty).collect(), result.clone());
+                self.expect(span, &callee, &called, |callee, _| {
+                    format!("not a function: {callee}")
+                });
+                return result;
+            }
+            other => {
+                let shown = self.show(&[&other]);
+                self.error(span, format!("not a function: {}", shown[0]));
+                return self.fresh();
+            }
+        };
+
+        let mut given = vec![false; func.params.len()];
+        for (i, (arg_span, arg)) in args.iter().enumerate() {
+            let param = match (func.params.get(i), &func.rest) {
+                (Some(param), _) => {
+                    given[i] = true;
+                    &param.ty
+                }
+                (None, Some(rest)) => rest,
+                (None, None) => {
+                    self.error(
+                        span,
+                        format!(
+                            "too many arguments: expected at most {}, got {}",
+                            func.params.len(),
+                            args.len()
+                        ),
+                    );
+                    break;
+                }
+            };
+            self.expect(*arg_span, param, arg, |param, arg| {
+                format!("expected {param}, got {arg}")
+            });
+        }
+        for (name, arg_span, arg) in &named {
+            let Some(i) = func
+                .params
+                .iter()
+                .position(|p| p.name.as_deref() == Some(name))
+            else {
+                self.error(*arg_span, format!("unknown parameter {name}"));
+                continue;
+            };
+            if given[i] {
+                self.error(*arg_span, format!("argument {name} given twice"));
+                continue;
+            }
+            given[i] = true;
+            self.expect(*arg_span, &func.params[i].ty, arg, |param, arg| {
+                format!("expected {param}, got {arg}")
+            });
+        }
+        for (i, param) in func.params.iter().enumerate() {
+            if !given[i] && !param.optional {
+                let name = match &param.name {
+                    Some(name) => name.clone(),
+                    None => (i + 1).to_string(),
+                };
+                self.error(span, format!("missing argument for parameter {name}"));
+            }
+        }
+        func.result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::parser::tests::parse;
+    use crate::resolver;
+
+    fn typing(input: &str) -> Typing {
+        let ast = Ast::from(&parse(input));
+        let resolution = resolver::resolve(&ast);
+        assert!(
+            resolution.errors.is_empty(),
+            "errors: {:?}",
+            resolution.errors
+        );
+        check(&ast, &resolution)
+    }
+
+    // The type of each top-level binding, as `name: type`.
+    fn lets(input: &str) -> Vec<String> {
+        let typing = typing(input);
+        assert!(typing.errors.is_empty(), "type errors: {:?}", typing.errors);
+        typing
+            .lets
+            .iter()
+            .map(|(name, scheme)| format!("{name}: {scheme}"))
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_lets() {
+        let tests = vec![
+            (
+                r#"let a = 1; let b = true; let c = "s"; let d = 1..3; let e = if (b) { 1 };"#,
+                vec!["a: int", "b: bool", "c: string", "d: range", "e: null"],
+            ),
+            (
+                r#"let xs = [1, 2]; let h = {"a": [true]}; let x = xs[0]; let y = h["a"][1];"#,
+                vec!["xs: [int]", "h: {string: [bool]}", "x: int", "y: bool"],
+            ),
+            (
+                "let add = fn(a, b) { a - b }; let three = add(1, 2);",
+                vec!["add: fn(int, int) -> int", "three: int"],
+            ),
+            (
+                "let compose = fn(f, g) { fn(x) { g(f(x)) } };",
+                vec!["compose: fn(fn(a) -> b, fn(b) -> c) -> fn(a) -> c"],
+            ),
+            (
+                "let map = fn(xs, f) { let out = []; for (x in xs) { push(out, f(x)) } out };",
+                vec!["map: fn([a], fn(a) -> b) -> [b]"],
+            ),
+            (
+                "let plus = fn(a, b) { a + b }; let s = plus(\"a\", \"b\");",
+                vec!["plus: fn(a, a) -> a where a: int | string", "s: string"],
+            ),
+            (
+                "let f = fn(a, b = 1, ...rest) { a + b + len(rest) }; let r = f(1, b: 2);",
+                vec!["f: fn(int, int?, ...a) -> int", "r: int"],
+            ),
+            (
+                "let fact = fn(n) { if (n < 2) { return 1; } n * fact(n - 1) };",
+                vec!["fact: fn(int) -> int"],
+            ),
+            (
+                r#"let [first, ...others] = [1, 2]; let {name} = {"name": "x"};"#,
+                vec!["first: int", "others: [int]", "name: string"],
+            ),
+            (
+                r#"let describe = fn(x) { match (x) { 0 => "zero", n if n < 0 => "negative", _ => "positive" } };"#,
+                vec!["describe: fn(int) -> string"],
+            ),
+            (
+                "let twice = fn(x) { [x, x] } |> doc;",
+                vec!["twice: string"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(lets(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_let_polymorphism() {
+        let tests = vec![
+            (
+                r#"let id = fn(x) { x }; let a = id(1); let b = id("s"); let c = id(id);"#,
+                vec!["id: fn(a) -> a", "a: int", "b: string", "c: fn(a) -> a"],
+            ),
+            // Polymorphic inside a function body too.
+            (
+                "let f = fn() { let pair = fn(x) { [x, x] }; pair(1)[0] + len(pair(true)) };",
+                vec!["f: fn() -> int"],
+            ),
+            // Parameters are not polymorphic.
+            (
+                "let apply = fn(f) { [f(1), f(2)] };",
+                vec!["apply: fn(fn(int) -> a) -> [a]"],
+            ),
+            // Neither are collections, nor bindings that are assigned to.
+            ("let xs = []; push(xs, 1);", vec!["xs: [int]"]),
+            (
+                "let f = fn(x) { x }; f = fn(x) { x + 1 };",
+                vec!["f: fn(int) -> int"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let typing = typing(input);
+            assert_eq!(typing.errors, Vec::<String>::new(), "input: {input}");
+            let lets: Vec<String> = typing
+                .lets
+                .iter()
+                .map(|(name, scheme)| format!("{name}: {scheme}"))
+                .collect();
+            assert_eq!(lets, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let input = "
+            let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+            let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+            let call = fn() { later(1) };
+            let later = fn(x) { x };";
+        assert_eq!(
+            lets(input),
+            [
+                "isEven: fn(int) -> bool",
+                "isOdd: fn(int) -> bool",
+                "call: fn() -> int",
+                "later: fn(int) -> int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let tests = vec![
+            ("5 + true", "type mismatch: int + bool at 1:3"),
+            ("true + false", "unknown operator: bool + bool at 1:6"),
+            ("[1] - [2]", "unknown operator: [int] - [int] at 1:5"),
+            ("-true", "unknown operator: -bool at 1:1"),
+            (
+                r#"if (true) { 1 } else { "a" }"#,
+                "if branches differ: int and string at 1:1",
+            ),
+            ("[1, true]", "array elements differ: int and bool at 1:5"),
+            ("{[1]: 2}", "unusable as hash key: [int] at 1:1"),
+            ("5(1)", "not a function: int at 1:2"),
+            (
+                "let f = fn(x) { x }; f(1, 2)",
+                "too many arguments: expected at most 1, got 2 at 1:23",
+            ),
+            (
+                "let f = fn(x) { x }; f()",
+                "missing argument for parameter x at 1:23",
+            ),
+            (
+                "let f = fn(x) { x }; f(1, y: 2)",
+                "unknown parameter y at 1:30",
+            ),
+            (
+                "let f = fn(x) { x }; f(1, x: 2)",
+                "argument x given twice at 1:30",
+            ),
+            (
+                "let f = fn(x) { x + 1 }; f(\"a\")",
+                "expected int, got string at 1:28",
+            ),
+            (
+                "let f = fn(x) { x(x) };",
+                "infinite type a = fn(a) -> b at 1:18",
+            ),
+            ("1[0]", "index operator not supported: int at 1:2"),
+            (r#"[1]["a"]"#, "index must be an integer, got string at 1:4"),
+            (r#"{1: 2}["a"]"#, "expected int key, got string at 1:7"),
+            ("for (x in 5) {}", "cannot iterate over int at 1:1"),
+            ("1..true", "range bounds must be integers, got bool at 1:4"),
+            (
+                "let x = 1; x = \"a\"",
+                "cannot assign string to int at 1:14",
+            ),
+            (
+                r#"match (1) { 0 => 1, _ => "a" }"#,
+                "match arms differ: int and string at 1:26",
+            ),
+            (
+                r#"match (1) { "a" => 1 }"#,
+                "a string pattern cannot match int at 1:13",
+            ),
+            ("let [a] = 1;", "an array pattern cannot match int at 1:5"),
+            (
+                r#"let f = fn(x) { if (x) { return 1; } "a" };"#,
+                "expected to return int, got string at 1:38",
+            ),
+            (
+                "let f = fn() { g(1) }; let g = fn() { 1 };",
+                "g is used as fn(int) -> a but is fn() -> int at 1:28",
+            ),
+            // Let-bound functions are instantiated afresh, parameters are not.
+            (
+                "let f = fn(g) { [g(1), g(true)] };",
+                "expected int, got bool at 1:26",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(typing(input).errors, vec![expected], "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_annotations() {
+        let tests = vec![
+            (
+                "let x: int = 5; let f = fn(a: int, b: string) -> bool { a > len(b) };",
+                vec!["x: int", "f: fn(int, string) -> bool"],
+            ),
+            (
+                "let id = fn(x) { x }; let inc: fn(int) -> int = id;",
+                vec!["id: fn(a) -> a", "inc: fn(int) -> int"],
+            ),
+            (
+                "let xs: [string] = []; let h = fn(h: {string: [int]}) { h };",
+                vec!["xs: [string]", "h: fn({string: [int]}) -> {string: [int]}"],
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(lets(input), expected, "input: {input}");
+        }
+
+        // Whether each error is about an annotation, rather than about the
+        // code that uses an annotated value.
+        let errors = vec![
+            (
+                r#"let x: int = "a";"#,
+                "expected int for x, got string at 1:1",
+                true,
+            ),
+            (
+                "let [a]: [bool] = [1];",
+                "expected [bool] for the value, got [int] at 1:1",
+                true,
+            ),
+            (
+                r#"let f = fn(n: int) { n }; f("a")"#,
+                "expected int, got string at 1:29",
+                false,
+            ),
+            (
+                r#"let f = fn(n: int = "a") { n };"#,
+                "expected int for parameter n, got string at 1:21",
+                true,
+            ),
+            (
+                "let f = fn(n) -> bool { n + 1 };",
+                "expected bool for the result, got int at 1:9",
+                true,
+            ),
+            (
+                "let f = fn(g: fn(int) -> int) { g(true) };",
+                "expected int, got bool at 1:35",
+                false,
+            ),
+        ];
+        for (input, expected, annotation) in errors {
+            let typing = typing(input);
+            assert_eq!(typing.errors, vec![expected], "input: {input}");
+            let annotation_errors = match annotation {
+                true => vec![expected],
+                false => vec![],
+            };
+            assert_eq!(
+                typing.annotation_errors, annotation_errors,
+                "input: {input}"
+            );
+        }
+
+        // The parser only accepts known types, so one is renamed after
+        // parsing.
+        let mut program = parse("let x: int = 1;");
+        let Statement::Let(s) = &mut program.statements[0] else {
+            panic!("not a let statement: {}", program.statements[0]);
+        };
+        let Some(TypeAnnotation::Named(name)) = &mut s.annotation else {
+            panic!("not a named annotation: {s}");
+        };
+        name.value = "float".to_string();
+        let ast = Ast::from(&program);
+        let typing = check(&ast, &resolver::resolve(&ast));
+        assert_eq!(typing.annotation_errors, ["unknown type float at 1:8"]);
+    }
+
+    #[test]
+    fn test_certain_annotation_errors() {
+        let tests = vec![
+            (
+                r#"let x: int = "a";"#,
+                vec!["expected int for x, got string at 1:1"],
+            ),
+            (
+                "let f = fn(n: int = true) { n };",
+                vec!["expected int for parameter n, got bool at 1:21"],
+            ),
+            // A one-armed `if` may give the value of its block.
+            ("let x: int = if (true) { 1 }; x", vec![]),
+            (
+                "let f = fn(n) { if (n > 0) { n } }; let y: int = f(1); y",
+                vec![],
+            ),
+            // Other errors make the types unreliable.
+            (r#"let a = [1, "a"]; let s: string = a[1];"#, vec![]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(typing(input).annotation_errors, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_proven_annotations() {
+        let input = "let a: int = 1;
+let f = fn(x) { let b: int = x; b };
+let g = fn(n: int) -> int { n * 2 };
+let h = fn(k) -> int { h(k) };
+let xs: [int] = [];
+let c: fn(int) -> int = fn(y) { y };";
+        let typing = typing(input);
+        assert!(typing.errors.is_empty(), "type errors: {:?}", typing.errors);
+        let mut proven: Vec<String> = typing.proven.iter().map(|s| s.to_string()).collect();
+        proven.sort();
+        assert_eq!(proven, vec!["1:1", "3:9", "6:1"]);
+    }
+}
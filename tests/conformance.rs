@@ -0,0 +1,70 @@
+// Golden-file tests for the grammar. Every `tests/conformance/NAME.monkey`
+// is parsed and the result compared with `NAME.expected`: the S-expression
+// dump followed by any warnings, or only the errors when parsing fails.
+//
+// Run with UPDATE_GOLDEN=1 to write the current output to the expected
+// files instead, then review the diff.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use rusty_monkey::{dump, lexer, parser};
+
+fn render(input: &str) -> String {
+    let lex = lexer::new(input);
+    let mut parser = parser::new(Rc::new(RefCell::new(lex)));
+    let program = parser.parse_program();
+
+    let mut out = String::new();
+    if parser.errors().is_empty() {
+        out.push_str(&dump::to_sexpr(&program));
+        out.push('\n');
+        for warning in parser.warnings() {
+            out.push_str(&format!("warning: {warning}\n"));
+        }
+    } else {
+        for err in parser.errors() {
+            out.push_str(&format!("error: {err}\n"));
+        }
+    }
+    out
+}
+
+#[test]
+fn conformance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("could not read the conformance directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "monkey"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no .monkey files in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let input = fs::read_to_string(path).unwrap();
+        let actual = render(&input);
+        let golden = path.with_extension("expected");
+
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}:\n--- expected\n{expected}--- actual\n{actual}",
+                path.display()
+            )),
+            Err(e) => failures.push(format!("{}: {e}", golden.display())),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}